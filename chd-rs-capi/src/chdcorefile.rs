@@ -1,11 +1,68 @@
 use crate::chdcorefile_sys::*;
 use crate::SeekRead;
 use std::any::Any;
-use std::io::{Read, Seek, SeekFrom};
-use std::os::raw::c_void;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::os::raw::{c_int, c_void};
+
+const SEEK_SET: c_int = 0;
+const SEEK_END: c_int = 2;
 
 pub struct CoreFile {
     pub(crate) file: *mut core_file,
+    // core_file can not report its position, so it is tracked from the reads and seeks made.
+    pos: u64,
+    len: Option<u64>,
+}
+
+impl CoreFile {
+    /// Wraps a `core_file` that is positioned at the start of the file.
+    pub(crate) fn new(file: *mut core_file) -> CoreFile {
+        CoreFile {
+            file,
+            pos: 0,
+            len: None,
+        }
+    }
+
+    /// Seeks with `core_fseek`, returning whether the seek succeeded.
+    fn fseek(&mut self, off: i64, origin: c_int) -> bool {
+        unsafe { core_fseek(self.file, off as size_t, origin) == 0 }
+    }
+
+    /// Returns the length of the file.
+    ///
+    /// Seeking before the start of the file fails, so the length is the furthest offset
+    /// before the end of the file that can be seeked to. The position of the file is
+    /// changed, and must be restored by the caller.
+    fn file_len(&mut self) -> std::io::Result<u64> {
+        if let Some(len) = self.len {
+            return Ok(len);
+        }
+        if !self.fseek(0, SEEK_END) {
+            return Err(std::io::Error::new(
+                ErrorKind::Other,
+                "core_fseek returned an error",
+            ));
+        }
+        // The length is at least `min` and less than `max`.
+        let (mut min, mut max) = (0i64, 1i64);
+        while self.fseek(-max, SEEK_END) {
+            min = max;
+            max = max
+                .checked_mul(2)
+                .ok_or_else(|| std::io::Error::new(ErrorKind::Other, "file length out of range"))?;
+        }
+        while max - min > 1 {
+            let mid = min + (max - min) / 2;
+            if self.fseek(-mid, SEEK_END) {
+                min = mid;
+            } else {
+                max = mid;
+            }
+        }
+        self.len = Some(min as u64);
+        Ok(min as u64)
+    }
 }
 
 impl SeekRead for CoreFile {
@@ -14,6 +71,21 @@ impl SeekRead for CoreFile {
     }
 }
 
+/// Checks the result of `core_fread` for a buffer of `len` bytes.
+///
+/// A count shorter than the buffer is a short read, such as at the end of the file.
+/// `core_fread` can not return a negative count, so an implementation signalling an error
+/// with `-1` returns a count larger than the buffer instead.
+fn read_result(res: size_t, len: usize) -> std::io::Result<usize> {
+    if res > len as size_t {
+        return Err(std::io::Error::new(
+            ErrorKind::Other,
+            "core_fread returned an error",
+        ));
+    }
+    Ok(res as usize)
+}
+
 impl Read for CoreFile {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let res = unsafe {
@@ -23,28 +95,59 @@ impl Read for CoreFile {
                 buf.len() as size_t,
             )
         };
-        Ok(res as usize)
+        let read = read_result(res, buf.len())?;
+        self.pos += read as u64;
+        Ok(read)
     }
 }
 
 impl Seek for CoreFile {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        let (off, set) = match pos {
-            SeekFrom::Start(off) => (off as i64, 0), // SEEK_SET
-            SeekFrom::End(off) => (off, 2),          // SEEK_END
-            SeekFrom::Current(off) => (off, 1),      // SEEK_CUR
+        // Every seek is made from the start of the file, to the position computed from the
+        // tracked position or the length of the file.
+        let target = match pos {
+            SeekFrom::Start(off) => i64::try_from(off).ok(),
+            SeekFrom::End(off) => {
+                let len = self.file_len()?;
+                i64::try_from(len).ok().and_then(|len| len.checked_add(off))
+            }
+            SeekFrom::Current(off) => i64::try_from(self.pos)
+                .ok()
+                .and_then(|pos| pos.checked_add(off)),
         };
-        let res = unsafe { core_fseek(self.file, off as size_t, set) };
-        Ok(res as u64)
+        let target = match target {
+            Some(target) if target >= 0 => target,
+            _ => {
+                // Finding the length of the file moves the position, so it is restored.
+                if !self.fseek(self.pos as i64, SEEK_SET) {
+                    return Err(std::io::Error::new(
+                        ErrorKind::Other,
+                        "core_fseek returned an error",
+                    ));
+                }
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "seek offset out of range",
+                ));
+            }
+        };
+        if !self.fseek(target, SEEK_SET) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "core_fseek returned an error",
+            ));
+        }
+        self.pos = target as u64;
+        Ok(self.pos)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::chdcorefile::CoreFile;
-    use crate::chdcorefile_sys::core_fopen;
+    use crate::chdcorefile::{read_result, CoreFile};
+    use crate::chdcorefile_sys::{core_fopen, size_t};
     use std::fs::File;
-    use std::io::{Read, Write};
+    use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 
     #[test]
     fn chdcorefile_read() {
@@ -54,15 +157,76 @@ mod tests {
         drop(f);
 
         let file = unsafe { core_fopen(b"test.txt\0".as_ptr() as *const std::os::raw::c_char) };
-        let mut file = CoreFile(file);
+        let mut file = CoreFile::new(file);
         let mut buf = [0u8; 10];
         file.read_exact(&mut buf).unwrap();
         assert_eq!(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9], &buf);
     }
+
+    #[test]
+    fn chdcorefile_short_read() {
+        let mut f = File::create("test_short.txt").unwrap();
+        f.write_all(&[0, 1, 2, 3]).unwrap();
+        f.flush().unwrap();
+        drop(f);
+
+        let file =
+            unsafe { core_fopen(b"test_short.txt\0".as_ptr() as *const std::os::raw::c_char) };
+        let mut file = CoreFile::new(file);
+        let mut buf = [0u8; 10];
+        assert_eq!(file.read(&mut buf).unwrap(), 4);
+        assert_eq!(&[0, 1, 2, 3], &buf[..4]);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+
+        let mut file = CoreFile::new(unsafe {
+            core_fopen(b"test_short.txt\0".as_ptr() as *const std::os::raw::c_char)
+        });
+        assert_eq!(
+            file.read_exact(&mut buf).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn chdcorefile_seek() {
+        let mut f = File::create("test_seek.txt").unwrap();
+        f.write_all(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        f.flush().unwrap();
+        drop(f);
+
+        let file =
+            unsafe { core_fopen(b"test_seek.txt\0".as_ptr() as *const std::os::raw::c_char) };
+        let mut file = CoreFile::new(file);
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 10);
+        assert_eq!(file.seek(SeekFrom::End(-3)).unwrap(), 7);
+        let mut buf = [0u8; 2];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&[7, 8], &buf);
+        assert_eq!(file.stream_position().unwrap(), 9);
+        assert_eq!(file.seek(SeekFrom::Start(4)).unwrap(), 4);
+        assert_eq!(file.seek(SeekFrom::Current(2)).unwrap(), 6);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&[6, 7], &buf);
+
+        // Seeking before the start of the file fails without moving the position.
+        assert!(file.seek(SeekFrom::Current(-100)).is_err());
+        assert!(file.seek(SeekFrom::End(-11)).is_err());
+        assert!(file.seek(SeekFrom::Start(u64::MAX)).is_err());
+        assert_eq!(file.stream_position().unwrap(), 8);
+    }
+
+    #[test]
+    fn chdcorefile_read_error() {
+        // An error sentinel of -1 wraps to the largest count.
+        assert!(read_result(size_t::MAX, 10).is_err());
+        assert!(read_result(11, 10).is_err());
+        assert_eq!(read_result(10, 10).unwrap(), 10);
+        assert_eq!(read_result(3, 10).unwrap(), 3);
+    }
 }
 
 impl Drop for CoreFile {
     fn drop(&mut self) {
-        unsafe { core_fclose(self.0) }
+        unsafe { core_fclose(self.file) }
     }
 }
@@ -0,0 +1,170 @@
+use chd::ChdError;
+use std::error::Error;
+use std::fmt::Display;
+
+/// Error types that may occur when reading a CHD file or hunk.
+///
+/// This type tries to be ABI-compatible with [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258),
+/// given sane defaults in the C compiler. See [repr(C) in the Rustonomicon](https://doc.rust-lang.org/nomicon/other-reprs.html#reprc) for more details.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum chd_error {
+    /// No error.
+    /// This is only used by the C API bindings.
+    None,
+    /// No drive interface.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    NoInterface,
+    /// Unable to allocate the required size of buffer.
+    OutOfMemory,
+    /// The file is not a valid CHD file.
+    InvalidFile,
+    /// An invalid parameter was provided.
+    InvalidParameter,
+    /// The data is invalid.
+    InvalidData,
+    /// The file was not found.
+    FileNotFound,
+    /// This CHD requires a parent CHD that was not provided.
+    RequiresParent,
+    /// The provided file is not writable.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    FileNotWriteable,
+    /// An error occurred when reading this CHD file.
+    ReadError,
+    /// An error occurred when writing this CHD file.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    WriteError,
+    /// An error occurred when initializing a codec.
+    CodecError,
+    /// The provided parent CHD is invalid.
+    InvalidParent,
+    /// The request hunk is out of range for this CHD file.
+    HunkOutOfRange,
+    /// An error occurred when decompressing a hunk.
+    DecompressionError,
+    /// An error occurred when compressing a hunk.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    CompressionError,
+    /// Could not create the file.
+    /// Since chd-rs does not implement CHD creation, this is unused.
+    CantCreateFile,
+    /// Could not verify the CHD.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    CantVerify,
+    /// The requested operation is not supported.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    NotSupported,
+    /// The requested metadata was not found.
+    /// This is only used by the C API bindings.
+    MetadataNotFound,
+    /// The metadata has an invalid size.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    InvalidMetadataSize,
+    /// The CHD version of the provided file is not supported by this library.
+    UnsupportedVersion,
+    /// Unable to verify the CHD completely.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    VerifyIncomplete,
+    /// The requested metadata is invalid.
+    InvalidMetadata,
+    /// The internal state of the decoder/encoder is invalid.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    InvalidState,
+    /// An operation is already pending.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    OperationPending,
+    /// No async operations are allowed.
+    /// This is only for C-compatibility purposes and is otherwise unused.
+    NoAsyncOperation,
+    /// Decompressing the CHD requires a codec that is not supported.
+    UnsupportedFormat,
+    /// Unknown error.
+    Unknown,
+}
+
+impl Error for chd_error {}
+
+impl Display for chd_error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            chd_error::None => f.write_str("no error"),
+            chd_error::NoInterface => f.write_str("no drive interface"),
+            chd_error::OutOfMemory => f.write_str("out of memory"),
+            chd_error::InvalidFile => f.write_str("invalid file"),
+            chd_error::InvalidParameter => f.write_str("invalid parameter"),
+            chd_error::InvalidData => f.write_str("invalid data"),
+            chd_error::FileNotFound => f.write_str("file not found"),
+            chd_error::RequiresParent => f.write_str("requires parent"),
+            chd_error::FileNotWriteable => f.write_str("file not writeable"),
+            chd_error::ReadError => f.write_str("read error"),
+            chd_error::WriteError => f.write_str("write error"),
+            chd_error::CodecError => f.write_str("codec error"),
+            chd_error::InvalidParent => f.write_str("invalid parent"),
+            chd_error::HunkOutOfRange => f.write_str("hunk out of range"),
+            chd_error::DecompressionError => f.write_str("decompression error"),
+            chd_error::CompressionError => f.write_str("compression error"),
+            chd_error::CantCreateFile => f.write_str("can't create file"),
+            chd_error::CantVerify => f.write_str("can't verify file"),
+            chd_error::NotSupported => f.write_str("operation not supported"),
+            chd_error::MetadataNotFound => f.write_str("can't find metadata"),
+            chd_error::InvalidMetadataSize => f.write_str("invalid metadata size"),
+            chd_error::UnsupportedVersion => f.write_str("unsupported CHD version"),
+            chd_error::VerifyIncomplete => f.write_str("incomplete verify"),
+            chd_error::InvalidMetadata => f.write_str("invalid metadata"),
+            chd_error::InvalidState => f.write_str("invalid state"),
+            chd_error::OperationPending => f.write_str("operation pending"),
+            chd_error::NoAsyncOperation => f.write_str("no async operation in progress"),
+            chd_error::UnsupportedFormat => f.write_str("unsupported format"),
+            chd_error::Unknown => f.write_str("undocumented error"),
+        }
+    }
+}
+
+impl From<ChdError> for chd_error {
+    fn from(err: ChdError) -> Self {
+        match err {
+            ChdError::None => chd_error::None,
+            ChdError::NoInterface => chd_error::NoInterface,
+            ChdError::OutOfMemory => chd_error::OutOfMemory,
+            ChdError::InvalidFile => chd_error::InvalidFile,
+            ChdError::InvalidParameter => chd_error::InvalidParameter,
+            ChdError::InvalidData => chd_error::InvalidData,
+            ChdError::FileNotFound => chd_error::FileNotFound,
+            ChdError::RequiresParent => chd_error::RequiresParent,
+            ChdError::FileNotWriteable => chd_error::FileNotWriteable,
+            ChdError::ReadError => chd_error::ReadError,
+            ChdError::WriteError => chd_error::WriteError,
+            ChdError::CodecError => chd_error::CodecError,
+            ChdError::InvalidParent => chd_error::InvalidParent,
+            ChdError::HunkOutOfRange => chd_error::HunkOutOfRange,
+            ChdError::DecompressionError => chd_error::DecompressionError,
+            ChdError::CompressionError => chd_error::CompressionError,
+            ChdError::CantCreateFile => chd_error::CantCreateFile,
+            ChdError::CantVerify => chd_error::CantVerify,
+            ChdError::NotSupported => chd_error::NotSupported,
+            ChdError::MetadataNotFound(_) => chd_error::MetadataNotFound,
+            ChdError::InvalidMetadataSize => chd_error::InvalidMetadataSize,
+            ChdError::UnsupportedVersion(_) => chd_error::UnsupportedVersion,
+            ChdError::VerifyIncomplete => chd_error::VerifyIncomplete,
+            ChdError::InvalidMetadata => chd_error::InvalidMetadata,
+            ChdError::InvalidState => chd_error::InvalidState,
+            ChdError::OperationPending => chd_error::OperationPending,
+            ChdError::NoAsyncOperation => chd_error::NoAsyncOperation,
+            ChdError::UnsupportedFormat => chd_error::UnsupportedFormat,
+            ChdError::Unknown => chd_error::Unknown,
+            // Errors with details are reported with the closest libchdr error code.
+            ChdError::Flac(_) => chd_error::DecompressionError,
+            ChdError::TruncatedInput => chd_error::DecompressionError,
+            ChdError::UnsupportedCodec(_) => chd_error::UnsupportedFormat,
+            ChdError::TruncatedFile { .. } => chd_error::InvalidFile,
+            ChdError::TrailingData => chd_error::DecompressionError,
+            ChdError::MetadataParse { .. } => chd_error::InvalidMetadata,
+            ChdError::UnsupportedFeature(_) => chd_error::NotSupported,
+            ChdError::UnsupportedForVersion { .. } => chd_error::NotSupported,
+            ChdError::Lzma(_) => chd_error::DecompressionError,
+            ChdError::ReadOnly => chd_error::FileNotWriteable,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 extern crate core;
 
+mod error;
 mod header;
 
 #[cfg(feature = "chd_core_file")]
@@ -10,6 +11,7 @@ mod chdcorefile;
 #[allow(unused)]
 mod chdcorefile_sys;
 
+pub use crate::error::chd_error;
 use crate::header::chd_header;
 use chd::header::ChdHeader;
 use chd::{ChdError, ChdFile};
@@ -45,7 +47,6 @@ impl SeekRead for Cursor<Vec<u8>> {
 pub type chd_file = ChdFile<Box<dyn SeekRead>>;
 
 use chd::metadata::{ChdMetadata, ChdMetadataTag, KnownMetadata};
-pub use chd::ChdError as chd_error;
 
 fn ffi_takeown_chd(chd: *mut chd_file) -> Box<ChdFile<Box<dyn SeekRead>>> {
     unsafe { Box::from_raw(chd) }
@@ -67,7 +68,7 @@ fn ffi_open_chd(
     let file = File::open(filename).map_err(|_| chd_error::FileNotFound)?;
 
     let bufread = Box::new(BufReader::new(file)) as Box<dyn SeekRead>;
-    ChdFile::open(bufread, parent)
+    ChdFile::open(bufread, parent).map_err(chd_error::from)
 }
 
 #[no_mangle]
@@ -152,7 +153,7 @@ pub extern "C" fn chd_read(chd: *mut chd_file, hunknum: u32, buffer: *mut c_void
                 let result = hunk.read_hunk_in(&mut comp_buf, output);
                 match result {
                     Ok(_) => chd_error::None,
-                    Err(e) => e,
+                    Err(e) => e.into(),
                 }
             } else {
                 chd_error::HunkOutOfRange
@@ -174,7 +175,7 @@ fn find_metadata(
             search_index -= 1;
         }
     }
-    Err(ChdError::MetadataNotFound)
+    Err(ChdError::MetadataNotFound(search_tag.to_be_bytes()))
 }
 #[no_mangle]
 /// Get indexed metadata of the given search tag and index.
@@ -277,10 +278,10 @@ pub extern "C" fn chd_read_header(
         Ok(chd) => {
             let chd_header = ffi_chd_get_header(&chd);
             match unsafe { header.as_mut() } {
-                None => ChdError::InvalidParameter,
+                None => chd_error::InvalidParameter,
                 Some(header) => {
                     header.write(chd_header);
-                    ChdError::None
+                    chd_error::None
                 }
             }
         }
@@ -316,7 +317,7 @@ pub unsafe extern "C" fn chd_core_file(chd: *mut chd_file) -> *mut chdcorefile_s
 
     let pointer = match file_ref.downcast_ref::<crate::chdcorefile::CoreFile>() {
         None => std::ptr::null_mut(),
-        Some(file) => file.0,
+        Some(file) => file.file,
     };
     std::mem::forget(file);
     pointer
@@ -345,10 +346,10 @@ pub extern "C" fn chd_open_file(
         Some(ffi_takeown_chd(parent))
     };
 
-    let core_file = Box::new(crate::chdcorefile::CoreFile(file)) as Box<dyn SeekRead>;
+    let core_file = Box::new(crate::chdcorefile::CoreFile::new(file)) as Box<dyn SeekRead>;
     let chd = match ChdFile::open(core_file, parent) {
         Ok(chd) => chd,
-        Err(e) => return e,
+        Err(e) => return e.into(),
     };
 
     unsafe { *out = ffi_expose_chd(Box::new(chd)) }
@@ -448,7 +449,7 @@ pub extern "C" fn chd_precache_progress(
     let (_file, parent) = chd_file.into_inner();
 
     let buffered_chd = match ChdFile::open(stream, parent) {
-        Err(e) => return e,
+        Err(e) => return e.into(),
         Ok(chd) => Box::new(chd),
     };
 
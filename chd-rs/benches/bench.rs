@@ -1,6 +1,8 @@
 use bencher::{benchmark_group, benchmark_main, Bencher};
+use chd::codecs::{CodecImplementation, RawFlacCodec};
 use chd::read::ChdHunkBufReader;
 use chd::ChdFile;
+use crc::{Crc, CRC_16_UMTS, CRC_8_SMBUS};
 use std::env::args;
 use std::fs::File;
 use std::io::BufReader;
@@ -28,5 +30,107 @@ fn read_hunks_unbuf_bench(bench: &mut Bencher) {
     });
 }
 
-benchmark_group!(benches, read_hunks_unbuf_bench);
+/// Reads every hunk of the test image with or without subcode data, so that the throughput
+/// of both can be compared.
+fn read_hunks(bench: &mut Bencher, want_subcode: bool) {
+    let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+    let mut bytes = 0;
+
+    bench.iter(|| {
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+        let hunk_count = chd.header().hunk_count();
+        let hunk_size = chd.header().hunk_size() as usize;
+        let mut hunk_buf = vec![0u8; hunk_size];
+        let mut cmp_buf = Vec::new();
+        bytes = 0;
+        for hunk_num in 0..hunk_count {
+            let mut hunk = chd.hunk(hunk_num).expect("could not acquire hunk");
+            let res = if want_subcode {
+                hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+            } else {
+                hunk.read_hunk_without_subcode_in(&mut cmp_buf, &mut hunk_buf)
+            };
+            bytes += res.expect(format!("could not read_hunk {}", hunk_num).as_str());
+        }
+    });
+    bench.bytes = bytes as u64;
+}
+
+fn read_hunks_with_subcode_bench(bench: &mut Bencher) {
+    read_hunks(bench, true);
+}
+
+fn read_hunks_without_subcode_bench(bench: &mut Bencher) {
+    read_hunks(bench, false);
+}
+
+/// Builds a raw FLAC stream of 4096 sample frames with verbatim subframes, which holds
+/// `frames * 4096` stereo 16-bit samples.
+fn flac_verbatim_stream(frames: u32) -> Vec<u8> {
+    const FLAC_CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+    const FLAC_CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_UMTS);
+    const BLOCK_SIZE: u16 = 4096;
+
+    let mut stream = Vec::new();
+    for frame_num in 0..frames {
+        // sync code, 16 bit block size at end of header, 44.1kHz,
+        // 2 independent channels, 16 bits per sample.
+        let mut frame = vec![0xff, 0xf8, 0x79, 0x18];
+        // The frame number is UTF-8 coded.
+        if frame_num < 0x80 {
+            frame.push(frame_num as u8);
+        } else {
+            frame.push(0xc0 | (frame_num >> 6) as u8);
+            frame.push(0x80 | (frame_num & 0x3f) as u8);
+        }
+        frame.extend_from_slice(&(BLOCK_SIZE - 1).to_be_bytes());
+        frame.push(FLAC_CRC8.checksum(&frame));
+        for channel in 0..2 {
+            // verbatim subframe, no wasted bits.
+            frame.push(0x02);
+            for sample in 0..BLOCK_SIZE as u32 {
+                let value = (sample * 31 + frame_num * 7 + channel) as i16;
+                frame.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        let crc = FLAC_CRC16.checksum(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        stream.extend(frame);
+    }
+    stream
+}
+
+fn decompress_flac(bench: &mut Bencher, order: u8) {
+    // A 4 MiB hunk of 256 frames.
+    const FRAMES: u32 = 256;
+    let hunk_bytes = FRAMES * 4096 * 4;
+    let mut input = vec![order];
+    input.extend(flac_verbatim_stream(FRAMES));
+    let mut codec = RawFlacCodec::new(hunk_bytes).expect("codec");
+    let mut output = vec![0u8; hunk_bytes as usize];
+
+    bench.iter(|| {
+        codec
+            .decompress(&input, &mut output)
+            .expect("could not decompress hunk")
+    });
+    bench.bytes = hunk_bytes as u64;
+}
+
+fn decompress_flac_be_bench(bench: &mut Bencher) {
+    decompress_flac(bench, b'B');
+}
+
+fn decompress_flac_le_bench(bench: &mut Bencher) {
+    decompress_flac(bench, b'L');
+}
+
+benchmark_group!(
+    benches,
+    read_hunks_unbuf_bench,
+    read_hunks_with_subcode_bench,
+    read_hunks_without_subcode_bench,
+    decompress_flac_be_bench,
+    decompress_flac_le_bench
+);
 benchmark_main!(benches);
@@ -0,0 +1,43 @@
+/// A most-significant-bit-first bit writer, the counterpart of
+/// [`BitReader`](bitreader::BitReader).
+///
+/// Any remaining bits in the last byte are padded with zeroes.
+pub(crate) struct BitWriter {
+    buffer: Vec<u8>,
+    accum: u64,
+    bits: u8,
+}
+
+impl BitWriter {
+    pub(crate) fn new() -> Self {
+        BitWriter {
+            buffer: Vec::new(),
+            accum: 0,
+            bits: 0,
+        }
+    }
+
+    /// Writes the low `num_bits` bits of `value`. `num_bits` must be at most 56.
+    pub(crate) fn write(&mut self, value: u64, num_bits: u8) {
+        debug_assert!(num_bits <= 56);
+        if num_bits == 0 {
+            return;
+        }
+        let mask = (1u64 << num_bits) - 1;
+        self.accum = (self.accum << num_bits) | (value & mask);
+        self.bits += num_bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.buffer.push((self.accum >> self.bits) as u8);
+        }
+        self.accum &= (1u64 << self.bits) - 1;
+    }
+
+    /// Flushes any partially written byte and returns the written bytes.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.buffer.push((self.accum << (8 - self.bits)) as u8);
+        }
+        self.buffer
+    }
+}
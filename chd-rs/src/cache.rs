@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+
+/// A least-recently-used cache of decompressed hunks, keyed by hunk number.
+///
+/// The cache is bounded either by a number of hunks or by the total length of the hunks it
+/// holds. Buffers of evicted hunks are kept to be reused for the next decompression,
+/// so that repeated cache misses do not allocate.
+pub(crate) struct HunkCache {
+    capacity: usize,
+    max_bytes: usize,
+    bytes: usize,
+    // The most recently used entry is at the back.
+    entries: VecDeque<(u32, Vec<u8>)>,
+    spare: Option<Vec<u8>>,
+}
+
+impl HunkCache {
+    /// Creates a new cache that holds up to `capacity` hunks. A cache
+    /// always holds at least one hunk.
+    pub(crate) fn new(capacity: usize) -> Self {
+        HunkCache {
+            capacity: std::cmp::max(capacity, 1),
+            max_bytes: usize::MAX,
+            bytes: 0,
+            entries: VecDeque::new(),
+            spare: None,
+        }
+    }
+
+    /// Creates a new empty cache with the same bounds as this cache.
+    pub(crate) fn new_like(&self) -> Self {
+        HunkCache {
+            capacity: self.capacity,
+            max_bytes: self.max_bytes,
+            bytes: 0,
+            entries: VecDeque::new(),
+            spare: None,
+        }
+    }
+
+    /// Bounds the cache to `capacity` hunks of any length, evicting the least recently used
+    /// hunks if the cache holds more.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = std::cmp::max(capacity, 1);
+        self.max_bytes = usize::MAX;
+        self.shrink_to(self.capacity, self.max_bytes);
+    }
+
+    /// Bounds the cache to hunks with a total length of up to `max_bytes`, evicting the least
+    /// recently used hunks if the cache holds more. A hunk longer than `max_bytes` is still
+    /// inserted, and is then held on its own until the next hunk is inserted.
+    pub(crate) fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.capacity = usize::MAX;
+        self.max_bytes = max_bytes;
+        self.shrink_to(self.capacity, self.max_bytes);
+    }
+
+    /// Returns the total length of the hunks held in the cache.
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns whether the given hunk is held in the cache.
+    pub(crate) fn contains(&self, hunk_num: u32) -> bool {
+        self.entries.iter().any(|(num, _)| *num == hunk_num)
+    }
+
+    /// Returns the decompressed contents of the given hunk if it is held in the cache,
+    /// marking it as the most recently used.
+    pub(crate) fn get(&mut self, hunk_num: u32) -> Option<&[u8]> {
+        let pos = self.entries.iter().position(|(num, _)| *num == hunk_num)?;
+        let entry = self.entries.remove(pos)?;
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, buf)| buf.as_slice())
+    }
+
+    /// Removes the given hunk from the cache if it is held in the cache.
+    pub(crate) fn remove(&mut self, hunk_num: u32) {
+        if let Some(pos) = self.entries.iter().position(|(num, _)| *num == hunk_num) {
+            self.spare = self.entries.remove(pos).map(|(_, buf)| {
+                self.bytes -= buf.len();
+                buf
+            });
+        }
+    }
+
+    /// Removes all hunks from the cache.
+    pub(crate) fn clear(&mut self) {
+        if let Some((_, buf)) = self.entries.pop_back() {
+            self.spare = Some(buf);
+        }
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// Takes a buffer of the given length to decompress a hunk into, reusing
+    /// the allocation of a previously evicted hunk if possible.
+    pub(crate) fn take_buffer(&mut self, len: usize) -> Vec<u8> {
+        let mut buf = self.spare.take().unwrap_or_default();
+        buf.resize(len, 0);
+        buf
+    }
+
+    /// Inserts a decompressed hunk into the cache, evicting the least recently used
+    /// hunks if the cache is full.
+    pub(crate) fn insert(&mut self, hunk_num: u32, buf: Vec<u8>) {
+        self.shrink_to(self.capacity - 1, self.max_bytes.saturating_sub(buf.len()));
+        self.bytes += buf.len();
+        self.entries.push_back((hunk_num, buf));
+    }
+
+    /// Evicts the least recently used hunks until the cache holds at most `hunks` hunks
+    /// with a total length of at most `bytes`.
+    fn shrink_to(&mut self, hunks: usize, bytes: usize) {
+        while self.entries.len() > hunks || self.bytes > bytes {
+            match self.entries.pop_front() {
+                Some((_, evicted)) => {
+                    self.bytes -= evicted.len();
+                    self.spare = Some(evicted);
+                }
+                None => break,
+            }
+        }
+    }
+}
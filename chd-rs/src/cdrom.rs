@@ -1,19 +1,820 @@
-/// Constants for CD-ROM decoding.
+//! Types and constants for CD-ROM and GD-ROM data stored in a CHD file.
+//!
+//! The table of contents of a CD-ROM CHD file is stored in its metadata, with one
+//! metadata entry per track. It can be read with [`ChdFile::cd_toc`](crate::ChdFile::cd_toc).
+use crate::error::{ChdError, Result};
+use crate::metadata::KnownMetadata;
+use crc::{Crc, CRC_16_GSM, CRC_32_CD_ROM_EDC};
+use num_traits::FromPrimitive;
+use std::ops::Range;
 
 /* The following constants are present in headers, but are unused for this implementation. */
 
-// pub const CD_TRACK_PADDING: u32 = 4;
 // pub const CD_MAX_TRACKS: u32 = 99;    /* AFAIK the theoretical limit */
 // pub const CD_METADATA_WORDS: u32 = 1 + (CD_MAX_TRACKS * 6);
 // pub const CD_FRAMES_PER_HUNK: u32 = 8;
 
+/// The number of frames each track is padded to a multiple of in a CHD file.
+pub const CD_TRACK_PADDING: u32 = 4;
+
+/// The maximum number of bytes of sector data in a CD frame.
 pub const CD_MAX_SECTOR_DATA: u32 = 2352;
+/// The maximum number of bytes of subcode data in a CD frame.
 pub const CD_MAX_SUBCODE_DATA: u32 = 96;
+/// The size of a CD frame in a CHD file, which is the sector data followed by the subcode data.
 pub const CD_FRAME_SIZE: u32 = CD_MAX_SECTOR_DATA + CD_MAX_SUBCODE_DATA;
+/// The number of bytes in the sync header of a data sector.
 pub const CD_SYNC_NUM_BYTES: usize = 12;
 
+/// The sync header at the beginning of every data sector.
 pub const CD_SYNC_HEADER: [u8; CD_SYNC_NUM_BYTES] = [
     0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00,
 ];
+/// The offset of the sync header in a data sector.
 pub const CD_SYNC_OFFSET: usize = 0x000;
+/// The offset of the mode byte in a data sector.
 pub const CD_MODE_OFFSET: usize = 0x00f;
+
+/// The number of bytes of the Q subchannel in a CD frame.
+pub const CD_SUBCODE_Q_SIZE: usize = 12;
+
+// The Q subchannel CRC is CRC-16/CCITT with the remainder inverted.
+const SUBCODE_Q_CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_GSM);
+
+// The EDC of a data sector is stored little-endian after the data it covers.
+const SECTOR_EDC: Crc<u32> = Crc::<u32>::new(&CRC_32_CD_ROM_EDC);
+const MODE1_EDC_RANGE: Range<usize> = 0x000..0x810;
+const MODE2_FORM1_EDC_RANGE: Range<usize> = 0x010..0x818;
+const MODE2_FORM2_EDC_RANGE: Range<usize> = 0x010..0x92c;
+// The offset of the submode byte of the Mode 2 subheader, and the bit marking Form 2.
+const MODE2_SUBMODE_OFFSET: usize = 0x012;
+const MODE2_SUBMODE_FORM2: u8 = 0x20;
+// The offset of the MSF address in the header of a data sector, and of the Mode 2 subheader.
+const SECTOR_MSF_OFFSET: usize = 0x00c;
+const MODE2_SUBHEADER_OFFSET: usize = 0x010;
+// The user data of each type of data sector.
+const MODE1_DATA_RANGE: Range<usize> = 0x010..0x810;
+const MODE2_FORM1_DATA_RANGE: Range<usize> = 0x018..0x818;
+const MODE2_FORM2_DATA_RANGE: Range<usize> = 0x018..0x92c;
+const MODE2_DATA_RANGE: Range<usize> = 0x010..0x930;
+
+/// The number of frames of the initial pregap of a disc, which precede LBA 0.
+pub const CD_INITIAL_PREGAP: u32 = 150;
+
+/// The number of frames in one second of MSF time.
+const CD_FRAMES_PER_SECOND: u32 = 75;
+
+/// The LBA where the high density area of a GD-ROM begins.
+pub const GDROM_HIGH_DENSITY_LBA: u32 = 45000;
+
+/// The type of data stored in the frames of a CD-ROM track.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CdTrackType {
+    /// Mode 1 with 2048 bytes of user data per sector.
+    Mode1,
+    /// Mode 1 raw sectors with 2352 bytes per sector.
+    Mode1Raw,
+    /// Mode 2 with 2336 bytes of user data per sector.
+    Mode2,
+    /// Mode 2 Form 1 with 2048 bytes of user data per sector.
+    Mode2Form1,
+    /// Mode 2 Form 2 with 2324 bytes of user data per sector.
+    Mode2Form2,
+    /// Mode 2 with mixed forms and 2336 bytes of user data per sector.
+    Mode2FormMix,
+    /// Mode 2 raw sectors with 2352 bytes per sector.
+    Mode2Raw,
+    /// Red Book audio with 2352 bytes per sector.
+    Audio,
+}
+
+impl CdTrackType {
+    /// Parses the track type from its name in the track metadata.
+    pub fn from_metadata(name: &str) -> Option<CdTrackType> {
+        match name {
+            "MODE1" => Some(CdTrackType::Mode1),
+            "MODE1_RAW" => Some(CdTrackType::Mode1Raw),
+            "MODE2" => Some(CdTrackType::Mode2),
+            "MODE2_FORM1" => Some(CdTrackType::Mode2Form1),
+            "MODE2_FORM2" => Some(CdTrackType::Mode2Form2),
+            "MODE2_FORM_MIX" => Some(CdTrackType::Mode2FormMix),
+            "MODE2_RAW" => Some(CdTrackType::Mode2Raw),
+            "AUDIO" => Some(CdTrackType::Audio),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the track type in the track metadata.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            CdTrackType::Mode1 => "MODE1",
+            CdTrackType::Mode1Raw => "MODE1_RAW",
+            CdTrackType::Mode2 => "MODE2",
+            CdTrackType::Mode2Form1 => "MODE2_FORM1",
+            CdTrackType::Mode2Form2 => "MODE2_FORM2",
+            CdTrackType::Mode2FormMix => "MODE2_FORM_MIX",
+            CdTrackType::Mode2Raw => "MODE2_RAW",
+            CdTrackType::Audio => "AUDIO",
+        }
+    }
+
+    /// Returns the number of bytes of sector data stored at the beginning of each frame of a
+    /// track of this type.
+    pub const fn data_size(&self) -> u32 {
+        match self {
+            CdTrackType::Mode1 | CdTrackType::Mode2Form1 => 2048,
+            CdTrackType::Mode2 | CdTrackType::Mode2FormMix => 2336,
+            CdTrackType::Mode2Form2 => 2324,
+            CdTrackType::Mode1Raw | CdTrackType::Mode2Raw | CdTrackType::Audio => {
+                CD_MAX_SECTOR_DATA
+            }
+        }
+    }
+
+    /// Returns whether or not this is an audio track type.
+    pub const fn is_audio(&self) -> bool {
+        matches!(self, CdTrackType::Audio)
+    }
+
+    /// Parses the track type from its mode in a cue sheet `TRACK` command.
+    fn from_cue_mode(mode: &str) -> Option<CdTrackType> {
+        match mode.to_ascii_uppercase().as_str() {
+            "MODE1/2048" => Some(CdTrackType::Mode1),
+            "MODE1/2352" => Some(CdTrackType::Mode1Raw),
+            "MODE2/2336" => Some(CdTrackType::Mode2),
+            "MODE2/2048" => Some(CdTrackType::Mode2Form1),
+            "MODE2/2324" => Some(CdTrackType::Mode2Form2),
+            "MODE2/2352" => Some(CdTrackType::Mode2Raw),
+            "AUDIO" => Some(CdTrackType::Audio),
+            _ => None,
+        }
+    }
+
+    /// Returns the mode of the track type in a cue sheet `TRACK` command.
+    const fn cue_mode(&self) -> &'static str {
+        match self {
+            CdTrackType::Mode1 => "MODE1/2048",
+            CdTrackType::Mode1Raw => "MODE1/2352",
+            CdTrackType::Mode2 | CdTrackType::Mode2FormMix => "MODE2/2336",
+            CdTrackType::Mode2Form1 => "MODE2/2048",
+            CdTrackType::Mode2Form2 => "MODE2/2324",
+            CdTrackType::Mode2Raw => "MODE2/2352",
+            CdTrackType::Audio => "AUDIO",
+        }
+    }
+}
+
+/// The type of subcode data stored in the frames of a CD-ROM track.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CdSubcodeType {
+    /// Interleaved R-W subcode data (RW).
+    Normal,
+    /// Raw R-W subcode data (RW_RAW).
+    Raw,
+    /// No subcode data is stored (NONE).
+    None,
+}
+
+impl CdSubcodeType {
+    /// Parses the subcode type from its name in the track metadata.
+    pub fn from_metadata(name: &str) -> Option<CdSubcodeType> {
+        match name {
+            "RW" => Some(CdSubcodeType::Normal),
+            "RW_RAW" => Some(CdSubcodeType::Raw),
+            "NONE" => Some(CdSubcodeType::None),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the subcode type in the track metadata.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            CdSubcodeType::Normal => "RW",
+            CdSubcodeType::Raw => "RW_RAW",
+            CdSubcodeType::None => "NONE",
+        }
+    }
+}
+
+/// The type of a raw CD sector, as determined by [`sector_type`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectorType {
+    /// An audio sector, or any sector that does not begin with the sync header.
+    Audio,
+    /// A Mode 0 data sector, which holds no user data.
+    Mode0,
+    /// A Mode 1 data sector with 2048 bytes of user data.
+    Mode1,
+    /// A Mode 2 Form 1 data sector with 2048 bytes of user data.
+    Mode2Form1,
+    /// A Mode 2 Form 2 data sector with 2324 bytes of user data.
+    Mode2Form2,
+    /// A data sector with a sync header and an unknown mode byte.
+    Unknown(u8),
+}
+
+/// Determines the type of a raw CD sector from its sync header, mode byte, and for Mode 2
+/// sectors, the submode byte of its subheader.
+pub fn sector_type(sector: &[u8; CD_MAX_SECTOR_DATA as usize]) -> SectorType {
+    if sector[CD_SYNC_OFFSET..][..CD_SYNC_NUM_BYTES] != CD_SYNC_HEADER {
+        return SectorType::Audio;
+    }
+    match sector[CD_MODE_OFFSET] {
+        0 => SectorType::Mode0,
+        1 => SectorType::Mode1,
+        2 if sector[MODE2_SUBMODE_OFFSET] & MODE2_SUBMODE_FORM2 != 0 => SectorType::Mode2Form2,
+        2 => SectorType::Mode2Form1,
+        mode => SectorType::Unknown(mode),
+    }
+}
+
+/// Verifies the EDC (error detection code) of a raw CD data sector.
+///
+/// The EDC is a CRC-32 over the header and user data of Mode 1 sectors, and over the
+/// subheader and user data of Mode 2 sectors. Since the EDC of Mode 2 Form 2 sectors is
+/// optional, an EDC of zero is accepted for them. Returns `false` for sectors that do not
+/// have an EDC.
+pub fn verify_edc(sector: &[u8; CD_MAX_SECTOR_DATA as usize]) -> bool {
+    let range = match sector_type(sector) {
+        SectorType::Mode1 => MODE1_EDC_RANGE,
+        SectorType::Mode2Form1 => MODE2_FORM1_EDC_RANGE,
+        SectorType::Mode2Form2 => MODE2_FORM2_EDC_RANGE,
+        SectorType::Audio | SectorType::Mode0 | SectorType::Unknown(_) => return false,
+    };
+    let stored = u32::from_le_bytes([
+        sector[range.end],
+        sector[range.end + 1],
+        sector[range.end + 2],
+        sector[range.end + 3],
+    ]);
+    if stored == 0 && range == MODE2_FORM2_EDC_RANGE {
+        return true;
+    }
+    SECTOR_EDC.checksum(&sector[range]) == stored
+}
+
+/// A raw CD sector with the subcode data of its frame, if any.
+///
+/// The accessors interpret the sector according to its [`mode`](Sector::mode), which is
+/// determined from the sector itself in the same way as [`sector_type`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sector {
+    sector: [u8; CD_MAX_SECTOR_DATA as usize],
+    subcode: Option<[u8; CD_MAX_SUBCODE_DATA as usize]>,
+}
+
+impl Sector {
+    /// Creates a sector from its 2352 bytes of sector data and optional subcode data.
+    pub fn new(
+        sector: [u8; CD_MAX_SECTOR_DATA as usize],
+        subcode: Option<[u8; CD_MAX_SUBCODE_DATA as usize]>,
+    ) -> Sector {
+        Sector { sector, subcode }
+    }
+
+    /// Creates a sector from a CD frame holding the sector data followed by the subcode data,
+    /// as returned by [`ChdFile::read_full_frame`](crate::ChdFile::read_full_frame).
+    pub fn from_frame(frame: &[u8; CD_FRAME_SIZE as usize]) -> Sector {
+        let (sector, subcode) = frame.split_at(CD_MAX_SECTOR_DATA as usize);
+        Sector {
+            sector: sector.try_into().unwrap(),
+            subcode: Some(subcode.try_into().unwrap()),
+        }
+    }
+
+    /// Returns the raw 2352 bytes of the sector.
+    pub fn raw(&self) -> &[u8; CD_MAX_SECTOR_DATA as usize] {
+        &self.sector
+    }
+
+    /// Returns the subcode data of the frame of the sector, if any.
+    pub fn subcode(&self) -> Option<&[u8; CD_MAX_SUBCODE_DATA as usize]> {
+        self.subcode.as_ref()
+    }
+
+    /// Returns the type of the sector.
+    pub fn mode(&self) -> SectorType {
+        sector_type(&self.sector)
+    }
+
+    /// Returns the user data of the sector.
+    ///
+    /// This is the 2048 bytes of user data of Mode 1 and Mode 2 Form 1 sectors, the 2324 bytes
+    /// of Mode 2 Form 2 sectors, and all 2352 bytes of audio sectors. For Mode 0 sectors and
+    /// sectors of an unknown mode, this is the 2336 bytes following the sector header.
+    pub fn data(&self) -> &[u8] {
+        let range = match self.mode() {
+            SectorType::Audio => return &self.sector,
+            SectorType::Mode1 => MODE1_DATA_RANGE,
+            SectorType::Mode2Form1 => MODE2_FORM1_DATA_RANGE,
+            SectorType::Mode2Form2 => MODE2_FORM2_DATA_RANGE,
+            SectorType::Mode0 | SectorType::Unknown(_) => MODE2_DATA_RANGE,
+        };
+        &self.sector[range]
+    }
+
+    /// Returns the address of a data sector from its header as minutes, seconds and frames,
+    /// decoded from BCD. Returns `None` for audio sectors, which do not have a header.
+    pub fn msf(&self) -> Option<(u8, u8, u8)> {
+        if self.mode() == SectorType::Audio {
+            return None;
+        }
+        let bcd = |value: u8| (value >> 4) * 10 + (value & 0x0f);
+        let msf = &self.sector[SECTOR_MSF_OFFSET..][..3];
+        Some((bcd(msf[0]), bcd(msf[1]), bcd(msf[2])))
+    }
+
+    /// Returns the subheader of a Mode 2 Form 1 or Form 2 sector, which is the file number,
+    /// channel number, submode and coding information. Returns `None` for other sectors.
+    pub fn subheader(&self) -> Option<[u8; 4]> {
+        match self.mode() {
+            SectorType::Mode2Form1 | SectorType::Mode2Form2 => {
+                self.sector[MODE2_SUBHEADER_OFFSET..][..4].try_into().ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A track of a CD-ROM or GD-ROM, as described by its track metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CdTrack {
+    /// The track number, starting at 1.
+    pub number: u32,
+    /// The type of data stored in the frames of this track.
+    pub track_type: CdTrackType,
+    /// The type of subcode data stored in the frames of this track.
+    pub subcode_type: CdSubcodeType,
+    /// The number of frames of this track stored in the CHD file, including the pregap if
+    /// [`pregap_in_file`](CdTrack::pregap_in_file) is set.
+    pub frames: u32,
+    /// The number of padding frames following this track in the CHD file.
+    pub pad_frames: u32,
+    /// The number of pregap frames before this track.
+    pub pregap: u32,
+    /// The type of the pregap frames, if specified.
+    pub pregap_type: Option<CdTrackType>,
+    /// The type of subcode data of the pregap frames.
+    pub pregap_subcode_type: CdSubcodeType,
+    /// Whether or not the pregap frames are stored in the CHD file at the beginning of this track.
+    pub pregap_in_file: bool,
+    /// The number of postgap frames after this track.
+    pub postgap: u32,
+}
+
+impl CdTrack {
+    /// Parses a track from the text contents of a `CHTR`, `CHT2` or `CHGD` metadata entry.
+    ///
+    /// Fields that are not present in older formats of track metadata take their default values.
+    /// If the track can not be parsed, returns `ChdError::MetadataParse` for the `CHT2` tag.
+    pub fn from_metadata(value: &[u8]) -> Result<CdTrack> {
+        CdTrack::parse(value).map_err(|detail| ChdError::MetadataParse {
+            tag: *b"CHT2",
+            detail,
+        })
+    }
+
+    /// Parses a track from the text contents of a track metadata entry, returning a
+    /// description of the first invalid field on failure.
+    fn parse(value: &[u8]) -> std::result::Result<CdTrack, String> {
+        let text = std::str::from_utf8(value)
+            .map_err(|_| String::from("not valid UTF-8"))?
+            .trim_end_matches('\0');
+
+        let mut number = None;
+        let mut track_type = None;
+        let mut subcode_type = None;
+        let mut frames = None;
+        let mut pad_frames = None;
+        let mut pregap = 0;
+        let mut pregap_type = None;
+        let mut pregap_in_file = false;
+        let mut pregap_subcode_type = CdSubcodeType::None;
+        let mut postgap = 0;
+
+        for field in text.split_whitespace() {
+            let (key, value) = field
+                .split_once(':')
+                .ok_or_else(|| format!("malformed field '{}'", field))?;
+            let parse_number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid number in field '{}'", field))
+            };
+            match key {
+                "TRACK" => number = Some(parse_number()?),
+                "TYPE" => track_type = CdTrackType::from_metadata(value),
+                "SUBTYPE" => subcode_type = CdSubcodeType::from_metadata(value),
+                "FRAMES" => frames = Some(parse_number()?),
+                "PAD" => pad_frames = Some(parse_number()?),
+                "PREGAP" => pregap = parse_number()?,
+                "PGTYPE" => {
+                    // A 'V' prefix indicates that the pregap is stored in the file.
+                    let (in_file, name) = match value.strip_prefix('V') {
+                        Some(name) => (true, name),
+                        None => (false, value),
+                    };
+                    pregap_in_file = in_file;
+                    pregap_type = CdTrackType::from_metadata(name);
+                }
+                "PGSUB" => {
+                    pregap_subcode_type = CdSubcodeType::from_metadata(value)
+                        .ok_or_else(|| format!("unknown subcode type in field '{}'", field))?
+                }
+                "POSTGAP" => postgap = parse_number()?,
+                _ => return Err(format!("unknown field '{}'", field)),
+            }
+        }
+
+        let missing = |name: &str| format!("missing or invalid {} field", name);
+        let frames = frames.ok_or_else(|| missing("FRAMES"))?;
+        Ok(CdTrack {
+            number: number.ok_or_else(|| missing("TRACK"))?,
+            track_type: track_type.ok_or_else(|| missing("TYPE"))?,
+            subcode_type: subcode_type.ok_or_else(|| missing("SUBTYPE"))?,
+            frames,
+            // Tracks are padded to a multiple of 4 frames unless otherwise specified.
+            pad_frames: pad_frames
+                .unwrap_or((CD_TRACK_PADDING - frames % CD_TRACK_PADDING) % CD_TRACK_PADDING),
+            pregap,
+            pregap_type,
+            pregap_subcode_type,
+            pregap_in_file: pregap_in_file && pregap > 0,
+            postgap,
+        })
+    }
+}
+
+/// The table of contents of a CD-ROM or GD-ROM stored in a CHD file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CdToc {
+    /// The tracks of the disc, in order.
+    pub tracks: Vec<CdTrack>,
+    /// Whether or not the disc is a GD-ROM.
+    pub gdrom: bool,
+}
+
+impl CdToc {
+    /// Creates a table of contents from the tag and contents of the track metadata entries
+    /// of a CHD file.
+    ///
+    /// The legacy binary `CHCD` and `CHGT` formats are not supported. If there are no track
+    /// metadata entries, returns `ChdError::MetadataNotFound` for the `CHT2` tag.
+    pub fn from_metadata<'a, I: IntoIterator<Item = (u32, &'a [u8])>>(entries: I) -> Result<CdToc> {
+        let mut gdrom = false;
+        let mut tracks = Vec::new();
+        for (metatag, value) in entries {
+            match KnownMetadata::from_u32(metatag) {
+                Some(KnownMetadata::CdRomTrack) | Some(KnownMetadata::CdRomTrack2) => {}
+                Some(KnownMetadata::GdRomTrack) => gdrom = true,
+                Some(KnownMetadata::CdRomOld) | Some(KnownMetadata::GdRomOld) => {
+                    return Err(ChdError::UnsupportedFormat)
+                }
+                _ => continue,
+            }
+            let track = CdTrack::parse(value).map_err(|detail| ChdError::MetadataParse {
+                tag: metatag.to_be_bytes(),
+                detail,
+            })?;
+            tracks.push(track);
+        }
+
+        if tracks.is_empty() {
+            return Err(ChdError::MetadataNotFound(*b"CHT2"));
+        }
+        tracks.sort_by_key(|track| track.number);
+        Ok(CdToc { tracks, gdrom })
+    }
+
+    /// Returns the LBA of the lead-out, which immediately follows the postgap of the last track.
+    pub fn leadout_lba(&self) -> u32 {
+        let last = self.tracks.last().zip(track_layout(self).last().copied());
+        last.map_or(0, |(track, placement)| {
+            placement.lba + track.frames + track.postgap
+        })
+    }
+
+    /// Returns the number of frames of the disc up to the lead-out, including the pregaps and
+    /// postgaps of all tracks and the [initial pregap](CD_INITIAL_PREGAP) before LBA 0.
+    ///
+    /// This is the absolute frame address of the lead-out, as used in MSF times.
+    pub fn total_frames(&self) -> u32 {
+        self.leadout_lba() + CD_INITIAL_PREGAP
+    }
+
+    /// Writes this table of contents as a cue sheet for a single binary file with the given name,
+    /// holding the frames of each track without padding, as extracted by chdman.
+    ///
+    /// Pregaps stored in the file are written as `INDEX 00`, and pregaps and postgaps that are
+    /// not stored in the file as `PREGAP` and `POSTGAP` commands.
+    ///
+    /// Cue sheets can not express subcode data, GD-ROM layouts or mixed form Mode 2 tracks,
+    /// which are written as `MODE2/2336`.
+    pub fn to_cue(&self, bin_filename: &str) -> String {
+        let mut cue = format!("FILE \"{}\" BINARY\n", bin_filename);
+        let mut file_frame = 0;
+        for track in &self.tracks {
+            cue.push_str(&format!(
+                "  TRACK {:02} {}\n",
+                track.number,
+                track.track_type.cue_mode()
+            ));
+            if track.pregap_in_file {
+                cue.push_str(&format!("    INDEX 00 {}\n", format_msf(file_frame)));
+                cue.push_str(&format!(
+                    "    INDEX 01 {}\n",
+                    format_msf(file_frame + track.pregap)
+                ));
+            } else {
+                if track.pregap > 0 {
+                    cue.push_str(&format!("    PREGAP {}\n", format_msf(track.pregap)));
+                }
+                cue.push_str(&format!("    INDEX 01 {}\n", format_msf(file_frame)));
+            }
+            if track.postgap > 0 {
+                cue.push_str(&format!("    POSTGAP {}\n", format_msf(track.postgap)));
+            }
+            file_frame += track.frames;
+        }
+        cue
+    }
+
+    /// Parses a table of contents from a cue sheet for a single binary file, such as one
+    /// written by [`to_cue`](CdToc::to_cue), with the length of the binary file in bytes.
+    ///
+    /// Each track extends to the first index of the next track. A cue sheet does not record
+    /// the length of the final track, so the final track extends to the end of the binary file.
+    /// Indexes after `INDEX 01` and commands that do not affect the layout of the tracks are
+    /// ignored. The tracks have no subcode data and are padded like tracks created by chdman.
+    ///
+    /// If the cue sheet is malformed, or the binary file does not end on a whole frame of the
+    /// final track, returns `ChdError::InvalidData`. If the cue sheet references more than one
+    /// file, or a track has both a pregap in the file and a `PREGAP` command, returns
+    /// `ChdError::NotSupported`.
+    pub fn from_cue(cue: &str, bin_len: u64) -> Result<CdToc> {
+        struct CueTrack {
+            number: u32,
+            track_type: CdTrackType,
+            pregap: u32,
+            postgap: u32,
+            index0: Option<u32>,
+            index1: Option<u32>,
+        }
+
+        let mut files = 0;
+        let mut cue_tracks: Vec<CueTrack> = Vec::new();
+        for line in cue.lines() {
+            let mut words = line.split_whitespace();
+            let command = match words.next() {
+                Some(command) => command.to_ascii_uppercase(),
+                None => continue,
+            };
+            let mut arg = || words.next().ok_or(ChdError::InvalidData);
+            match command.as_str() {
+                "FILE" => {
+                    files += 1;
+                    if files > 1 {
+                        return Err(ChdError::NotSupported);
+                    }
+                }
+                "TRACK" => {
+                    let number = arg()?.parse().map_err(|_| ChdError::InvalidData)?;
+                    let track_type =
+                        CdTrackType::from_cue_mode(arg()?).ok_or(ChdError::InvalidData)?;
+                    cue_tracks.push(CueTrack {
+                        number,
+                        track_type,
+                        pregap: 0,
+                        postgap: 0,
+                        index0: None,
+                        index1: None,
+                    });
+                }
+                "INDEX" | "PREGAP" | "POSTGAP" => {
+                    let track = cue_tracks.last_mut().ok_or(ChdError::InvalidData)?;
+                    if command == "INDEX" {
+                        let index: u32 = arg()?.parse().map_err(|_| ChdError::InvalidData)?;
+                        let frame = parse_msf(arg()?)?;
+                        match index {
+                            0 => track.index0 = Some(frame),
+                            1 => track.index1 = Some(frame),
+                            _ => {}
+                        }
+                    } else if command == "PREGAP" {
+                        track.pregap = parse_msf(arg()?)?;
+                    } else {
+                        track.postgap = parse_msf(arg()?)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if cue_tracks.is_empty() {
+            return Err(ChdError::InvalidData);
+        }
+
+        let starts = cue_tracks
+            .iter()
+            .map(|track| track.index0.or(track.index1).ok_or(ChdError::InvalidData))
+            .collect::<Result<Vec<_>>>()?;
+        let mut tracks = Vec::with_capacity(cue_tracks.len());
+        let mut remaining_bytes = bin_len;
+        for (index, track) in cue_tracks.iter().enumerate() {
+            let start = starts[index];
+            let frame_bytes = track.track_type.data_size() as u64;
+            let frames = match starts.get(index + 1) {
+                Some(&end) => end.checked_sub(start).ok_or(ChdError::InvalidData)?,
+                None if remaining_bytes % frame_bytes == 0 => (remaining_bytes / frame_bytes)
+                    .try_into()
+                    .map_err(|_| ChdError::InvalidData)?,
+                None => return Err(ChdError::InvalidData),
+            };
+            remaining_bytes = remaining_bytes
+                .checked_sub(frames as u64 * frame_bytes)
+                .ok_or(ChdError::InvalidData)?;
+
+            let in_file_pregap = match (track.index0, track.index1) {
+                (Some(index0), Some(index1)) => {
+                    index1.checked_sub(index0).ok_or(ChdError::InvalidData)?
+                }
+                _ => 0,
+            };
+            if in_file_pregap > 0 && track.pregap > 0 {
+                return Err(ChdError::NotSupported);
+            }
+            let pregap = in_file_pregap + track.pregap;
+
+            tracks.push(CdTrack {
+                number: track.number,
+                track_type: track.track_type,
+                subcode_type: CdSubcodeType::None,
+                frames,
+                pad_frames: (CD_TRACK_PADDING - frames % CD_TRACK_PADDING) % CD_TRACK_PADDING,
+                pregap,
+                pregap_type: if pregap > 0 {
+                    Some(track.track_type)
+                } else {
+                    None
+                },
+                pregap_subcode_type: CdSubcodeType::None,
+                pregap_in_file: in_file_pregap > 0,
+                postgap: track.postgap,
+            });
+        }
+
+        Ok(CdToc {
+            tracks,
+            gdrom: false,
+        })
+    }
+
+    /// Returns the frames in the CHD file that belong to tracks with subcode data, excluding
+    /// the padding frames between tracks, with the subcode type of each track.
+    pub(crate) fn subcode_frames(&self) -> Vec<(Range<u32>, CdSubcodeType)> {
+        self.tracks
+            .iter()
+            .zip(track_layout(self))
+            .filter(|(track, _)| track.subcode_type != CdSubcodeType::None)
+            .map(|(track, placement)| (placement.chd_frames(), track.subcode_type))
+            .collect()
+    }
+}
+
+/// Formats a number of frames as an MSF time in the form `mm:ss:ff`.
+fn format_msf(frames: u32) -> String {
+    let seconds = frames / CD_FRAMES_PER_SECOND;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 60,
+        seconds % 60,
+        frames % CD_FRAMES_PER_SECOND
+    )
+}
+
+/// Parses an MSF time in the form `mm:ss:ff` into a number of frames.
+fn parse_msf(msf: &str) -> Result<u32> {
+    let mut parts = msf.split(':').map(|part| part.parse::<u32>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(m)), Some(Some(s)), Some(Some(f)), None)
+            if s < 60 && f < CD_FRAMES_PER_SECOND =>
+        {
+            Ok((m * 60 + s) * CD_FRAMES_PER_SECOND + f)
+        }
+        _ => Err(ChdError::InvalidData),
+    }
+}
+
+/// The placement of a track within a CHD file, as computed by [`track_layout`](crate::cdrom::track_layout).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TrackPlacement {
+    /// The LBA of the first frame of the track that is stored in the CHD file. If the pregap
+    /// of the track is stored in the file, this is the LBA of the start of the pregap.
+    pub lba: u32,
+    /// The frame in the CHD file where the track begins, including the pad frames of all
+    /// previous tracks.
+    pub chd_frame: u32,
+    /// The number of frames of the track stored in the CHD file.
+    pub frames: u32,
+    /// The number of pad frames following the track in the CHD file.
+    pub pad_frames: u32,
+}
+
+impl TrackPlacement {
+    /// Returns the range of frames in the CHD file that hold the track, excluding padding.
+    pub fn chd_frames(&self) -> Range<u32> {
+        self.chd_frame..self.chd_frame + self.frames
+    }
+}
+
+/// Computes the placement of each track of the table of contents within the CHD file.
+///
+/// Each track is followed by its pad frames in the CHD file, which pad the track to a multiple
+/// of 4 frames unless the track metadata specifies otherwise. Pregaps that are not stored in
+/// the file and postgaps advance the LBA without occupying frames in the CHD file.
+///
+/// The high density area of a GD-ROM begins at the third track, which is placed at
+/// [`GDROM_HIGH_DENSITY_LBA`](crate::cdrom::GDROM_HIGH_DENSITY_LBA) after its pregap.
+pub fn track_layout(toc: &CdToc) -> Vec<TrackPlacement> {
+    let mut lba = 0;
+    let mut chd_frame = 0;
+    let mut layout = Vec::with_capacity(toc.tracks.len());
+    for (index, track) in toc.tracks.iter().enumerate() {
+        if !track.pregap_in_file {
+            lba += track.pregap;
+        }
+        if toc.gdrom && index == 2 {
+            lba = GDROM_HIGH_DENSITY_LBA;
+        }
+        layout.push(TrackPlacement {
+            lba,
+            chd_frame,
+            frames: track.frames,
+            pad_frames: track.pad_frames,
+        });
+        lba += track.frames + track.postgap;
+        chd_frame += track.frames + track.pad_frames;
+    }
+    layout
+}
+
+/// Extracts the Q subchannel from the subcode data of a frame, where each byte of the
+/// subcode data holds one bit of each of the P to W subchannels.
+///
+/// This is the layout of the subcode data of `RW` tracks. Use [`subcode_q_of`] to extract the
+/// Q subchannel according to the subcode type of the track.
+pub fn subcode_q(subcode: &[u8; CD_MAX_SUBCODE_DATA as usize]) -> [u8; CD_SUBCODE_Q_SIZE] {
+    let mut q = [0u8; CD_SUBCODE_Q_SIZE];
+    for (byte, bits) in q.iter_mut().zip(subcode.chunks_exact(8)) {
+        *byte = bits
+            .iter()
+            .fold(0, |byte, &bits| (byte << 1) | ((bits >> 6) & 1));
+    }
+    q
+}
+
+/// Returns the subcode data of a frame of a track with the given subcode type, with each of
+/// the P to W subchannels stored as 12 consecutive bytes.
+///
+/// The subcode data of `RW` tracks is interleaved, with each byte holding one bit of each
+/// of the P to W subchannels, and is deinterleaved. The subcode data of `RW_RAW` tracks already
+/// stores each subchannel separately, and is returned as is, as is the subcode data of
+/// tracks without subcode data.
+pub fn deinterleave_subcode(
+    subcode: &[u8; CD_MAX_SUBCODE_DATA as usize],
+    subcode_type: CdSubcodeType,
+) -> [u8; CD_MAX_SUBCODE_DATA as usize] {
+    if subcode_type != CdSubcodeType::Normal {
+        return *subcode;
+    }
+    let mut channels = [0u8; CD_MAX_SUBCODE_DATA as usize];
+    for (channel, out) in channels.chunks_exact_mut(CD_SUBCODE_Q_SIZE).enumerate() {
+        for (byte, bits) in out.iter_mut().zip(subcode.chunks_exact(8)) {
+            *byte = bits
+                .iter()
+                .fold(0, |byte, &bits| (byte << 1) | ((bits >> (7 - channel)) & 1));
+        }
+    }
+    channels
+}
+
+/// Returns the Q subchannel of the subcode data of a frame of a track with the given
+/// subcode type. See [`deinterleave_subcode`] for the layouts of each subcode type.
+pub fn subcode_q_of(
+    subcode: &[u8; CD_MAX_SUBCODE_DATA as usize],
+    subcode_type: CdSubcodeType,
+) -> [u8; CD_SUBCODE_Q_SIZE] {
+    match subcode_type {
+        CdSubcodeType::Normal => subcode_q(subcode),
+        _ => {
+            let mut q = [0u8; CD_SUBCODE_Q_SIZE];
+            q.copy_from_slice(&subcode[CD_SUBCODE_Q_SIZE..][..CD_SUBCODE_Q_SIZE]);
+            q
+        }
+    }
+}
+
+/// Returns whether the CRC at the end of the Q subchannel matches its contents.
+pub fn subcode_q_crc_valid(q: &[u8; CD_SUBCODE_Q_SIZE]) -> bool {
+    SUBCODE_Q_CRC.checksum(&q[..10]) == u16::from_be_bytes([q[10], q[11]])
+}
@@ -1,40 +1,153 @@
 use crate::block_hash::ChdBlockChecksum;
+use crate::cache::HunkCache;
+use crate::cdrom::{
+    deinterleave_subcode, track_layout, CdSubcodeType, CdToc, CdTrack, TrackPlacement,
+    CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA,
+};
 use crate::compression::CompressionCodec;
 use crate::error::{ChdError, Result};
-use crate::header::ChdHeader;
+use crate::header::{hard_disk_bps, ChdHeader, CodecType, Flags, SHA1_BYTES};
 use crate::map::{
-    ChdMap, CompressedEntryProof, LegacyEntryType, MapEntry, UncompressedEntryProof,
-    V5CompressionType,
+    ChdMap, CodecStats, CompressedEntryProof, CompressionStats, HunkCompression, LegacyEntryType,
+    MapEntry, UncompressedEntryProof, V5CompressionType,
 };
 
 #[cfg(feature = "unstable_lending_iterators")]
 use crate::iter::{HunkIter, MetadataIter};
 
-use crate::metadata::MetadataRefIter;
-use byteorder::{BigEndian, WriteBytesExt};
-use crc::Crc;
-use num_traits::ToPrimitive;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use crate::make_tag;
+use crate::metadata::{ChdMetadata, ChdMetadataTag, KnownMetadata, MetadataRef, MetadataRefIter};
+use crate::read::ChdHunkBufReader;
+use crate::readahead::{read_fully, ReadAhead};
+use crate::verify::{
+    ChdDigests, MetadataHasher, SubcodeChecker, SubcodeReport, TrackHashes, VerifyReport,
+};
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use num_traits::{FromPrimitive, ToPrimitive};
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+// The number of decompressed hunks kept around for ranged reads.
+const DEFAULT_HUNK_CACHE_SIZE: usize = 1;
+
+// The CRC32 used by Redump for the hashes of track files.
+const TRACK_CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// The mode to open a CHD file with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OpenMode {
+    /// The CHD file can only be read from.
+    ReadOnly,
+    /// The CHD file can be read from and written to.
+    ReadWrite,
+}
+
+/// The kind of media stored in a CHD file, as indicated by its metadata.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChdKind {
+    /// A CD-ROM, with CD-ROM track metadata.
+    CdRom,
+    /// A GD-ROM, with GD-ROM track metadata.
+    GdRom,
+    /// A DVD, with DVD metadata.
+    Dvd,
+    /// A hard disk, with hard disk geometry metadata.
+    HardDisk,
+    /// A video such as a LaserDisc, with A/V metadata.
+    AudioVideo,
+    /// The metadata does not indicate the kind of media.
+    Unknown,
+}
 
 /// A CHD (MAME Compressed Hunks of Data) file.
+///
+/// All codecs are `Send`, so a `ChdFile` can be moved to another thread if its stream is `Send`.
 pub struct ChdFile<F: Read + Seek> {
     file: F,
-    header: ChdHeader,
+    header: Arc<ChdHeader>,
     // feature(generic_associated_types) to be generic over all possible parents of G: Read+Seek?
     parent: Option<Box<ChdFile<F>>>,
-    map: ChdMap,
+    map: Arc<ChdMap>,
     codecs: Vec<Box<dyn CompressionCodec>>,
+    cmp_buf: Vec<u8>,
+    cache: HunkCache,
+    sector_size: Option<u32>,
+    toc: Option<Arc<CdToc>>,
+    layout: Option<Arc<Vec<TrackPlacement>>>,
+    readahead: ReadAhead,
+    mode: OpenMode,
+    strict: bool,
+}
+
+/// Returns an upper bound on the length of the stored data of any hunk of a CHD file with the
+/// given header, which is at least the hunk size for uncompressed hunks.
+fn max_compressed_size(header: &ChdHeader) -> usize {
+    let hunk_size = header.hunk_size();
+    header
+        .codec_types()
+        .iter()
+        .map(|codec| codec.max_compressed_size(hunk_size))
+        .fold(hunk_size as usize, usize::max)
+}
+
+/// Checks that a CHD file with the given header can be opened with the given mode.
+fn check_mode(header: &ChdHeader, mode: OpenMode) -> Result<()> {
+    let writable = match header.flags() {
+        Some(flags) => flags & Flags::IsWritable as u32 != 0,
+        None => !header.is_compressed(),
+    };
+    if mode == OpenMode::ReadWrite && !writable {
+        return Err(ChdError::ReadOnly);
+    }
+    Ok(())
+}
+
+/// Returns the codec that compressed hunks of a V5 CHD file with the given codec index use,
+/// which is the codec in that slot of the compressors declared in the header.
+///
+/// Hunks that refer to an empty slot are invalid, and return `ChdError::InvalidData`.
+fn slot_codec(header: &ChdHeader, slot: usize) -> Result<CodecType> {
+    let tag = match header {
+        ChdHeader::V5Header(header) => header.compression.get(slot).copied(),
+        _ => None,
+    };
+    match tag.and_then(CodecType::from_u32) {
+        Some(CodecType::None) => Err(ChdError::InvalidData),
+        Some(codec) => Ok(codec),
+        None => Err(ChdError::UnsupportedFormat),
+    }
 }
 
 impl<F: Read + Seek> ChdFile<F> {
     /// Open a CHD file from a `Read + Seek` stream. Optionally provide a parent of the same stream
     /// type.
     ///
-    /// The CHD header and hunk map are read and validated immediately.
-    pub fn open(mut file: F, parent: Option<Box<ChdFile<F>>>) -> Result<ChdFile<F>> {
+    /// The CHD header and hunk map are read and validated immediately. If the header declares
+    /// a compression codec that is not supported, returns `ChdError::UnsupportedCodec` before
+    /// any hunk data is read. If the stream is too short to hold the hunk map or all the hunk
+    /// data referenced by the map, returns `ChdError::TruncatedFile`.
+    ///
+    /// The CHD file is opened read-only.
+    pub fn open(file: F, parent: Option<Box<ChdFile<F>>>) -> Result<ChdFile<F>> {
+        ChdFile::open_with_mode(file, parent, OpenMode::ReadOnly)
+    }
+
+    /// Open a CHD file from a stream with the given mode, in the same way as
+    /// [`open`](ChdFile::open).
+    ///
+    /// Only uncompressed CHD files, and V1-4 CHD files that are flagged as writable, can be
+    /// opened read-write. Otherwise, opening a CHD file read-write returns `ChdError::ReadOnly`.
+    pub fn open_with_mode(
+        mut file: F,
+        parent: Option<Box<ChdFile<F>>>,
+        mode: OpenMode,
+    ) -> Result<ChdFile<F>> {
         let header = ChdHeader::try_read_header(&mut file)?;
-        // No point in checking writable because traits are read only.
-        // In the future if we want to support a Write feature, will need to ensure writable.
+        check_mode(&header, mode)?;
 
         // Make sure we have a parent if we have one
         if parent.is_none() && header.has_parent() {
@@ -43,21 +156,131 @@ impl<F: Read + Seek> ChdFile<F> {
 
         let map = ChdMap::try_read_map(&header, &mut file)?;
         let codecs = header.create_compression_codecs()?;
+        let cmp_buf = Vec::with_capacity(max_compressed_size(&header));
 
         Ok(ChdFile {
             file,
-            header,
+            header: Arc::new(header),
             parent,
-            map,
+            map: Arc::new(map),
             codecs,
+            cmp_buf,
+            cache: HunkCache::new(DEFAULT_HUNK_CACHE_SIZE),
+            sector_size: None,
+            toc: None,
+            layout: None,
+            readahead: ReadAhead::new(),
+            mode,
+            strict: false,
         })
     }
 
+    /// Replaces the stream of this CHD file with a new stream of the same CHD file, such as
+    /// after the file was replaced on disk, and reads the header and hunk map again.
+    ///
+    /// The new file must have the same hunk size, unit size and logical size as the current
+    /// file, otherwise `ChdError::InvalidFile` is returned and the current stream is kept.
+    /// The parent, open mode and all options such as the cache size are kept, and the hunk
+    /// cache is cleared.
+    pub fn reopen(&mut self, mut file: F) -> Result<()> {
+        let header = ChdHeader::try_read_header(&mut file)?;
+        if header.hunk_size() != self.header.hunk_size()
+            || header.unit_bytes() != self.header.unit_bytes()
+            || header.logical_bytes() != self.header.logical_bytes()
+        {
+            return Err(ChdError::InvalidFile);
+        }
+        check_mode(&header, self.mode)?;
+        if self.parent.is_none() && header.has_parent() {
+            return Err(ChdError::RequiresParent);
+        }
+
+        let map = ChdMap::try_read_map(&header, &mut file)?;
+        let codecs = header.create_compression_codecs()?;
+
+        self.file = file;
+        self.header = Arc::new(header);
+        self.map = Arc::new(map);
+        self.codecs = codecs;
+        self.cache.clear();
+        self.readahead.clear();
+        self.sector_size = None;
+        self.toc = None;
+        self.layout = None;
+        Ok(())
+    }
+
+    /// Creates another `ChdFile` for the same CHD file that reads from a new stream, such as
+    /// another handle to the same file, without reading the header and hunk map again.
+    ///
+    /// The header and hunk map are shared with this `ChdFile`, while the new `ChdFile` has its
+    /// own codecs and an empty hunk cache, so that each can be used independently, for example
+    /// by a different worker thread. The open mode and all options such as the cache size are
+    /// kept. Since the stream of the parent can not be cloned either, the parent must be
+    /// provided again if the CHD file requires a parent, otherwise `ChdError::RequiresParent`
+    /// is returned.
+    ///
+    /// The stream must be of the same CHD file. This is not checked, and reading from a stream
+    /// of a different file returns invalid data or errors.
+    pub fn clone_config(&self, file: F, parent: Option<Box<ChdFile<F>>>) -> Result<ChdFile<F>> {
+        if parent.is_none() && self.header.has_parent() {
+            return Err(ChdError::RequiresParent);
+        }
+        let mut readahead = ReadAhead::new();
+        readahead.set_hunks(self.readahead.hunks());
+        Ok(ChdFile {
+            file,
+            header: Arc::clone(&self.header),
+            parent,
+            map: Arc::clone(&self.map),
+            codecs: self.header.create_compression_codecs()?,
+            cmp_buf: Vec::with_capacity(self.cmp_buf.capacity()),
+            cache: self.cache.new_like(),
+            sector_size: self.sector_size,
+            toc: self.toc.clone(),
+            layout: self.layout.clone(),
+            readahead,
+            mode: self.mode,
+            strict: self.strict,
+        })
+    }
+
+    /// Returns whether this CHD file was opened read-write.
+    pub fn is_writable(&self) -> bool {
+        self.mode == OpenMode::ReadWrite
+    }
+
     /// Returns a reference to the CHD header for this CHD file.
     pub fn header(&self) -> &ChdHeader {
         &self.header
     }
 
+    /// Returns the version number of this CHD file, from 1 to 5.
+    ///
+    /// Operations that are not supported by the version of this CHD file, such as verifying
+    /// the SHA1 hashes of a V1-2 CHD file or writing hunks of a V1-4 CHD file, return
+    /// `ChdError::UnsupportedForVersion`.
+    pub fn version(&self) -> u32 {
+        self.header.version()
+    }
+
+    /// Returns the compression codecs declared in the header of this CHD file.
+    ///
+    /// These are the codecs that hunks of this CHD file may be compressed with, not the codec
+    /// that any particular hunk is compressed with.
+    pub fn codec_types(&self) -> Vec<CodecType> {
+        self.header.codec_types()
+    }
+
+    /// Returns an upper bound on the length of the stored data of any hunk of this CHD file,
+    /// given the compression codecs declared in the header.
+    ///
+    /// A buffer of this length can hold the data of any hunk read with
+    /// [`read_compressed_hunk`](ChdFile::read_compressed_hunk).
+    pub fn max_compressed_size(&self) -> usize {
+        max_compressed_size(&self.header)
+    }
+
     /// Returns an iterator over references to metadata entries for this CHD file.
     ///
     /// The contents of each metadata entry are lazily read.
@@ -70,6 +293,60 @@ impl<F: Read + Seek> ChdFile<F> {
         }
     }
 
+    /// Returns an iterator over references to the metadata entries for this CHD file with the
+    /// given tag, in index order.
+    ///
+    /// The metadata section is walked lazily as the iterator is advanced, and the contents of
+    /// each metadata entry are not read.
+    pub fn metadata_by_tag(&mut self, tag: [u8; 4]) -> impl Iterator<Item = MetadataRef> + '_ {
+        let tag = make_tag(&tag);
+        self.metadata_refs()
+            .filter(move |metadata| metadata.metatag() == tag)
+    }
+
+    /// Reads the metadata entry of this CHD file with the given tag and index among the entries
+    /// with that tag, like `chd_get_metadata` in libchdr.
+    ///
+    /// Entries with the same tag are counted regardless of their flags.
+    /// If there is no such entry, returns `ChdError::MetadataNotFound`.
+    pub fn metadata_by_tag_index(&mut self, tag: [u8; 4], index: u32) -> Result<ChdMetadata> {
+        let metadata = self
+            .metadata_by_tag(tag)
+            .find(|metadata| metadata.index() == index)
+            .ok_or(ChdError::MetadataNotFound(tag))?;
+        metadata.read(&mut self.file)
+    }
+
+    /// Reads all metadata entries of this CHD file into pairs of their tag and contents
+    /// in the human-readable form printed by `chdman info`, in the order they are stored.
+    ///
+    /// See [`ChdMetadata::to_text`] for how the contents of each entry are rendered.
+    pub fn metadata_text(&mut self) -> Result<Vec<(String, String)>> {
+        Ok(self
+            .metadata_refs()
+            .try_into_vec()?
+            .iter()
+            .map(|metadata| {
+                let tag = String::from_utf8_lossy(&metadata.metatag.to_be_bytes()).into_owned();
+                (tag, metadata.to_text())
+            })
+            .collect())
+    }
+
+    /// Reads the string recording the tool and version that created this CHD file from its
+    /// `CRTR` metadata entry, such as `chdman 0.262`.
+    ///
+    /// MAME does not define a standard creator metadata tag, so most CHD files made by `chdman`
+    /// do not have this entry. Returns `None` if there is no such entry or it can not be read.
+    pub fn creator_info(&mut self) -> Option<String> {
+        let metadata = self.metadata_by_tag_index(*b"CRTR", 0).ok()?;
+        let text = metadata
+            .value
+            .strip_suffix(b"\0")
+            .unwrap_or(&metadata.value);
+        Some(String::from_utf8_lossy(text).into_owned())
+    }
+
     #[cfg(feature = "unstable_lending_iterators")]
     #[cfg_attr(docsrs, doc(cfg(unstable_lending_iterators)))]
     /// Returns an iterator over metadata entries for this CHD file.
@@ -79,6 +356,357 @@ impl<F: Read + Seek> ChdFile<F> {
         MetadataIter::new(self.metadata_refs())
     }
 
+    /// Reads the table of contents of this CHD file from its CD-ROM or GD-ROM track metadata.
+    ///
+    /// The table of contents is only read from the metadata once, and kept until the CHD file
+    /// is [reopened](ChdFile::reopen).
+    ///
+    /// If this CHD file does not contain track metadata, returns `ChdError::MetadataNotFound`.
+    pub fn cd_toc(&mut self) -> Result<CdToc> {
+        Ok(CdToc::clone(&*self.toc()?))
+    }
+
+    /// Returns the cached table of contents of this CHD file, reading it from the track
+    /// metadata if it was not read yet.
+    fn toc(&mut self) -> Result<Arc<CdToc>> {
+        if let Some(toc) = &self.toc {
+            return Ok(Arc::clone(toc));
+        }
+        let metadata_refs: Vec<_> = self
+            .metadata_refs()
+            .filter(|metadata| KnownMetadata::is_cdrom(metadata.metatag()))
+            .collect();
+        let metadata = metadata_refs
+            .iter()
+            .map(|metadata| metadata.read(&mut self.file))
+            .collect::<Result<Vec<_>>>()?;
+        let toc = Arc::new(CdToc::from_metadata(
+            metadata
+                .iter()
+                .map(|metadata| (metadata.metatag, metadata.value.as_slice())),
+        )?);
+        self.toc = Some(Arc::clone(&toc));
+        Ok(toc)
+    }
+
+    /// Returns the cached track layout of this CHD file, computing it from the table of
+    /// contents if it was not computed yet.
+    fn layout(&mut self) -> Result<Arc<Vec<TrackPlacement>>> {
+        if let Some(layout) = &self.layout {
+            return Ok(Arc::clone(layout));
+        }
+        let layout = Arc::new(track_layout(&*self.toc()?));
+        self.layout = Some(Arc::clone(&layout));
+        Ok(layout)
+    }
+
+    /// Returns whether any track of this CHD file stores subcode data, as declared by its
+    /// CD-ROM or GD-ROM track metadata.
+    ///
+    /// Only the metadata is read. CHD files without track metadata, such as hard disks, never
+    /// have subcode data.
+    pub fn has_subcode(&mut self) -> bool {
+        self.toc()
+            .map(|toc| {
+                toc.tracks
+                    .iter()
+                    .any(|track| track.subcode_type != CdSubcodeType::None)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Returns the kind of media stored in this CHD file, as indicated by the tags of its
+    /// metadata entries.
+    ///
+    /// Only the metadata entry headers are read, along with the table of contents of CHD files
+    /// with track metadata. GD-ROM track metadata takes precedence over CD-ROM track metadata,
+    /// followed by DVD, hard disk and A/V metadata.
+    pub fn kind(&mut self) -> ChdKind {
+        if let Ok(toc) = self.toc() {
+            return if toc.gdrom {
+                ChdKind::GdRom
+            } else {
+                ChdKind::CdRom
+            };
+        }
+        let tags: Vec<_> = self
+            .metadata_refs()
+            .filter_map(|metadata| KnownMetadata::from_u32(metadata.metatag()))
+            .collect();
+        let has = |f: fn(&KnownMetadata) -> bool| tags.iter().any(f);
+        if has(|tag| matches!(tag, KnownMetadata::GdRomTrack | KnownMetadata::GdRomOld)) {
+            ChdKind::GdRom
+        } else if has(|tag| KnownMetadata::is_cdrom(tag.metatag())) {
+            ChdKind::CdRom
+        } else if has(|tag| matches!(tag, KnownMetadata::Dvd)) {
+            ChdKind::Dvd
+        } else if has(|tag| matches!(tag, KnownMetadata::HardDisk)) {
+            ChdKind::HardDisk
+        } else if has(|tag| {
+            matches!(
+                tag,
+                KnownMetadata::AudioVideo | KnownMetadata::AudioVideoLaserDisc
+            )
+        }) {
+            ChdKind::AudioVideo
+        } else {
+            ChdKind::Unknown
+        }
+    }
+
+    /// Returns the size in bytes of a sector of the media stored in this CHD file, according
+    /// to its [`kind`](ChdFile::kind).
+    ///
+    /// CD-ROM and GD-ROM sectors are 2448 bytes if any track stores subcode data and 2352
+    /// bytes otherwise, DVD sectors are 2048 bytes, and hard disk sectors are the size
+    /// declared by the hard disk geometry metadata. For other media, the unit size of the
+    /// header is returned.
+    ///
+    /// If the hard disk geometry does not declare a valid sector size, returns
+    /// `ChdError::MetadataParse`.
+    pub fn sector_size(&mut self) -> Result<u32> {
+        match self.kind() {
+            ChdKind::CdRom | ChdKind::GdRom if self.has_subcode() => Ok(CD_FRAME_SIZE),
+            ChdKind::CdRom | ChdKind::GdRom => Ok(CD_MAX_SECTOR_DATA),
+            ChdKind::Dvd => Ok(2048),
+            ChdKind::HardDisk => self.hd_sector_size(),
+            ChdKind::AudioVideo | ChdKind::Unknown => Ok(self.header.unit_bytes()),
+        }
+    }
+
+    /// Exports the tracks of a GD-ROM CHD file to the given directory as a GDI file named
+    /// `disc.gdi`, with the sector data of each track in a file named `trackNN.bin` for data
+    /// tracks or `trackNN.raw` for audio tracks.
+    ///
+    /// Audio data is written in little-endian byte order, and subcode data is not exported.
+    ///
+    /// If this CHD file is not a GD-ROM, returns `ChdError::NotSupported`.
+    pub fn export_gdi(&mut self, dir: &Path) -> Result<()> {
+        let toc = self.toc()?;
+        if !toc.gdrom {
+            return Err(ChdError::NotSupported);
+        }
+
+        let layout = self.layout()?;
+        let mut gdi = format!("{}\n", toc.tracks.len());
+        for (track, placement) in toc.tracks.iter().zip(layout.iter()) {
+            let (extension, gdi_type) = if track.track_type.is_audio() {
+                ("raw", 0)
+            } else {
+                ("bin", 4)
+            };
+            let file_name = format!("track{:02}.{}", track.number, extension);
+            let data_size = track.track_type.data_size();
+            gdi.push_str(&format!(
+                "{} {} {} {} {} 0\n",
+                track.number, placement.lba, gdi_type, data_size, file_name
+            ));
+
+            let mut out = BufWriter::new(File::create(dir.join(&file_name))?);
+            self.for_each_track_frame(track, placement, |frame| Ok(out.write_all(frame)?))?;
+            out.flush()?;
+        }
+
+        File::create(dir.join("disc.gdi"))?.write_all(gdi.as_bytes())?;
+        Ok(())
+    }
+
+    /// Computes the CRC32, MD5 and SHA1 hashes of the given track of a CD-ROM or GD-ROM CHD
+    /// file, in the layout used by Redump for the track files of a disc.
+    ///
+    /// The hashes cover the sector data of each frame of the track that is stored in the CHD
+    /// file, including a pregap stored in the file, without subcode data. Audio data is hashed
+    /// in little-endian byte order. For CHD files created from a Redump dump, the sector data
+    /// of data and audio tracks is 2352 bytes per frame, and the hashes match the Redump entry.
+    ///
+    /// If the track number does not exist, returns `ChdError::InvalidParameter`.
+    pub fn track_hashes(&mut self, track: u32) -> Result<TrackHashes> {
+        let toc = self.toc()?;
+        let layout = self.layout()?;
+        let (track, placement) = toc
+            .tracks
+            .iter()
+            .zip(layout.iter())
+            .find(|(t, _)| t.number == track)
+            .ok_or(ChdError::InvalidParameter)?;
+
+        let mut crc32 = TRACK_CRC32.digest();
+        let mut md5 = md5::Context::new();
+        let mut sha1 = Sha1::new();
+        self.for_each_track_frame(track, placement, |frame| {
+            crc32.update(frame);
+            md5.consume(frame);
+            sha1.update(frame);
+            Ok(())
+        })?;
+        Ok(TrackHashes {
+            crc32: crc32.finalize(),
+            md5: md5.compute().0,
+            sha1: sha1.finalize().into(),
+        })
+    }
+
+    /// Reads the sector data of each frame of the given track in order, passing it to `f`
+    /// with audio data in little-endian byte order.
+    fn for_each_track_frame<C: FnMut(&[u8]) -> Result<()>>(
+        &mut self,
+        track: &CdTrack,
+        placement: &TrackPlacement,
+        mut f: C,
+    ) -> Result<()> {
+        let mut frame = vec![0u8; track.track_type.data_size() as usize];
+        for frame_num in placement.chd_frames() {
+            self.read_frame_in(frame_num, &mut frame)?;
+            // CD audio is stored big-endian.
+            if track.track_type.is_audio() {
+                frame
+                    .chunks_exact_mut(2)
+                    .for_each(|sample| sample.swap(0, 1));
+            }
+            f(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the given audio track of a CD-ROM or GD-ROM CHD file to `out` as a WAV file
+    /// with 44.1 kHz 16-bit stereo PCM samples, with 588 samples in each frame of the track.
+    ///
+    /// If the track number does not exist, returns `ChdError::InvalidParameter`. If the track
+    /// is not an audio track, returns `ChdError::NotSupported`.
+    pub fn export_track_wav<W: Write>(&mut self, track: u32, out: &mut W) -> Result<()> {
+        let toc = self.toc()?;
+        let layout = self.layout()?;
+        let (track, placement) = toc
+            .tracks
+            .iter()
+            .zip(layout.iter())
+            .find(|(t, _)| t.number == track)
+            .ok_or(ChdError::InvalidParameter)?;
+        if !track.track_type.is_audio() {
+            return Err(ChdError::NotSupported);
+        }
+
+        let data_len = track
+            .frames
+            .checked_mul(CD_MAX_SECTOR_DATA)
+            .filter(|len| *len <= u32::MAX - 36)
+            .ok_or(ChdError::InvalidData)?;
+        out.write_all(b"RIFF")?;
+        out.write_u32::<LittleEndian>(36 + data_len)?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_u32::<LittleEndian>(16)?;
+        // PCM, 2 channels at 44100 Hz, 4 bytes per sample frame, 16 bits per sample.
+        out.write_u16::<LittleEndian>(1)?;
+        out.write_u16::<LittleEndian>(2)?;
+        out.write_u32::<LittleEndian>(44100)?;
+        out.write_u32::<LittleEndian>(44100 * 4)?;
+        out.write_u16::<LittleEndian>(4)?;
+        out.write_u16::<LittleEndian>(16)?;
+        out.write_all(b"data")?;
+        out.write_u32::<LittleEndian>(data_len)?;
+
+        let mut frame = [0u8; CD_MAX_SECTOR_DATA as usize];
+        for frame_num in placement.chd_frames() {
+            self.read_frame_in(frame_num, &mut frame)?;
+            // CD audio is stored big-endian.
+            frame
+                .chunks_exact_mut(2)
+                .for_each(|sample| sample.swap(0, 1));
+            out.write_all(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the CD frame at the given LBA of a CD-ROM or GD-ROM CHD file, with the 2352 bytes
+    /// of sector data followed by the 96 bytes of subcode data, exactly as stored in the
+    /// decompressed hunk.
+    ///
+    /// The LBA is mapped to a frame of the CHD file with the track layout, which skips the pad
+    /// frames between tracks.
+    ///
+    /// If the LBA does not lie within a frame stored in the file, such as in a pregap that is
+    /// not stored in the file, returns `ChdError::HunkOutOfRange`. If the track of the frame
+    /// does not store subcode data, returns `ChdError::NotSupported`, since the subcode data
+    /// is undefined. Use [`read_full_frame_zero_subcode`](ChdFile::read_full_frame_zero_subcode)
+    /// to accept zeroed subcode data instead.
+    pub fn read_full_frame(&mut self, lba: u32) -> Result<[u8; CD_FRAME_SIZE as usize]> {
+        match self.read_frame_at(lba)? {
+            (_, CdSubcodeType::None) => Err(ChdError::NotSupported),
+            (frame, _) => Ok(frame),
+        }
+    }
+
+    /// Reads the CD frame at the given LBA like [`read_full_frame`](ChdFile::read_full_frame),
+    /// but returns zeroes for the subcode data of tracks that do not store subcode data.
+    pub fn read_full_frame_zero_subcode(
+        &mut self,
+        lba: u32,
+    ) -> Result<[u8; CD_FRAME_SIZE as usize]> {
+        self.read_frame_at(lba).map(|(frame, _)| frame)
+    }
+
+    /// Reads the subcode data of the CD frame at the given LBA of a CD-ROM or GD-ROM CHD file,
+    /// with each of the P to W subchannels as 12 consecutive bytes.
+    ///
+    /// The subcode data is deinterleaved or returned as stored according to the subcode type
+    /// of the track, as described by [`deinterleave_subcode`](crate::cdrom::deinterleave_subcode). The LBA is mapped to a frame
+    /// in the same way as [`read_full_frame`](ChdFile::read_full_frame), and the same errors
+    /// are returned.
+    pub fn read_subcode(&mut self, lba: u32) -> Result<[u8; CD_MAX_SUBCODE_DATA as usize]> {
+        let (frame, subcode_type) = self.read_frame_at(lba)?;
+        if subcode_type == CdSubcodeType::None {
+            return Err(ChdError::NotSupported);
+        }
+        let subcode = frame[CD_MAX_SECTOR_DATA as usize..].try_into()?;
+        Ok(deinterleave_subcode(subcode, subcode_type))
+    }
+
+    /// Reads the CD frame at the given LBA with the subcode type of its track, zeroing the
+    /// subcode data if the track does not store subcode data.
+    fn read_frame_at(&mut self, lba: u32) -> Result<([u8; CD_FRAME_SIZE as usize], CdSubcodeType)> {
+        let toc = self.toc()?;
+        let layout = self.layout()?;
+        let (track, frame_num) = toc
+            .tracks
+            .iter()
+            .zip(layout.iter())
+            .find(|(_, placement)| (placement.lba..placement.lba + placement.frames).contains(&lba))
+            .map(|(track, placement)| (track, placement.chd_frame + lba - placement.lba))
+            .ok_or(ChdError::HunkOutOfRange)?;
+        let subcode_type = track.subcode_type;
+
+        let mut frame = [0u8; CD_FRAME_SIZE as usize];
+        // Frames stored without subcode data are only as long as the sector data.
+        let stored = std::cmp::min(self.cd_frame_bytes(), CD_FRAME_SIZE) as usize;
+        self.read_frame_in(frame_num, &mut frame[..stored])?;
+        if subcode_type == CdSubcodeType::None {
+            frame[CD_MAX_SECTOR_DATA as usize..].fill(0);
+        }
+        Ok((frame, subcode_type))
+    }
+
+    /// Returns the size in bytes of each CD frame stored in this CHD file.
+    ///
+    /// This is the unit size declared in the header if a unit can hold the sector data of a
+    /// frame and evenly divides the hunk size, and otherwise [`CD_FRAME_SIZE`], which is the
+    /// unit size of CD-ROM CHD files created by chdman.
+    fn cd_frame_bytes(&self) -> u32 {
+        let unit_bytes = self.header.unit_bytes();
+        if unit_bytes >= CD_MAX_SECTOR_DATA && self.header.hunk_size() % unit_bytes == 0 {
+            unit_bytes
+        } else {
+            CD_FRAME_SIZE
+        }
+    }
+
+    /// Reads the beginning of the given CD frame of this CHD file into `dest`.
+    pub(crate) fn read_frame_in(&mut self, frame_num: u32, dest: &mut [u8]) -> Result<()> {
+        let hunk_size = self.header.hunk_size() as u64;
+        let offset = frame_num as u64 * self.cd_frame_bytes() as u64;
+        let hunk_num = u32::try_from(offset / hunk_size).map_err(|_| ChdError::HunkOutOfRange)?;
+        self.read_hunk_range(hunk_num, (offset % hunk_size) as usize, dest)
+    }
+
     /// Returns the hunk map of this CHD File.
     pub fn map(&self) -> &ChdMap {
         &self.map
@@ -98,12 +726,584 @@ impl<F: Read + Seek> ChdFile<F> {
         })
     }
 
+    /// Decompresses the given hunk and returns a reader over its decompressed contents,
+    /// which reaches the end of the stream at the [logical length](ChdFile::hunk_logical_len)
+    /// of the hunk.
+    ///
+    /// If the requested hunk is larger than the number of hunks in the CHD file,
+    /// returns `ChdError::HunkOutOfRange`.
+    pub fn hunk_reader(&mut self, hunk_num: u32) -> Result<ChdHunkBufReader> {
+        let logical_len = self.hunk_logical_len(hunk_num)?;
+        let mut buf = self.get_hunksized_buffer();
+        let mut cmp_buf = std::mem::take(&mut self.cmp_buf);
+        let res = self
+            .hunk(hunk_num)
+            .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, &mut buf));
+        self.cmp_buf = cmp_buf;
+        res?;
+        buf.truncate(logical_len);
+        Ok(ChdHunkBufReader::from_buffer(buf))
+    }
+
+    /// Reads the bytes of the given hunk starting at `offset` into `dest`, filling `dest`
+    /// completely.
+    ///
+    /// The entire hunk is still decompressed, but the decompressed hunk is cached so that
+    /// repeated ranged reads within the same hunk do not decompress the hunk again.
+    ///
+    /// If `offset + dest.len()` is larger than the hunk size, returns `ChdError::InvalidParameter`.
+    pub fn read_hunk_range(&mut self, hunk_num: u32, offset: usize, dest: &mut [u8]) -> Result<()> {
+        let hunk_size = self.header.hunk_size() as usize;
+        match offset.checked_add(dest.len()) {
+            Some(end) if end <= hunk_size => {}
+            _ => return Err(ChdError::InvalidParameter),
+        }
+
+        let hunk = self.cached_hunk(hunk_num)?;
+        dest.copy_from_slice(&hunk[offset..][..dest.len()]);
+        Ok(())
+    }
+
+    /// Returns the index of the hunk that holds the byte at the given offset of the logical
+    /// image of this CHD file, and the offset of the byte within the hunk.
+    ///
+    /// If the offset is not less than the logical size of the CHD file, returns
+    /// `ChdError::InvalidParameter`.
+    pub fn locate(&self, logical_offset: u64) -> Result<(u32, usize)> {
+        if logical_offset >= self.header.logical_bytes() {
+            return Err(ChdError::InvalidParameter);
+        }
+        let hunk_size = self.header.hunk_size() as u64;
+        let hunk_num =
+            u32::try_from(logical_offset / hunk_size).map_err(|_| ChdError::InvalidParameter)?;
+        Ok((hunk_num, (logical_offset % hunk_size) as usize))
+    }
+
+    /// Reads the bytes of the logical image of this CHD file starting at `offset` into `dest`,
+    /// filling `dest` completely.
+    ///
+    /// Only the hunks spanning the range are decompressed, through the same cache as
+    /// [`read_hunk_range`](ChdFile::read_hunk_range).
+    ///
+    /// If `offset + dest.len()` is larger than the logical size of the CHD file, returns
+    /// `ChdError::InvalidParameter`.
+    pub fn read_bytes(&mut self, offset: u64, dest: &mut [u8]) -> Result<()> {
+        match offset.checked_add(dest.len() as u64) {
+            Some(end) if end <= self.header.logical_bytes() => {}
+            _ => return Err(ChdError::InvalidParameter),
+        }
+
+        let hunk_size = self.header.hunk_size() as usize;
+        let mut pos = offset;
+        let mut dest = dest;
+        while !dest.is_empty() {
+            let (hunk_num, in_hunk) = self.locate(pos)?;
+            let len = dest.len().min(hunk_size - in_hunk);
+            let (hunk_dest, rest) = dest.split_at_mut(len);
+            self.read_hunk_range(hunk_num, in_hunk, hunk_dest)?;
+            dest = rest;
+            pos += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Reads the data of the given hunk as it is stored in the file without decompressing it,
+    /// and returns how the hunk is stored.
+    ///
+    /// For compressed and uncompressed hunks, the stored data is returned, which may be shorter
+    /// than the hunk size for an uncompressed final hunk. For mini hunks, the repeated value is
+    /// returned. Hunks that are copies of another hunk or of the parent, and hunks that are not
+    /// stored in the file, are reported with no data.
+    ///
+    /// If the requested hunk is larger than the number of hunks in the CHD file,
+    /// returns `ChdError::HunkOutOfRange`.
+    pub fn read_compressed_hunk(&mut self, index: u32) -> Result<(HunkCompression, Vec<u8>)> {
+        let compression = self.hunk_compression(index)?;
+        let entry = self
+            .map
+            .get_entry(index as usize)
+            .ok_or(ChdError::HunkOutOfRange)?;
+        if let (HunkCompression::Mini, MapEntry::LegacyEntry(entry)) = (compression, &entry) {
+            return Ok((compression, entry.block_offset().to_be_bytes().to_vec()));
+        }
+
+        let extent = entry.stored_extent();
+        let mut data = Vec::new();
+        if let Some((offset, length)) = extent {
+            data.resize(length as usize, 0);
+            let read = self.read_stored(index, offset, &mut data)?;
+            // Only an uncompressed final hunk may be stored short of its length.
+            if read < data.len() && compression != HunkCompression::Uncompressed {
+                return Err(ChdError::ReadError);
+            }
+            data.truncate(read);
+        }
+        Ok((compression, data))
+    }
+
+    /// Collects statistics about how the hunks of this CHD file are stored from the hunk map.
+    ///
+    /// Only compressed hunks are counted towards the statistics of a codec. Uncompressed hunks,
+    /// copies of other hunks and hunks that are not stored in the file are only counted.
+    pub fn compression_stats(&self) -> Result<CompressionStats> {
+        let mut stats = CompressionStats::default();
+        for index in 0..self.header.hunk_count() {
+            match self.hunk_compression(index)? {
+                HunkCompression::Compressed(codec) => {
+                    let compressed_bytes = self
+                        .map
+                        .get_entry(index as usize)
+                        .and_then(|entry| entry.stored_extent())
+                        .map_or(0, |(_, length)| length as u64);
+                    let hunk_bytes = self.logical_len_of(index) as u64;
+
+                    let position = stats.codecs.iter().position(|c| c.codec == codec);
+                    let codec_stats = match position {
+                        Some(position) => &mut stats.codecs[position],
+                        None => {
+                            stats.codecs.push(CodecStats {
+                                codec,
+                                hunks: 0,
+                                logical_bytes: 0,
+                                compressed_bytes: 0,
+                            });
+                            stats.codecs.last_mut().unwrap()
+                        }
+                    };
+                    codec_stats.hunks += 1;
+                    codec_stats.logical_bytes += hunk_bytes;
+                    codec_stats.compressed_bytes += compressed_bytes;
+                }
+                HunkCompression::Uncompressed | HunkCompression::Mini => {
+                    stats.uncompressed_hunks += 1
+                }
+                HunkCompression::SelfHunk(_) | HunkCompression::Parent(_) => {
+                    stats.copied_hunks += 1
+                }
+                HunkCompression::Zero => stats.zero_hunks += 1,
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Returns whether the given hunk holds any data, according to its map entry only.
+    ///
+    /// Hunks that are not stored in the file, are stored compressed without any data, or are
+    /// mini hunks of zeroes are filled with zeroes and are not populated. Copies of other hunks
+    /// and of the parent are always considered populated. This allows skipping the zero hunks
+    /// of sparse CHD files without decompressing them.
+    ///
+    /// If the requested hunk is larger than the number of hunks in the CHD file,
+    /// returns `ChdError::HunkOutOfRange`.
+    pub fn hunk_is_populated(&self, index: u32) -> Result<bool> {
+        Ok(match self.hunk_compression(index)? {
+            HunkCompression::Zero => false,
+            HunkCompression::Mini => match self.map.get_entry(index as usize) {
+                Some(MapEntry::LegacyEntry(entry)) => entry.block_offset() != 0,
+                _ => true,
+            },
+            _ => true,
+        })
+    }
+
+    /// Returns how the given hunk is stored according to its map entry.
+    fn hunk_compression(&self, index: u32) -> Result<HunkCompression> {
+        let entry = self
+            .map
+            .get_entry(index as usize)
+            .ok_or(ChdError::HunkOutOfRange)?;
+        Ok(match entry {
+            MapEntry::V5Compressed(entry) => match entry.hunk_type()? {
+                V5CompressionType::CompressionType0
+                | V5CompressionType::CompressionType1
+                | V5CompressionType::CompressionType2
+                | V5CompressionType::CompressionType3
+                    if entry.block_size()? == 0 =>
+                {
+                    HunkCompression::Zero
+                }
+                comptype @ V5CompressionType::CompressionType0
+                | comptype @ V5CompressionType::CompressionType1
+                | comptype @ V5CompressionType::CompressionType2
+                | comptype @ V5CompressionType::CompressionType3 => {
+                    HunkCompression::Compressed(slot_codec(&self.header, comptype as usize)?)
+                }
+                V5CompressionType::CompressionNone => HunkCompression::Uncompressed,
+                V5CompressionType::CompressionSelf => {
+                    HunkCompression::SelfHunk(entry.block_offset()? as u32)
+                }
+                V5CompressionType::CompressionParent => {
+                    HunkCompression::Parent(entry.block_offset()?)
+                }
+                _ => return Err(ChdError::UnsupportedFormat),
+            },
+            MapEntry::V5Uncompressed(entry) => match entry.block_offset()? {
+                0 if self.header.has_parent() => {
+                    let units_in_hunk = self.header.hunk_size() / self.header.unit_bytes();
+                    HunkCompression::Parent(index as u64 * units_in_hunk as u64)
+                }
+                0 => HunkCompression::Zero,
+                _ => HunkCompression::Uncompressed,
+            },
+            MapEntry::LegacyEntry(entry) => match entry.hunk_type()? {
+                LegacyEntryType::Compressed if entry.block_size() == 0 => HunkCompression::Zero,
+                LegacyEntryType::Compressed => HunkCompression::Compressed(
+                    *self
+                        .header
+                        .codec_types()
+                        .first()
+                        .ok_or(ChdError::UnsupportedFormat)?,
+                ),
+                LegacyEntryType::Uncompressed => HunkCompression::Uncompressed,
+                LegacyEntryType::Mini => HunkCompression::Mini,
+                LegacyEntryType::SelfHunk => HunkCompression::SelfHunk(entry.block_offset() as u32),
+                LegacyEntryType::ParentHunk => HunkCompression::Parent(entry.block_offset()),
+                LegacyEntryType::ExternalCompressed => return Err(ChdError::UnsupportedFormat),
+                LegacyEntryType::Invalid => return Err(ChdError::InvalidData),
+            },
+        })
+    }
+
+    /// Decompresses the given hunk into a buffer owned by the caller, which must be exactly
+    /// the hunk size of this CHD file.
+    ///
+    /// The compressed data is read into a scratch buffer kept by the `ChdFile`, but the hunk
+    /// cache used by [`read_hunk_range`](ChdFile::read_hunk_range) is neither used nor updated.
+    ///
+    /// If the length of `buf` is not the hunk size, returns `ChdError::InvalidParameter`.
+    pub fn decode_hunk_into(&mut self, index: u32, buf: &mut [u8]) -> Result<()> {
+        if buf.len() != self.header.hunk_size() as usize {
+            return Err(ChdError::InvalidParameter);
+        }
+        let mut cmp_buf = std::mem::take(&mut self.cmp_buf);
+        let res = self
+            .hunk(index)
+            .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, buf));
+        self.cmp_buf = cmp_buf;
+        res.map(|_| ())
+    }
+
+    /// Reads the sector at the given LBA of a hard disk CHD file into `out`, which must be
+    /// exactly the size of a sector as declared by the hard disk geometry metadata.
+    ///
+    /// If this CHD file does not contain hard disk geometry metadata, returns
+    /// `ChdError::MetadataNotFound`, and if the geometry does not declare a valid sector size,
+    /// returns `ChdError::MetadataParse`. If the sector is past the end of the disk, returns
+    /// `ChdError::HunkOutOfRange`. If `out` is not the size of a sector, or the byte offset of
+    /// the sector overflows, returns `ChdError::InvalidParameter`.
+    pub fn read_hd_sector(&mut self, lba: u64, out: &mut [u8]) -> Result<()> {
+        let sector_size = self.hd_sector_size()? as u64;
+        if out.len() as u64 != sector_size {
+            return Err(ChdError::InvalidParameter);
+        }
+        let offset = lba
+            .checked_mul(sector_size)
+            .ok_or(ChdError::InvalidParameter)?;
+        let end = offset
+            .checked_add(sector_size)
+            .ok_or(ChdError::InvalidParameter)?;
+        if end > self.header.logical_bytes() {
+            return Err(ChdError::HunkOutOfRange);
+        }
+
+        // The hunk size should be a multiple of the sector size, but a sector that straddles
+        // hunks in a malformed file is read from both hunks.
+        self.read_bytes(offset, out)
+    }
+
+    /// Returns the raw ATA IDENTIFY data of a hard disk CHD file from its `IDNT` metadata,
+    /// or `None` if this CHD file does not contain identify data.
+    ///
+    /// If the identify data is not exactly 512 bytes long, returns `ChdError::InvalidMetadataSize`.
+    pub fn ata_identify(&mut self) -> Result<Option<[u8; 512]>> {
+        let metadata = match self.metadata_by_tag(*b"IDNT").next() {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        };
+        let value = metadata.read(&mut self.file)?.value;
+        let identify = value
+            .try_into()
+            .map_err(|_| ChdError::InvalidMetadataSize)?;
+        Ok(Some(identify))
+    }
+
+    /// Returns the sector size declared by the hard disk geometry metadata of this CHD file.
+    fn hd_sector_size(&mut self) -> Result<u32> {
+        if let Some(sector_size) = self.sector_size {
+            return Ok(sector_size);
+        }
+        let metadata = self
+            .metadata_by_tag(*b"GDDD")
+            .next()
+            .ok_or(ChdError::MetadataNotFound(*b"GDDD"))?;
+        let sector_size = hard_disk_bps(&metadata.read(&mut self.file)?.value)
+            .filter(|&bps| bps > 0)
+            .ok_or_else(|| ChdError::MetadataParse {
+                tag: *b"GDDD",
+                detail: String::from("missing or invalid BPS field"),
+            })?;
+        self.sector_size = Some(sector_size);
+        Ok(sector_size)
+    }
+
+    /// Sets the number of decompressed hunks kept in the hunk cache used by ranged reads such
+    /// as [`read_hunk_range`](ChdFile::read_hunk_range). By default, one hunk is cached.
+    ///
+    /// The cache always holds at least one hunk. This replaces any limit set with
+    /// [`set_cache_bytes`](ChdFile::set_cache_bytes).
+    pub fn set_cache_size(&mut self, hunks: usize) {
+        self.cache.set_capacity(hunks);
+    }
+
+    /// Bounds the hunk cache by the total length in bytes of the decompressed hunks it holds
+    /// instead of by a number of hunks, evicting the least recently used hunks to stay
+    /// within `limit`.
+    ///
+    /// This gives a predictable memory footprint regardless of the hunk size of the CHD file.
+    /// A hunk that is larger than `limit` can still be read, and is then the only hunk held
+    /// in the cache until the next hunk is decompressed. This replaces any limit set with
+    /// [`set_cache_size`](ChdFile::set_cache_size).
+    pub fn set_cache_bytes(&mut self, limit: usize) {
+        self.cache.set_max_bytes(limit);
+    }
+
+    /// Returns the total length in bytes of the decompressed hunks held in the hunk cache.
+    pub fn cached_bytes(&self) -> usize {
+        self.cache.bytes()
+    }
+
+    /// Sets the number of hunks following the current hunk whose stored data is read ahead
+    /// when hunks are read in order. By default, no hunks are read ahead.
+    ///
+    /// Reading ahead combines the reads of the stored data of several hunks into a single
+    /// read of the stream, which reduces the cost of sequential access to streams where each
+    /// read has a high latency. Reads of hunks out of order do not read ahead. If reading
+    /// ahead fails, any error is returned when the hunk that could not be read is read.
+    pub fn set_readahead(&mut self, hunks: u32) {
+        self.readahead.set_hunks(hunks);
+    }
+
+    /// Sets whether compressed hunks must consume all of their compressed data when they are
+    /// decompressed. By default, any data that follows the compressed stream is ignored.
+    ///
+    /// In strict mode, decompressing a hunk that is compressed with a single stream, and that
+    /// is followed by unused bytes, returns `ChdError::TrailingData`. This does not apply to
+    /// CD-ROM codecs, where the subcode data follows the sector data.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Reads the data of the given hunk stored at the given offset of the file into `dest`,
+    /// returning the number of bytes read, which is only short at the end of the file.
+    pub(crate) fn read_stored(
+        &mut self,
+        hunk_num: u32,
+        offset: u64,
+        dest: &mut [u8],
+    ) -> Result<usize> {
+        let sequential = self.readahead.record(hunk_num);
+        if self.readahead.get(offset, dest.len()).is_none()
+            && sequential
+            && self.readahead.hunks() > 0
+        {
+            // Extend the read to the data of the following hunks stored after this hunk.
+            let hunk_size = self.header.hunk_size() as u64;
+            let limit = offset + (self.readahead.hunks() as u64 + 1) * hunk_size;
+            let last_hunk = std::cmp::min(
+                hunk_num.saturating_add(self.readahead.hunks()),
+                self.header.hunk_count().saturating_sub(1),
+            );
+            let mut end = offset + dest.len() as u64;
+            for next in hunk_num.saturating_add(1)..=last_hunk {
+                if let Some((next_offset, next_len)) = self
+                    .map
+                    .get_entry(next as usize)
+                    .and_then(|entry| entry.stored_extent())
+                {
+                    let next_end = next_offset + next_len as u64;
+                    if next_offset >= offset && next_end <= limit {
+                        end = std::cmp::max(end, next_end);
+                    }
+                }
+            }
+            // If reading ahead fails, the window is invalidated so that no stale data is
+            // returned, and the error is reported by the direct read below instead.
+            if self
+                .readahead
+                .fill(&mut self.file, offset, (end - offset) as usize)
+                .is_err()
+            {
+                self.readahead.clear();
+            }
+        }
+
+        if let Some(data) = self.readahead.get(offset, dest.len()) {
+            dest.copy_from_slice(data);
+            return Ok(dest.len());
+        }
+        self.file.seek(SeekFrom::Start(offset))?;
+        read_fully(&mut self.file, dest)
+    }
+
+    /// Returns the decompressed contents of the given hunk, decompressing it into
+    /// the hunk cache if it is not already cached.
+    pub(crate) fn cached_hunk(&mut self, hunk_num: u32) -> Result<&[u8]> {
+        if !self.cache.contains(hunk_num) {
+            let mut buf = self.cache.take_buffer(self.header.hunk_size() as usize);
+            let mut cmp_buf = std::mem::take(&mut self.cmp_buf);
+            let res = self
+                .hunk(hunk_num)
+                .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, &mut buf));
+            self.cmp_buf = cmp_buf;
+            res?;
+            self.cache.insert(hunk_num, buf);
+        }
+        self.cache.get(hunk_num).ok_or(ChdError::HunkOutOfRange)
+    }
+
+    /// Decompresses every hunk of this CHD file in order into a newly allocated buffer
+    /// with the logical size of the CHD file.
+    ///
+    /// The entire decompressed contents of the CHD file are held in memory, so this is only
+    /// suitable for small CHD files. For large images, the hunks should be read individually
+    /// with [`ChdHunk::read_hunk_in`](crate::ChdHunk::read_hunk_in) instead.
+    pub fn read_all(&mut self) -> Result<Vec<u8>> {
+        let logical_bytes =
+            usize::try_from(self.header.logical_bytes()).map_err(|_| ChdError::OutOfMemory)?;
+        let mut output = Vec::with_capacity(logical_bytes);
+        self.for_each_hunk(|data| {
+            output.extend_from_slice(data);
+            Ok(())
+        })?;
+        Ok(output)
+    }
+
+    /// Verifies the contents of this CHD file by decompressing every hunk, and computes the
+    /// SHA1 hashes of the raw data and of the raw data with the checksummed metadata in the
+    /// same way as MAME.
+    ///
+    /// Both hashes are computed from a single pass over the hunks. The overall hash only
+    /// depends on the raw data through its SHA1 hash, so the metadata is hashed separately
+    /// without reading the hunks again.
+    ///
+    /// Hunk checksums are only verified if the `verify_block_crc` feature is enabled.
+    pub fn verify(&mut self) -> Result<ChdDigests> {
+        self.verify_hunks(|_| {})
+    }
+
+    /// Verifies the contents of this CHD file like [`verify`](ChdFile::verify), and compares
+    /// the computed hashes against the hashes recorded in the header.
+    ///
+    /// V1-2 CHD files do not record SHA1 hashes, and return `ChdError::UnsupportedForVersion`.
+    pub fn verify_report(&mut self) -> Result<VerifyReport> {
+        let digests = self.verify()?;
+        digests
+            .compare(&self.header)
+            .ok_or(ChdError::UnsupportedForVersion {
+                version: self.version(),
+                feature: "SHA1 verification",
+            })
+    }
+
+    /// Verifies the contents of this CD-ROM CHD file like [`verify`](ChdFile::verify), and
+    /// also validates the Q subchannel CRC of every frame of the tracks with subcode data.
+    ///
+    /// If this CHD file does not contain track metadata, returns `ChdError::MetadataNotFound`.
+    /// If any track has subcode data but the `want_subcode` feature is not enabled, returns
+    /// `ChdError::UnsupportedFeature`, since the subcode data can not be decompressed.
+    pub fn verify_with_subcode(&mut self) -> Result<(ChdDigests, SubcodeReport)> {
+        let subcode_frames = self.toc()?.subcode_frames();
+        if !cfg!(feature = "want_subcode") && !subcode_frames.is_empty() {
+            return Err(ChdError::UnsupportedFeature("want_subcode"));
+        }
+        let mut checker = SubcodeChecker::new(subcode_frames, self.cd_frame_bytes());
+        let digests = self.verify_hunks(|data| checker.push(data))?;
+        Ok((digests, checker.finish()))
+    }
+
+    fn verify_hunks<C: FnMut(&[u8])>(&mut self, mut f: C) -> Result<ChdDigests> {
+        let mut raw_hasher = Sha1::new();
+        self.for_each_hunk(|data| {
+            raw_hasher.update(data);
+            f(data);
+            Ok(())
+        })?;
+        let raw_sha1: [u8; SHA1_BYTES] = raw_hasher.finalize().into();
+
+        let mut metadata_hasher = MetadataHasher::new();
+        let metadata_refs: Vec<_> = self.metadata_refs().collect();
+        for metadata_ref in metadata_refs {
+            metadata_hasher.push(&metadata_ref.read(&mut self.file)?);
+        }
+
+        Ok(ChdDigests {
+            raw_sha1,
+            sha1: metadata_hasher.finalize(&raw_sha1),
+        })
+    }
+
+    /// Decompresses every hunk of this CHD file in order and writes the raw data to `out`,
+    /// returning the number of bytes written.
+    ///
+    /// A single hunk-sized buffer is reused for every hunk, and the final hunk is truncated
+    /// to the logical size of the CHD file.
+    pub fn extract_to<W: Write>(&mut self, out: &mut W) -> Result<u64> {
+        let mut written = 0;
+        self.for_each_hunk(|data| {
+            out.write_all(data)?;
+            written += data.len() as u64;
+            Ok(())
+        })?;
+        Ok(written)
+    }
+
+    /// Decompresses every hunk of this CHD file in order, passing the contents of each hunk
+    /// that lie within the logical size of the CHD file to `f`.
+    fn for_each_hunk<C: FnMut(&[u8]) -> Result<()>>(&mut self, mut f: C) -> Result<()> {
+        let mut hunk_buf = self.get_hunksized_buffer();
+        let mut cmp_buf = std::mem::take(&mut self.cmp_buf);
+        let mut remaining = self.header.logical_bytes();
+
+        let res = (0..self.header.hunk_count()).try_for_each(|hunk_num| {
+            let mut hunk = self.hunk(hunk_num)?;
+            hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)?;
+            // The final hunk may extend past the logical size of the CHD.
+            let len = std::cmp::min(remaining, hunk_buf.len() as u64) as usize;
+            remaining -= len as u64;
+            f(&hunk_buf[..len])
+        });
+        self.cmp_buf = cmp_buf;
+        res
+    }
+
     /// Allocates a buffer with the same length as the hunk size of this CHD file.
     pub fn get_hunksized_buffer(&self) -> Vec<u8> {
         let hunk_size = self.header.hunk_size() as usize;
         vec![0u8; hunk_size]
     }
 
+    /// Returns the number of bytes of the given hunk that lie within the logical size of the
+    /// CHD file.
+    ///
+    /// This is the hunk size for every hunk except possibly the final hunk, which is truncated
+    /// if the logical size of the CHD file is not a multiple of the hunk size.
+    /// If the hunk index is out of range, returns `ChdError::HunkOutOfRange`.
+    pub fn hunk_logical_len(&self, index: u32) -> Result<usize> {
+        if index >= self.header.hunk_count() {
+            return Err(ChdError::HunkOutOfRange);
+        }
+        Ok(self.logical_len_of(index))
+    }
+
+    fn logical_len_of(&self, index: u32) -> usize {
+        let hunk_size = self.header.hunk_size() as u64;
+        let hunk_start = index as u64 * hunk_size;
+        self.header
+            .logical_bytes()
+            .saturating_sub(hunk_start)
+            .min(hunk_size) as usize
+    }
+
     #[cfg_attr(docsrs, doc(cfg(unstable_lending_iterators)))]
     #[cfg(feature = "unstable_lending_iterators")]
     /// Returns an iterator over the hunks of this CHD file.
@@ -122,6 +1322,100 @@ impl<F: Read + Seek> ChdFile<F> {
     }
 }
 
+impl<F: Read + Write + Seek> ChdFile<F> {
+    /// Replaces the contents of the given hunk of an uncompressed V5 CHD file with `data`,
+    /// which must be exactly the hunk size of the CHD file.
+    ///
+    /// Only hunks that are already stored in the file can be replaced. The hashes in the header
+    /// are not updated.
+    ///
+    /// If this CHD file was not opened read-write, returns `ChdError::ReadOnly`. If the
+    /// hunk is not stored in the file, returns `ChdError::NotSupported`. V1-4 CHD files return
+    /// `ChdError::UnsupportedForVersion`.
+    pub fn write_hunk(&mut self, hunk_num: u32, data: &[u8]) -> Result<()> {
+        if !self.is_writable() {
+            return Err(ChdError::ReadOnly);
+        }
+        if data.len() != self.header.hunk_size() as usize {
+            return Err(ChdError::InvalidParameter);
+        }
+        let offset = match self.map.get_entry(hunk_num as usize) {
+            None => return Err(ChdError::HunkOutOfRange),
+            Some(MapEntry::V5Uncompressed(entry)) => match entry.block_offset()? {
+                0 => return Err(ChdError::NotSupported),
+                offset => offset,
+            },
+            Some(MapEntry::LegacyEntry(_)) => {
+                return Err(ChdError::UnsupportedForVersion {
+                    version: self.version(),
+                    feature: "hunk writing",
+                })
+            }
+            Some(_) => return Err(ChdError::NotSupported),
+        };
+
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+        self.cache.remove(hunk_num);
+        self.readahead.clear();
+        Ok(())
+    }
+}
+
+impl<'a> ChdFile<Cursor<&'a [u8]>> {
+    /// Open a CHD file that is entirely held in memory, borrowing the provided bytes.
+    ///
+    /// CHD files that require a parent can not be opened with this function, and will
+    /// return `ChdError::RequiresParent`.
+    pub fn from_bytes(data: &'a [u8]) -> Result<ChdFile<Cursor<&'a [u8]>>> {
+        ChdFile::open(Cursor::new(data), None)
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[cfg_attr(docsrs, doc(cfg(mmap)))]
+impl ChdFile<Cursor<memmap2::Mmap>> {
+    /// Open the CHD file at the given path by memory-mapping it.
+    ///
+    /// The mapping is owned by the returned `ChdFile`, so that it outlives every read, and the
+    /// file is unmapped when the `ChdFile` is dropped. The file must not be modified while it
+    /// is mapped.
+    ///
+    /// CHD files that require a parent can not be opened with this function, and will
+    /// return `ChdError::RequiresParent`.
+    pub fn open_path_mmap(path: &Path) -> Result<ChdFile<Cursor<memmap2::Mmap>>> {
+        let file = File::open(path)?;
+        // SAFETY: The mapping is only read, and modifying the file while it is mapped is
+        // forbidden by the documentation of this function.
+        #[allow(unsafe_code)]
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        ChdFile::open(Cursor::new(map), None)
+    }
+}
+
+/// A readable and seekable stream, which allows CHD files to be opened from a reader whose
+/// type is only known at runtime.
+///
+/// This is implemented for every type that implements [`Read`](std::io::Read) and
+/// [`Seek`](std::io::Seek).
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+impl ChdFile<Box<dyn ReadSeek>> {
+    /// Open a CHD file from a boxed reader, erasing the type of the reader.
+    ///
+    /// This allows holding CHD files opened from different kinds of readers, such as files
+    /// and in-memory buffers, as the same type. The parent must also be opened from a boxed
+    /// reader.
+    pub fn open_boxed(
+        file: Box<dyn ReadSeek>,
+        parent: Option<Box<ChdFile<Box<dyn ReadSeek>>>>,
+    ) -> Result<ChdFile<Box<dyn ReadSeek>>> {
+        ChdFile::open(file, parent)
+    }
+}
+
 /// A reference to a compressed Hunk in a CHD file.
 pub struct ChdHunk<'a, F: Read + Seek> {
     inner: &'a mut ChdFile<F>,
@@ -140,8 +1434,7 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
 
         comp_buf.resize(length as usize, 0);
 
-        self.inner.file.seek(SeekFrom::Start(offset))?;
-        let read = self.inner.file.read(comp_buf)?;
+        let read = self.inner.read_stored(self.hunk_num, offset, comp_buf)?;
         if read != length as usize {
             return Err(ChdError::ReadError);
         }
@@ -159,8 +1452,14 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
         if dest.len() != length as usize {
             return Err(ChdError::InvalidParameter);
         }
-        self.inner.file.seek(SeekFrom::Start(offset))?;
-        let read = self.inner.file.read(dest)?;
+        let mut read = self.inner.read_stored(self.hunk_num, offset, dest)?;
+
+        // The final hunk may be stored with only enough data to fill the logical size
+        // of the CHD, and is zero-padded the rest of the way.
+        if read < dest.len() && read >= self.logical_len() {
+            dest[read..].fill(0);
+            read = dest.len();
+        }
         Ok(read)
     }
 
@@ -179,10 +1478,19 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
 
                 match entry.hunk_type()? {
                     LegacyEntryType::Compressed => {
+                        // A compressed hunk without any data holds only zeroes.
+                        if block_len == 0 {
+                            dest.fill(0);
+                            return Crc::<u32>::verify_block_checksum(block_crc, dest, dest.len());
+                        }
+
                         // buffer the compressed data
                         let proof = entry.prove_compressed()?;
                         self.read_compressed_in(proof, comp_buf)?;
                         let res = &self.inner.codecs[0].decompress(&comp_buf[..block_len], dest)?;
+                        if self.inner.strict && res.total_in() < block_len {
+                            return Err(ChdError::TrailingData);
+                        }
 
                         Crc::<u32>::verify_block_checksum(block_crc, dest, res.total_out())
                     }
@@ -228,7 +1536,12 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
         }
     }
 
-    fn read_hunk_v5(&mut self, comp_buf: &mut Vec<u8>, dest: &mut [u8]) -> Result<usize> {
+    fn read_hunk_v5(
+        &mut self,
+        comp_buf: &mut Vec<u8>,
+        dest: &mut [u8],
+        want_subcode: bool,
+    ) -> Result<usize> {
         let map_entry = self
             .inner
             .map()
@@ -249,12 +1562,59 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
                         // buffer the compressed data
                         let proof = entry.prove_compressed()?;
 
+                        // A compressed hunk without any data holds only zeroes, and is not
+                        // passed to the codec.
+                        if proof.block_size() == 0 {
+                            dest.fill(0);
+                            return Crc::<u16>::verify_block_checksum(block_crc, dest, dest.len());
+                        }
+
+                        // The codec index must refer to a codec declared in the header.
+                        let slot = comptype.to_usize().unwrap();
+                        let codec_type = slot_codec(&self.inner.header, slot)?;
                         self.read_compressed_in(proof, comp_buf)?;
 
-                        if let Some(codec) = self.inner.codecs.get_mut(comptype.to_usize().unwrap())
-                        {
-                            let res = codec.decompress(comp_buf, dest)?;
-                            Crc::<u16>::verify_block_checksum(block_crc, dest, res.total_out())
+                        // CD-ROM codecs always decompress whole hunks of frames.
+                        let logical_len = if codec_type.is_cd() {
+                            dest.len()
+                        } else {
+                            self.logical_len()
+                        };
+                        // CD-ROM codecs store the subcode data after the sector data, so only
+                        // single stream hunks can be checked for trailing data.
+                        let strict = self.inner.strict && !codec_type.is_cd();
+                        if let Some(codec) = self.inner.codecs.get_mut(slot) {
+                            let mut decompress = |input: &[u8], output: &mut [u8]| {
+                                if want_subcode {
+                                    codec.decompress(input, output)
+                                } else {
+                                    codec.decompress_without_subcode(input, output)
+                                }
+                            };
+                            let res = match decompress(comp_buf, dest) {
+                                // The final hunk may be compressed with only enough data to fill
+                                // the logical size of the CHD, and is zero-padded the rest of
+                                // the way.
+                                Err(ChdError::TruncatedInput) if logical_len < dest.len() => {
+                                    let res = decompress(comp_buf, &mut dest[..logical_len])?;
+                                    if strict && res.total_in() < comp_buf.len() {
+                                        return Err(ChdError::TrailingData);
+                                    }
+                                    dest[logical_len..].fill(0);
+                                    dest.len()
+                                }
+                                Ok(res) if strict && res.total_in() < comp_buf.len() => {
+                                    return Err(ChdError::TrailingData)
+                                }
+                                res => res?.total_out(),
+                            };
+                            // The hunk checksum includes the subcode data, which is only
+                            // skipped by CD-ROM codecs.
+                            if want_subcode || !codec_type.is_cd() {
+                                Crc::<u16>::verify_block_checksum(block_crc, dest, res)
+                            } else {
+                                Ok(res)
+                            }
                         } else {
                             Err(ChdError::UnsupportedFormat)
                         }
@@ -266,7 +1626,7 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
                     }
                     V5CompressionType::CompressionSelf => {
                         let mut self_hunk = self.inner.hunk(block_off as u32)?;
-                        let res = self_hunk.read_hunk_in(comp_buf, dest)?;
+                        let res = self_hunk.read_hunk(comp_buf, dest, want_subcode)?;
                         Ok(res)
                     }
                     V5CompressionType::CompressionParent => {
@@ -281,7 +1641,8 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
 
                                 let mut parent_hunk =
                                     parent.hunk(block_off as u32 / units_in_hunk)?;
-                                let res_1 = parent_hunk.read_hunk_in(comp_buf, &mut buf)?;
+                                let res_1 =
+                                    parent_hunk.read_hunk(comp_buf, &mut buf, want_subcode)?;
 
                                 if block_off % units_in_hunk as u64 == 0 {
                                     dest.copy_from_slice(&buf);
@@ -298,7 +1659,8 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
 
                                 let mut parent_hunk =
                                     parent.hunk((block_off as u32 / units_in_hunk) + 1)?;
-                                let _res_2 = parent_hunk.read_hunk_in(comp_buf, &mut buf)?;
+                                let _res_2 =
+                                    parent_hunk.read_hunk(comp_buf, &mut buf, want_subcode)?;
 
                                 dest[hunk_split..].copy_from_slice(
                                     &buf[..remainder_in_hunk
@@ -324,7 +1686,7 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
                     (0, true) => {
                         if let Some(parent) = self.inner.parent.as_deref_mut() {
                             let mut parent = parent.hunk(self.hunk_num)?;
-                            let res = parent.read_hunk_in(comp_buf, dest)?;
+                            let res = parent.read_hunk(comp_buf, dest, want_subcode)?;
                             Ok(res)
                         } else {
                             Err(ChdError::RequiresParent)
@@ -343,22 +1705,52 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
     }
 
     /// Decompresses the hunk into output, using the provided temporary buffer to hold the
-    /// compressed hunk. The size of the output buffer must be equal to the hunk size of the
-    /// CHD file.
+    /// compressed hunk. The size of the output buffer must be at least the hunk size of the
+    /// CHD file, and only the first hunk size bytes of a larger buffer are written. If the
+    /// output buffer is smaller than the hunk size, returns `ChdError::InvalidParameter`.
     ///
-    /// Returns the number of bytes decompressed on success, which should be the length of
-    /// the output buffer.
+    /// Returns the number of bytes decompressed on success, which should be the hunk size.
     pub fn read_hunk_in(
         &mut self,
         compressed_buffer: &mut Vec<u8>,
         output: &mut [u8],
     ) -> Result<usize> {
-        if output.len() != self.inner.header.hunk_size() as usize {
-            return Err(ChdError::OutOfMemory);
-        }
+        self.read_hunk(compressed_buffer, output, true)
+    }
+
+    /// Decompresses the hunk into output like [`read_hunk_in`](ChdHunk::read_hunk_in), but
+    /// skips decompressing the subcode data of hunks compressed with a CD-ROM codec. The subcode
+    /// data of every frame in the output buffer is zeroed instead.
+    ///
+    /// This is faster when the subcode data would be discarded anyways. Since the checksum of a
+    /// hunk covers its subcode data, the checksum is not verified for hunks compressed with a
+    /// CD-ROM codec. Hunks read without subcode data are never stored in the hunk cache
+    /// used by [`ChdFile::read_hunk_range`](crate::ChdFile::read_hunk_range).
+    ///
+    /// Returns the number of bytes decompressed on success.
+    pub fn read_hunk_without_subcode_in(
+        &mut self,
+        compressed_buffer: &mut Vec<u8>,
+        output: &mut [u8],
+    ) -> Result<usize> {
+        self.read_hunk(compressed_buffer, output, false)
+    }
+
+    fn read_hunk(
+        &mut self,
+        compressed_buffer: &mut Vec<u8>,
+        output: &mut [u8],
+        want_subcode: bool,
+    ) -> Result<usize> {
+        // Codecs decompress as much data as fits in the output, so a larger buffer is
+        // truncated to the hunk size.
+        let output = output
+            .get_mut(..self.inner.header.hunk_size() as usize)
+            .ok_or(ChdError::InvalidParameter)?;
 
         match self.inner.map() {
-            ChdMap::V5(_) => self.read_hunk_v5(compressed_buffer, output),
+            ChdMap::V5(_) => self.read_hunk_v5(compressed_buffer, output, want_subcode),
+            // V1-4 CHD files do not have CD-ROM codecs.
             ChdMap::Legacy(_) => self.read_hunk_legacy(compressed_buffer, output),
         }
     }
@@ -367,4 +1759,9 @@ impl<'a, F: Read + Seek> ChdHunk<'a, F> {
     pub fn len(&self) -> usize {
         self.inner.header.hunk_size() as usize
     }
+
+    /// Returns the number of bytes of this hunk that lie within the logical size of the CHD file.
+    fn logical_len(&self) -> usize {
+        self.inner.logical_len_of(self.hunk_num)
+    }
 }
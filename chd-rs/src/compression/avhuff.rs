@@ -1,3 +1,4 @@
+use crate::compression::flac::flac_error;
 use crate::compression::{
     CodecImplementation, CompressionCodec, CompressionCodecType, DecompressResult,
 };
@@ -130,7 +131,7 @@ fn avhuff_write_header(
     output.write_u16::<BigEndian>(samples)?;
     output.write_u16::<BigEndian>(width)?;
     output.write_u16::<BigEndian>(height)?;
-    Ok(DecompressResult::new(AVHU_HEADER_LEN, 0))
+    Ok(DecompressResult::from_parts(AVHU_HEADER_LEN, 0))
 }
 
 impl CodecImplementation for AVHuffCodec {
@@ -216,8 +217,10 @@ impl CodecImplementation for AVHuffCodec {
         input_cursor.read_exact(out_meta)?;
 
         // So far we have written HEADER_LEN
-        let mut result =
-            DecompressResult::new(header_result.total_out(), input_cursor.position() as usize);
+        let mut result = DecompressResult::from_parts(
+            header_result.total_out(),
+            input_cursor.position() as usize,
+        );
         if channels > 0 {
             // decode_audio returns the number of bytes read from the input buffer,
             // and the number of bytes written into the output buffer (channel_slices).
@@ -260,6 +263,10 @@ impl CodecImplementation for AVHuffCodec {
 
         Ok(result)
     }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
 }
 
 impl AVHuffCodec {
@@ -291,7 +298,8 @@ impl AVHuffCodec {
                         }
                         block_buf = block.into_buffer();
                     }
-                    _ => return Err(ChdError::DecompressionError),
+                    Ok(None) => return Err(ChdError::TruncatedInput),
+                    Err(err) => return Err(flac_error(err)),
                 }
             }
             total_read += frame_read.into_inner().position();
@@ -299,7 +307,7 @@ impl AVHuffCodec {
 
             self.buffer = block_buf;
         }
-        Ok(DecompressResult::new(
+        Ok(DecompressResult::from_parts(
             total_written as usize,
             total_read as usize,
         ))
@@ -350,7 +358,7 @@ impl AVHuffCodec {
                     // Advance the slice
                     input = &input[size as usize..]
                 }
-                Ok(DecompressResult::new(bytes_written, bytes_written))
+                Ok(DecompressResult::from_parts(bytes_written, bytes_written))
             }
             tree_size => {
                 let mut source = input;
@@ -396,7 +404,10 @@ impl AVHuffCodec {
                     bytes_read += bit_reader.position() / 8;
                     source = &source[size as usize..]
                 }
-                Ok(DecompressResult::new(bytes_written, bytes_read as usize))
+                Ok(DecompressResult::from_parts(
+                    bytes_written,
+                    bytes_read as usize,
+                ))
             }
         }
     }
@@ -456,7 +467,7 @@ impl AVHuffCodec {
         // If we don't fill the output buffer, fill the remainder with zeroes.
         output[bytes_written..].fill(0);
 
-        Ok(DecompressResult::new(
+        Ok(DecompressResult::from_parts(
             output.len(),
             1 + bit_reader.position() as usize / 8,
         ))
@@ -108,6 +108,13 @@ impl CompressionCodecType for CdZlibCodec {
 impl CompressionCodec for CdZlibCodec {}
 impl CompressionCodec for CdLzmaCodec {}
 
+/// Zeroes the subcode data of every frame in the buffer.
+pub(crate) fn clear_subcode(output: &mut [u8]) {
+    for frame in output.chunks_exact_mut(CD_FRAME_SIZE as usize) {
+        frame[CD_MAX_SECTOR_DATA as usize..].fill(0);
+    }
+}
+
 // unstable(adt_const_params): const TYPE: CodecType, but marker traits bring us
 // most of the way.
 /// CD-ROM codec wrapper.
@@ -137,7 +144,44 @@ impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplement
         })
     }
 
+    fn max_compressed_size(hunk_size: u32) -> usize {
+        let frames = hunk_size / CD_FRAME_SIZE;
+        let complen_bytes = if hunk_size < 65536 { 2 } else { 3 };
+        let ecc_bytes = (frames as usize + 7) / 8;
+        ecc_bytes
+            + complen_bytes
+            + Engine::max_compressed_size(frames * CD_MAX_SECTOR_DATA)
+            + SubEngine::max_compressed_size(frames * CD_MAX_SUBCODE_DATA)
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
+        self.decompress_frames(input, output, true)
+    }
+
+    fn decompress_without_subcode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<DecompressResult> {
+        self.decompress_frames(input, output, false)
+    }
+
+    fn reset(&mut self) {
+        self.engine.reset();
+        self.sub_engine.reset();
+        self.buffer.fill(0);
+    }
+}
+
+impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CdCodec<Engine, SubEngine> {
+    /// Decompresses the frames of a CD-ROM hunk, only decompressing the subcode data
+    /// if `want_subcode` is true.
+    fn decompress_frames(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        want_subcode: bool,
+    ) -> Result<DecompressResult> {
         // https://github.com/rtissera/libchdr/blob/cdcb714235b9ff7d207b703260706a364282b063/src/libchdr_chd.c#L647
         let frames = output.len() / CD_FRAME_SIZE as usize;
         let complen_bytes = if output.len() < 65536 { 2 } else { 3 };
@@ -158,11 +202,15 @@ impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplement
         )?;
 
         #[cfg(feature = "want_subcode")]
-        let sub_res = self.sub_engine.decompress(
-            &input[header_bytes + sector_compressed_len as usize..],
-            &mut self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
-                [..frames * CD_MAX_SUBCODE_DATA as usize],
-        )?;
+        let sub_res = if want_subcode {
+            self.sub_engine.decompress(
+                &input[header_bytes + sector_compressed_len as usize..],
+                &mut self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
+                    [..frames * CD_MAX_SUBCODE_DATA as usize],
+            )?
+        } else {
+            DecompressResult::default()
+        };
 
         #[cfg(not(feature = "want_subcode"))]
         let sub_res = DecompressResult::default();
@@ -183,13 +231,20 @@ impl<Engine: CodecImplementation, SubEngine: CodecImplementation> CodecImplement
 
         // Reassemble subcode data to expected layout.
         #[cfg(feature = "want_subcode")]
-        for (frame_num, chunk) in self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
-            .chunks_exact(CD_MAX_SUBCODE_DATA as usize)
-            .enumerate()
-        {
-            output[frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
-                [..CD_MAX_SUBCODE_DATA as usize]
-                .copy_from_slice(chunk);
+        if want_subcode {
+            for (frame_num, chunk) in self.buffer[frames * CD_MAX_SECTOR_DATA as usize..]
+                .chunks_exact(CD_MAX_SUBCODE_DATA as usize)
+                .take(frames)
+                .enumerate()
+            {
+                output[frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
+                    [..CD_MAX_SUBCODE_DATA as usize]
+                    .copy_from_slice(chunk);
+            }
+        }
+
+        if !want_subcode {
+            clear_subcode(output);
         }
 
         // Recreate ECC data
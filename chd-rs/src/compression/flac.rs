@@ -1,23 +1,39 @@
-use std::io::Cursor;
+use std::io::{Cursor, ErrorKind};
 use std::marker::PhantomData;
 use std::mem;
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use claxon::frame::FrameReader;
 
 use crate::cdrom::{CD_FRAME_SIZE, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA};
+use crate::compression::cdrom::clear_subcode;
 use crate::compression::zlib::ZlibCodec;
 use crate::compression::{
     CodecImplementation, CompressionCodec, CompressionCodecType, DecompressResult,
 };
+use crate::const_assert;
 use crate::error::{ChdError, Result};
 use crate::header::CodecType;
 
+/// Converts a FLAC decoding error into a [`ChdError`], keeping the description of the
+/// error without exposing claxon types.
+///
+/// Running out of input in the middle of a frame is reported as `ChdError::TruncatedInput`.
+pub(crate) fn flac_error(err: claxon::Error) -> ChdError {
+    match err {
+        claxon::Error::IoError(e) if e.kind() == ErrorKind::UnexpectedEof => {
+            ChdError::TruncatedInput
+        }
+        err => ChdError::Flac(err.to_string()),
+    }
+}
+
 /// Generic block decoder for FLAC.
 ///
 /// Defaults assume 2 channel interleaved FLAC.
 /// The byte order determines the endianness of the output data.
-struct FlacCodec<T: ByteOrder, const CHANNELS: usize = 2> {
+/// A channel count of 0 is rejected at compile time.
+pub(crate) struct FlacCodec<T: ByteOrder, const CHANNELS: usize = 2> {
     buffer: Vec<i32>,
     _byteorder: PhantomData<T>,
 }
@@ -34,6 +50,8 @@ impl<T: ByteOrder, const CHANNELS: usize> CodecImplementation for FlacCodec<T, C
     where
         Self: Sized,
     {
+        // The hunk size is checked against the size of one sample for all channels.
+        const_assert!(CHANNELS: usize => CHANNELS > 0);
         if hunk_bytes % (CHANNELS * mem::size_of::<i16>()) as u32 != 0 {
             return Err(ChdError::CodecError);
         }
@@ -44,66 +62,104 @@ impl<T: ByteOrder, const CHANNELS: usize> CodecImplementation for FlacCodec<T, C
         })
     }
 
-    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
-        let comp_buf = Cursor::new(input);
+    fn max_compressed_size(hunk_bytes: u32) -> usize {
+        // Verbatim subframes store each sample with up to 17 bits for a side channel, and each
+        // frame of at least 16 samples adds a header of up to 16 bytes, a subframe header for
+        // each channel and a CRC-16 footer.
+        let len = hunk_bytes as usize;
+        let frames = len / (CHANNELS * mem::size_of::<i16>()) / 16 + 1;
+        len + len / 16 + frames * (16 + CHANNELS + 2)
+    }
 
-        // Number of samples to write to the buffer.
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
+        // Number of samples to write to the buffer. For a short final hunk, the caller passes
+        // an output buffer of the logical length of the hunk, which gives the exact number of
+        // samples to decode.
         let sample_len = output.len() / (CHANNELS * mem::size_of::<i16>());
 
+        // There must be at least one frame to read from.
+        if input.is_empty() && sample_len > 0 {
+            return Err(ChdError::TruncatedInput);
+        }
+
+        let comp_buf = Cursor::new(input);
+
         // We don't need to create a fake header since claxon will read raw FLAC frames just fine.
         // We just need to be careful not to read past the number of blocks in the input buffer.
         let mut frame_read = FrameReader::new(comp_buf);
 
-        let mut cursor = Cursor::new(output);
-
         // Buffer to hold decompressed FLAC block data.
         let mut block_buf = mem::take(&mut self.buffer);
 
         // A little bit of a misnomer. 1 'sample' refers to a sample for all channels.
         let mut samples_written = 0;
+        let sample_bytes = CHANNELS * mem::size_of::<i16>();
 
         while samples_written < sample_len {
             // Loop through all blocks until we have enough samples written.
             match frame_read.read_next_or_eof(block_buf) {
                 Ok(Some(block)) => {
-                    // We assume 2 channels (by default), so we can use claxon's stereo_samples
-                    // iterator for slightly better performance.
+                    // A block that extends past the requested samples is only converted up to
+                    // the end of the output, so that a short final hunk stops at its logical
+                    // length without reading any further blocks.
+                    let block_len =
+                        std::cmp::min(block.duration() as usize, sample_len - samples_written);
+                    let block_out = output
+                        .get_mut(samples_written * sample_bytes..)
+                        .and_then(|out| out.get_mut(..block_len * sample_bytes))
+                        .ok_or(ChdError::DecompressionError)?;
+
+                    // We assume 2 channels (by default), so the channels can be interleaved
+                    // directly from the decoded channel buffers.
                     #[cfg(not(feature = "nonstandard_channel_count"))]
-                    for (l, r) in block.stereo_samples() {
-                        cursor.write_i16::<T>(l as i16)?;
-                        cursor.write_i16::<T>(r as i16)?;
-                        samples_written += 1;
+                    {
+                        if block.channels() != 2 {
+                            return Err(ChdError::UnsupportedFeature("nonstandard_channel_count"));
+                        }
+                        let samples = block.channel(0).iter().zip(block.channel(1));
+                        for (out, (&l, &r)) in block_out.chunks_exact_mut(4).zip(samples) {
+                            T::write_i16(&mut out[..2], l as i16);
+                            T::write_i16(&mut out[2..], r as i16);
+                        }
                     }
 
                     // This is generic over number of assumed channels, but is broken effectively
                     // for any value other than 2.
                     // What we really want here is specialization for CHANNELS = 2 ...
                     #[cfg(feature = "nonstandard_channel_count")]
-                    for sample in 0..block.len() / block.channels() {
-                        for channel in 0..block.channels() {
-                            let sample_data = block.sample(channel, sample) as u16;
-                            cursor.write_i16::<T>(sample_data as i16)?;
+                    for (sample, out) in block_out.chunks_exact_mut(sample_bytes).enumerate() {
+                        for (channel, out) in out.chunks_exact_mut(2).enumerate() {
+                            T::write_i16(out, block.sample(channel as u32, sample as u32) as i16);
                         }
-                        samples_written += 1;
                     }
 
+                    samples_written += block_len;
                     block_buf = block.into_buffer();
                 }
-                _ => {
-                    // If frame_read dies our buffer just gets eaten. The Error return for a failed
-                    // read does not expose the inner buffer.
-                    return Err(ChdError::DecompressionError);
-                }
+                // The stream ended at a frame boundary, but with fewer samples than requested.
+                // This is only valid for a short final hunk, which the caller has to
+                // decompress with a shorter output buffer.
+                Ok(None) => return Err(ChdError::TruncatedInput),
+                // If frame_read dies our buffer just gets eaten. The Error return for a failed
+                // read does not expose the inner buffer.
+                Err(err) => return Err(flac_error(err)),
             }
         }
 
         self.buffer = block_buf;
+        // claxon reads the input one byte at a time and stops at the end of the CRC footer
+        // of each frame, so the position is exactly the end of the last frame that was read.
+        // CD-ROM wrapped FLAC relies on this to find the start of the subcode data.
         let bytes_in = frame_read.into_inner().position();
-        Ok(DecompressResult::new(
+        Ok(DecompressResult::from_parts(
             samples_written * 4,
             bytes_in as usize,
         ))
     }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
 }
 
 /// Raw FLAC (flac) decompression codec.
@@ -114,11 +170,17 @@ impl<T: ByteOrder, const CHANNELS: usize> CodecImplementation for FlacCodec<T, C
 ///
 /// FLAC compressed audio data is assumed to be 2-channel 16-bit signed integer PCM.
 /// The audio data is decompressed in interleaved format, with the left channel first, then
-/// the right channel for each sample, for 32 bits each sample.
+/// the right channel for each sample, for 32 bits each sample. Unless the
+/// `nonstandard_channel_count` feature is enabled, a FLAC block with any other number of
+/// channels returns
+/// [`ChdError::UnsupportedFeature("nonstandard_channel_count")`](crate::ChdError::UnsupportedFeature).
 ///
 /// ## Buffer Restrictions
 /// Each compressed FLAC hunk decompresses to a hunk-sized chunk.
-/// The input buffer must contain enough samples to fill the hunk-sized output buffer.
+/// The input buffer must contain enough samples to fill the hunk-sized output buffer,
+/// otherwise [`ChdError::TruncatedInput`](crate::ChdError::TruncatedInput) is returned.
+/// A shorter output buffer is filled with exactly as many samples as fit, even if the last
+/// FLAC block read extends past the end of the buffer. Blocks after that are not read.
 pub struct RawFlacCodec {
     be: FlacCodec<BigEndian>,
     le: FlacCodec<LittleEndian>,
@@ -147,12 +209,28 @@ impl CodecImplementation for RawFlacCodec {
         })
     }
 
+    fn max_compressed_size(hunk_bytes: u32) -> usize {
+        // The FLAC frames are preceded by the byte order marker.
+        1 + FlacCodec::<BigEndian>::max_compressed_size(hunk_bytes)
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
-        match input[0] {
-            b'L' => self.le.decompress(&input[1..], output),
-            b'B' => self.be.decompress(&input[1..], output),
-            _ => Err(ChdError::DecompressionError),
-        }
+        let res = match input.first() {
+            Some(b'L') => self.le.decompress(&input[1..], output)?,
+            Some(b'B') => self.be.decompress(&input[1..], output)?,
+            Some(_) => return Err(ChdError::DecompressionError),
+            None => return Err(ChdError::TruncatedInput),
+        };
+        // The byte order marker is part of the input that was read.
+        Ok(DecompressResult::from_parts(
+            res.total_out(),
+            res.total_in() + 1,
+        ))
+    }
+
+    fn reset(&mut self) {
+        self.be.reset();
+        self.le.reset();
     }
 }
 
@@ -188,7 +266,9 @@ impl CodecImplementation for RawFlacCodec {
 /// ## Buffer Restrictions
 /// Each compressed CDFL hunk decompresses to a hunk-sized chunk. The hunk size must be a multiple
 /// of 2448, the size of each CD frame. The input buffer must contain enough samples to fill
-/// the number of CD sectors that can fit into the output buffer.
+/// the number of CD sectors that can fit into the output buffer. If the length of the output
+/// buffer is not a multiple of 2448 or is larger than the hunk size, returns
+/// [`ChdError::CodecError`](crate::ChdError::CodecError).
 pub struct CdFlacCodec {
     // cdfl always writes in big endian.
     engine: FlacCodec<BigEndian>,
@@ -229,7 +309,45 @@ impl CodecImplementation for CdFlacCodec {
         })
     }
 
+    fn max_compressed_size(hunk_size: u32) -> usize {
+        let frames = hunk_size / CD_FRAME_SIZE;
+        FlacCodec::<BigEndian>::max_compressed_size(frames * CD_MAX_SECTOR_DATA)
+            + ZlibCodec::max_compressed_size(frames * CD_MAX_SUBCODE_DATA)
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
+        self.decompress_frames(input, output, true)
+    }
+
+    fn decompress_without_subcode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<DecompressResult> {
+        self.decompress_frames(input, output, false)
+    }
+
+    fn reset(&mut self) {
+        self.engine.reset();
+        self.sub_engine.reset();
+        self.buffer.fill(0);
+    }
+}
+
+impl CdFlacCodec {
+    /// Decompresses the frames of a CD-ROM hunk, only decompressing the subcode data
+    /// if `want_subcode` is true.
+    fn decompress_frames(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        want_subcode: bool,
+    ) -> Result<DecompressResult> {
+        // The output must hold whole frames that fit in the hunk-sized buffer.
+        if output.len() % CD_FRAME_SIZE as usize != 0 || output.len() > self.buffer.len() {
+            return Err(ChdError::CodecError);
+        }
+
         let total_frames = output.len() / CD_FRAME_SIZE as usize;
         let frame_res = self.engine.decompress(
             input,
@@ -237,11 +355,15 @@ impl CodecImplementation for CdFlacCodec {
         )?;
 
         #[cfg(feature = "want_subcode")]
-        let sub_res = self.sub_engine.decompress(
-            &input[frame_res.total_in()..],
-            &mut self.buffer[total_frames * CD_MAX_SECTOR_DATA as usize..]
-                [..total_frames * CD_MAX_SUBCODE_DATA as usize],
-        )?;
+        let sub_res = if want_subcode {
+            self.sub_engine.decompress(
+                &input[frame_res.total_in()..],
+                &mut self.buffer[total_frames * CD_MAX_SECTOR_DATA as usize..]
+                    [..total_frames * CD_MAX_SUBCODE_DATA as usize],
+            )?
+        } else {
+            DecompressResult::default()
+        };
 
         #[cfg(not(feature = "want_subcode"))]
         let sub_res = DecompressResult::default();
@@ -262,13 +384,20 @@ impl CodecImplementation for CdFlacCodec {
 
         // Reassemble subcode data to expected layout.
         #[cfg(feature = "want_subcode")]
-        for (frame_num, chunk) in self.buffer[total_frames * CD_MAX_SECTOR_DATA as usize..]
-            .chunks_exact(CD_MAX_SUBCODE_DATA as usize)
-            .enumerate()
-        {
-            output[frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
-                [..CD_MAX_SUBCODE_DATA as usize]
-                .copy_from_slice(chunk);
+        if want_subcode {
+            for (frame_num, chunk) in self.buffer[total_frames * CD_MAX_SECTOR_DATA as usize..]
+                .chunks_exact(CD_MAX_SUBCODE_DATA as usize)
+                .take(total_frames)
+                .enumerate()
+            {
+                output[frame_num * CD_FRAME_SIZE as usize + CD_MAX_SECTOR_DATA as usize..]
+                    [..CD_MAX_SUBCODE_DATA as usize]
+                    .copy_from_slice(chunk);
+            }
+        }
+
+        if !want_subcode {
+            clear_subcode(output);
         }
 
         Ok(frame_res + sub_res)
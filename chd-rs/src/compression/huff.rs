@@ -40,9 +40,9 @@ impl CodecImplementation for HuffmanCodec {
             output[i] = decoder.decode_one(&mut bit_reader)? as u8;
         }
 
-        Ok(DecompressResult::new(
+        Ok(DecompressResult::from_parts(
             output.len(),
-            ((input.len() * 8) - bit_reader.remaining() as usize) / 8,
+            ((input.len() * 8) - bit_reader.remaining() as usize + 7) / 8,
         ))
     }
 }
@@ -4,9 +4,22 @@ use crate::compression::{
 use crate::error::{ChdError, Result};
 use crate::header::CodecType;
 use lzma_rs::decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties};
-use std::io::Cursor;
+use std::io::{Cursor, ErrorKind};
 // use lzma_rs_headerless::decompress::LzmaDecoder;
 
+/// Converts an LZMA decoding error into a [`ChdError`], keeping the description of the
+/// error without exposing lzma-rs types.
+///
+/// Running out of input before the output is filled is reported as `ChdError::TruncatedInput`.
+fn lzma_error(err: lzma_rs::error::Error) -> ChdError {
+    match err {
+        lzma_rs::error::Error::IoError(e) if e.kind() == ErrorKind::UnexpectedEof => {
+            ChdError::TruncatedInput
+        }
+        err => ChdError::Lzma(err.to_string()),
+    }
+}
+
 /// LZMA (lzma) decompression codec.
 ///
 /// ## Format Details
@@ -17,6 +30,8 @@ use std::io::Cursor;
 /// * Literal Position Bits (`lp`): 0
 /// * Position Bits (`pb`): 2
 ///
+/// Hunks encoded with other settings can be decoded with [`LzmaCodec::with_props`].
+///
 /// The dictionary size is determined via the following algorithm with a level of 9, and a
 /// reduction size of hunk size.
 ///
@@ -93,6 +108,34 @@ fn get_lzma_dict_size(level: u32, reduce_size: u32) -> u32 {
     dict_size
 }
 
+impl LzmaCodec {
+    /// Creates an LZMA codec for raw LZMA chunks encoded with the given literal context bits (`lc`),
+    /// literal position bits (`lp`) and position bits (`pb`).
+    ///
+    /// CHD files created by chdman always use the standard properties of
+    /// [`LzmaCodec::new`](crate::codecs::CodecImplementation::new). This allows decoding hunks of
+    /// CHD variants written by encoders with nonstandard settings.
+    ///
+    /// Returns [`ChdError::CodecError`](crate::ChdError::CodecError) if `lc` is greater than 8,
+    /// or `lp` or `pb` is greater than 4.
+    pub fn with_props(lc: u32, lp: u32, pb: u32, hunk_size: u32) -> Result<Self> {
+        if lc > 8 || lp > 4 || pb > 4 {
+            return Err(ChdError::CodecError);
+        }
+        Ok(LzmaCodec {
+            engine: LzmaDecoder::new(
+                LzmaParams::new(
+                    LzmaProperties { lc, lp, pb },
+                    get_lzma_dict_size(9, hunk_size),
+                    None,
+                ),
+                None,
+            )
+            .map_err(|_| ChdError::DecompressionError)?,
+        })
+    }
+}
+
 impl CompressionCodecType for LzmaCodec {
     fn codec_type(&self) -> CodecType
     where
@@ -107,22 +150,15 @@ impl CodecImplementation for LzmaCodec {
         false
     }
 
+    /// Creates an LZMA codec with the CHD standard properties `lc = 3`, `lp = 0`, `pb = 2`.
     fn new(hunk_size: u32) -> Result<Self> {
-        Ok(LzmaCodec {
-            engine: LzmaDecoder::new(
-                LzmaParams::new(
-                    LzmaProperties {
-                        lc: 3,
-                        lp: 0,
-                        pb: 2,
-                    },
-                    get_lzma_dict_size(9, hunk_size),
-                    None,
-                ),
-                None,
-            )
-            .map_err(|_| ChdError::DecompressionError)?,
-        })
+        LzmaCodec::with_props(3, 0, 2, hunk_size)
+    }
+
+    fn max_compressed_size(hunk_size: u32) -> usize {
+        // The output buffer size recommended by LzmaLib.h for incompressible data.
+        let len = hunk_size as usize;
+        len + len / 3 + 128
     }
 
     fn decompress(&mut self, input: &[u8], mut output: &mut [u8]) -> Result<DecompressResult> {
@@ -131,7 +167,7 @@ impl CodecImplementation for LzmaCodec {
         let len = output.len();
         self.engine
             .decompress(&mut read, &mut output)
-            .map_err(|_| ChdError::DecompressionError)?;
-        Ok(DecompressResult::new(len, read.position() as usize))
+            .map_err(lzma_error)?;
+        Ok(DecompressResult::from_parts(len, read.position() as usize))
     }
 }
@@ -1,13 +1,16 @@
-use crate::error::Result;
+use crate::error::{ChdError, Result};
 use crate::header::CodecType;
+use num_traits::FromPrimitive;
 use std::ops::{Add, AddAssign};
 
 mod avhuff;
 mod cdrom;
 mod ecc;
-mod flac;
+pub(crate) mod flac;
 mod lzma;
 mod none;
+#[cfg(feature = "codec_self_test")]
+mod self_test;
 mod zlib;
 
 #[cfg(feature = "flac_header")]
@@ -24,6 +27,10 @@ pub mod codecs {
     pub use crate::compression::lzma::LzmaCodec;
     pub use crate::compression::none::NoneCodec;
     pub use crate::compression::zlib::ZlibCodec;
+
+    #[cfg(feature = "codec_self_test")]
+    #[cfg_attr(docsrs, doc(cfg(codec_self_test)))]
+    pub use crate::compression::self_test::self_test;
 }
 
 // unstable(trait_alias)
@@ -51,17 +58,57 @@ pub trait CodecImplementation {
     where
         Self: Sized;
 
+    /// Returns an upper bound on the length of the compressed data of a hunk of the provided
+    /// hunk size, so that a single buffer can hold the compressed data of any hunk.
+    ///
+    /// Defaults to the hunk size with an allowance of 1 KiB for headers and tables.
+    fn max_compressed_size(hunk_size: u32) -> usize
+    where
+        Self: Sized,
+    {
+        hunk_size as usize + 1024
+    }
+
     /// Decompress compressed bytes from the input buffer into the
     /// output buffer.
     ///
     /// Usually the output buffer must have the exact
     /// length as `hunk_size`, but this may be dependent on the codec
     /// implementation.
+    ///
+    /// If the input is not valid compressed data for this codec, returns
+    /// `ChdError::DecompressionError`, or a more specific error if the codec
+    /// can describe the failure.
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult>;
+
+    /// Decompress compressed bytes from the input buffer into the
+    /// output buffer, skipping the decompression of subcode data.
+    ///
+    /// CD-ROM codecs zero the subcode data of every frame in the output
+    /// buffer instead of decompressing it. Codecs that do not store
+    /// subcode data decompress the input as with [`decompress`](CodecImplementation::decompress).
+    fn decompress_without_subcode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<DecompressResult> {
+        self.decompress(input, output)
+    }
+
+    /// Clears any state left over from previous decompressions, so that the codec can be
+    /// reused to decompress hunks of another CHD file with the same hunk size.
+    ///
+    /// Allocated buffers are kept to avoid reallocation. Codecs that do not keep state
+    /// between decompressions do nothing.
+    fn reset(&mut self) {}
 }
 
 /// The result of a chunk decompression operation.
-#[derive(Copy, Clone, Default)]
+///
+/// Adding two results sums the number of bytes written and read by each, which is how
+/// codecs that decompress in multiple stages, such as the CD-ROM codecs, report the
+/// combined result. Additions saturate at `usize::MAX` instead of overflowing.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
 pub struct DecompressResult {
     bytes_out: usize,
     bytes_read: usize,
@@ -72,24 +119,25 @@ impl Add for DecompressResult {
 
     fn add(self, rhs: Self) -> Self::Output {
         DecompressResult {
-            bytes_out: self.total_out() + rhs.total_out(),
-            bytes_read: self.total_in() + rhs.total_in(),
+            bytes_out: self.total_out().saturating_add(rhs.total_out()),
+            bytes_read: self.total_in().saturating_add(rhs.total_in()),
         }
     }
 }
 
 impl AddAssign for DecompressResult {
     fn add_assign(&mut self, rhs: Self) {
-        self.bytes_read += rhs.bytes_read;
-        self.bytes_out += rhs.bytes_out;
+        *self = *self + rhs;
     }
 }
 
 impl DecompressResult {
-    pub(crate) fn new(out: usize, read: usize) -> Self {
+    /// Creates a result from the number of decompressed bytes written to the output
+    /// buffer and the number of bytes read from the compressed input buffer.
+    pub const fn from_parts(total_out: usize, total_in: usize) -> Self {
         DecompressResult {
-            bytes_out: out,
-            bytes_read: read,
+            bytes_out: total_out,
+            bytes_read: total_in,
         }
     }
 
@@ -103,3 +151,35 @@ impl DecompressResult {
         self.bytes_read
     }
 }
+
+/// Decompresses a single hunk that was extracted from a CHD file, given the tag of the codec
+/// that compressed it and the hunk size of the CHD file.
+///
+/// A new codec is initialized for every call, so this is only suitable for one-shot decoding.
+/// The same restrictions on the buffers apply as for the
+/// [`decompress`](crate::codecs::CodecImplementation::decompress) method of the codec.
+///
+/// If the tag is not a supported codec, returns `ChdError::UnsupportedCodec` with the tag.
+/// ```rust
+/// // A hunk of 4096 bytes compressed with Deflate.
+/// let hunk: [u8; 36] = [
+///     237, 199, 55, 17, 0, 32, 16, 0, 176, 167, 87, 255, 122, 241, 193, 37, 91, 98, 164, 153,
+///     87, 217, 245, 180, 219, 67, 68, 68, 68, 68, 68, 68, 68, 126, 204, 3,
+/// ];
+///
+/// let mut output = vec![0u8; 4096];
+/// let res = chd::decompress_hunk_blob(*b"zlib", 4096, &hunk, &mut output)?;
+/// assert_eq!(res.total_out(), 4096);
+/// assert!(output.iter().enumerate().all(|(i, &b)| b == (i * 7 % 13) as u8));
+/// # Ok::<(), chd::ChdError>(())
+/// ```
+pub fn decompress_hunk_blob(
+    tag: [u8; 4],
+    hunk_size: u32,
+    input: &[u8],
+    output: &mut [u8],
+) -> Result<DecompressResult> {
+    let tag = u32::from_be_bytes(tag);
+    let codec = CodecType::from_u32(tag).ok_or(ChdError::UnsupportedCodec(tag))?;
+    codec.init(hunk_size)?.decompress(input, output)
+}
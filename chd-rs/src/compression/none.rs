@@ -20,8 +20,15 @@ impl CodecImplementation for NoneCodec {
         Ok(NoneCodec)
     }
 
+    fn max_compressed_size(hunk_size: u32) -> usize {
+        hunk_size as usize
+    }
+
     fn decompress(&mut self, input: &[u8], mut output: &mut [u8]) -> Result<DecompressResult> {
-        Ok(DecompressResult::new(output.write(&input)?, input.len()))
+        Ok(DecompressResult::from_parts(
+            output.write(&input)?,
+            input.len(),
+        ))
     }
 }
 
@@ -0,0 +1,56 @@
+// Codec self-tests
+use crate::compression::codecs::{LzmaCodec, NoneCodec, ZlibCodec};
+use crate::compression::CodecImplementation;
+use crate::error::{ChdError, Result};
+use crate::header::CodecType;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+// A hunk of 4096 bytes of the self-test pattern, compressed with LZMA.
+const LZMA_HUNK: [u8; 45] = [
+    0, 0, 2, 13, 1, 50, 145, 32, 218, 112, 4, 224, 63, 181, 136, 209, 149, 180, 145, 254, 197, 25,
+    225, 157, 168, 88, 160, 91, 207, 178, 60, 170, 66, 145, 197, 30, 179, 38, 114, 199, 255, 254,
+    135, 184, 0,
+];
+const LZMA_HUNK_LEN: usize = 4096;
+
+/// Returns the deterministic data that the self-tests decompress.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 13) as u8).collect()
+}
+
+/// Decompresses `input` with a new codec and checks that it holds `len` bytes of the pattern.
+fn check<C: CodecImplementation>(hunk_size: u32, input: &[u8], len: usize) -> Result<()> {
+    let mut codec = C::new(hunk_size)?;
+    let mut output = vec![0u8; len];
+    let res = codec.decompress(input, &mut output)?;
+    if res.total_out() != len || output != pattern(len) {
+        return Err(ChdError::DecompressionError);
+    }
+    Ok(())
+}
+
+/// Checks that the codec of the given type decompresses data correctly for the given hunk size.
+///
+/// Codecs that can be compressed with a dependency of chd-rs, such as zlib, compress a
+/// hunk-sized pattern and check that it decompresses to the same data. Other codecs decompress
+/// a bundled hunk of 4096 bytes. This allows checking that every codec of a build of chd-rs
+/// is functional, for example in the continuous integration of crates that depend on it.
+///
+/// The None, zlib and LZMA codec types can be tested. Other codec types return
+/// `ChdError::NotSupported`. If the decompressed data is incorrect, returns
+/// `ChdError::DecompressionError`.
+pub fn self_test(codec_type: CodecType, hunk_size: u32) -> Result<()> {
+    let hunk_len = hunk_size as usize;
+    match codec_type {
+        CodecType::None => check::<NoneCodec>(hunk_size, &pattern(hunk_len), hunk_len),
+        CodecType::Zlib | CodecType::ZlibPlus | CodecType::ZLibV5 => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&pattern(hunk_len))?;
+            check::<ZlibCodec>(hunk_size, &encoder.finish()?, hunk_len)
+        }
+        CodecType::LzmaV5 => check::<LzmaCodec>(hunk_size, &LZMA_HUNK, LZMA_HUNK_LEN),
+        _ => Err(ChdError::NotSupported),
+    }
+}
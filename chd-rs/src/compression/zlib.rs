@@ -29,6 +29,12 @@ impl CodecImplementation for ZlibCodec {
         })
     }
 
+    fn max_compressed_size(hunk_size: u32) -> usize {
+        // The same bound as compressBound in zlib.
+        let len = hunk_size as usize;
+        len + (len >> 12) + (len >> 14) + (len >> 25) + 13
+    }
+
     fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<DecompressResult> {
         self.engine.reset(false);
         let status = self
@@ -45,11 +51,15 @@ impl CodecImplementation for ZlibCodec {
             return Err(ChdError::DecompressionError);
         }
 
-        Ok(DecompressResult::new(
+        Ok(DecompressResult::from_parts(
             total_out as usize,
             self.engine.total_in() as usize,
         ))
     }
+
+    fn reset(&mut self) {
+        self.engine.reset(false);
+    }
 }
 
 impl CompressionCodecType for ZlibCodec {
@@ -9,10 +9,15 @@ use std::str::Utf8Error;
 
 /// Error types that may occur when reading a CHD file or hunk.
 ///
-/// This type tries to be ABI-compatible with [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258),
-/// given sane defaults in the C compiler. See [repr(C) in the Rustonomicon](https://doc.rust-lang.org/nomicon/other-reprs.html#reprc) for more details.
-#[derive(Debug)]
-#[repr(C)]
+/// The variants up to [`ChdError::Unknown`](crate::ChdError::Unknown) are the error codes of
+/// [libchdr](https://github.com/rtissera/libchdr/blob/6eeb6abc4adc094d489c8ba8cafdcff9ff61251b/include/libchdr/chd.h#L258),
+/// in the same order. Variants following it carry additional detail about the error and have
+/// no equivalent in libchdr. Since variants carry data, this type is not ABI-compatible with
+/// libchdr; the C API bindings convert it to their own `chd_error` type.
+///
+/// Errors compare equal if they are the same variant with equal details, which allows asserting
+/// on specific errors with `assert_eq!`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ChdError {
     /// No error.
     /// This is only used by the C API bindings.
@@ -33,7 +38,7 @@ pub enum ChdError {
     /// This CHD requires a parent CHD that was not provided.
     RequiresParent,
     /// The provided file is not writable.
-    /// Since chd-rs does not implement CHD creation, this is unused.
+    /// This is only for C-compatibility purposes and is otherwise unused.
     FileNotWriteable,
     /// An error occurred when reading this CHD file.
     ReadError,
@@ -58,16 +63,14 @@ pub enum ChdError {
     /// This is only for C-compatibility purposes and is otherwise unused.
     CantVerify,
     /// The requested operation is not supported.
-    /// This is only for C-compatibility purposes and is otherwise unused.
     NotSupported,
-    /// The requested metadata was not found.
-    /// This is only used by the C API bindings.
-    MetadataNotFound,
+    /// The requested metadata was not found, with the tag of the metadata.
+    MetadataNotFound([u8; 4]),
     /// The metadata has an invalid size.
-    /// This is only for C-compatibility purposes and is otherwise unused.
     InvalidMetadataSize,
-    /// The CHD version of the provided file is not supported by this library.
-    UnsupportedVersion,
+    /// The CHD version of the provided file is not supported by this library, with the version
+    /// number of the file.
+    UnsupportedVersion(u32),
     /// Unable to verify the CHD completely.
     /// This is only for C-compatibility purposes and is otherwise unused.
     VerifyIncomplete,
@@ -86,6 +89,44 @@ pub enum ChdError {
     UnsupportedFormat,
     /// Unknown error.
     Unknown,
+    /// An error occurred when decoding a FLAC frame, with a description of the error.
+    Flac(String),
+    /// The compressed data ended before the requested amount of data could be decompressed.
+    TruncatedInput,
+    /// The CHD file declares a compression codec that is not supported, with the tag of the codec.
+    UnsupportedCodec(u32),
+    /// The CHD file is shorter than the data it references, with the expected and actual
+    /// length of the file in bytes.
+    TruncatedFile {
+        /// The length in bytes the file should be at least.
+        expected: u64,
+        /// The actual length of the file in bytes.
+        actual: u64,
+    },
+    /// The compressed data of a hunk continues past the end of the compressed stream.
+    /// This is only returned in strict mode.
+    TrailingData,
+    /// A metadata entry could not be parsed.
+    MetadataParse {
+        /// The tag of the metadata entry.
+        tag: [u8; 4],
+        /// A description of why the metadata entry could not be parsed.
+        detail: String,
+    },
+    /// The operation requires a capability that was not enabled when chd-rs was compiled,
+    /// with the name of the build feature that enables it.
+    UnsupportedFeature(&'static str),
+    /// The operation is not supported by the version of the CHD file.
+    UnsupportedForVersion {
+        /// The version number of the CHD file.
+        version: u32,
+        /// A description of the unsupported operation.
+        feature: &'static str,
+    },
+    /// An error occurred when decoding LZMA data, with a description of the error.
+    Lzma(String),
+    /// The CHD file was not opened read-write, or can not be opened read-write.
+    ReadOnly,
 }
 
 impl Error for ChdError {}
@@ -112,9 +153,13 @@ impl Display for ChdError {
             ChdError::CantCreateFile => f.write_str("can't create file"),
             ChdError::CantVerify => f.write_str("can't verify file"),
             ChdError::NotSupported => f.write_str("operation not supported"),
-            ChdError::MetadataNotFound => f.write_str("can't find metadata"),
+            ChdError::MetadataNotFound(tag) => {
+                write!(f, "can't find metadata '{}'", String::from_utf8_lossy(tag))
+            }
             ChdError::InvalidMetadataSize => f.write_str("invalid metadata size"),
-            ChdError::UnsupportedVersion => f.write_str("unsupported CHD version"),
+            ChdError::UnsupportedVersion(version) => {
+                write!(f, "unsupported CHD version {}", version)
+            }
             ChdError::VerifyIncomplete => f.write_str("incomplete verify"),
             ChdError::InvalidMetadata => f.write_str("invalid metadata"),
             ChdError::InvalidState => f.write_str("invalid state"),
@@ -122,6 +167,38 @@ impl Display for ChdError {
             ChdError::NoAsyncOperation => f.write_str("no async operation in progress"),
             ChdError::UnsupportedFormat => f.write_str("unsupported format"),
             ChdError::Unknown => f.write_str("undocumented error"),
+            ChdError::Flac(detail) => write!(f, "FLAC decode error: {}", detail),
+            ChdError::TruncatedInput => f.write_str("truncated compressed data"),
+            ChdError::UnsupportedCodec(tag) => {
+                let bytes = tag.to_be_bytes();
+                if bytes.iter().all(u8::is_ascii_graphic) {
+                    write!(f, "unsupported codec '{}'", String::from_utf8_lossy(&bytes))
+                } else {
+                    write!(f, "unsupported codec {:#x}", tag)
+                }
+            }
+            ChdError::TruncatedFile { expected, actual } => write!(
+                f,
+                "truncated file: expected at least {} bytes, found {}",
+                expected, actual
+            ),
+            ChdError::TrailingData => f.write_str("trailing data after compressed stream"),
+            ChdError::MetadataParse { tag, detail } => write!(
+                f,
+                "invalid metadata '{}': {}",
+                String::from_utf8_lossy(tag),
+                detail
+            ),
+            ChdError::UnsupportedFeature(feature) => write!(
+                f,
+                "unsupported operation: chd-rs must be built with the '{}' feature",
+                feature
+            ),
+            ChdError::UnsupportedForVersion { version, feature } => {
+                write!(f, "{} is not supported by CHD version {}", feature, version)
+            }
+            ChdError::Lzma(detail) => write!(f, "LZMA decode error: {}", detail),
+            ChdError::ReadOnly => f.write_str("file is read-only"),
         }
     }
 }
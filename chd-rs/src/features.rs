@@ -0,0 +1,57 @@
+use crate::header::CodecType;
+
+/// The optional capabilities of chd-rs that were enabled when it was compiled, as returned by
+/// [`features`](crate::features).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Features {
+    /// Whether the subcode data of CD-ROM hunks is decompressed (`want_subcode`).
+    pub want_subcode: bool,
+    /// Whether the sync header and ECC data of CD-ROM data sectors are regenerated
+    /// (`want_raw_data_sector`).
+    pub want_raw_data_sector: bool,
+    /// Whether FLAC hunks with a channel count other than 2 can be decompressed
+    /// (`nonstandard_channel_count`).
+    pub nonstandard_channel_count: bool,
+    /// Whether the checksum of each hunk is verified when it is read (`verify_block_crc`).
+    pub verify_block_crc: bool,
+    /// Whether the Huffman decoder API is public (`huffman_api`).
+    pub huffman_api: bool,
+    /// Whether the lending iterators over hunks and metadata are available
+    /// (`unstable_lending_iterators`).
+    pub unstable_lending_iterators: bool,
+    /// Whether CHD files can be opened by memory-mapping them (`mmap`).
+    pub mmap: bool,
+}
+
+const SUPPORTED_CODECS: &[CodecType] = &[
+    CodecType::None,
+    CodecType::Zlib,
+    CodecType::ZlibPlus,
+    CodecType::AV,
+    CodecType::ZLibV5,
+    CodecType::ZLibCdV5,
+    CodecType::LzmaCdV5,
+    CodecType::FlacCdV5,
+    CodecType::FlacV5,
+    CodecType::LzmaV5,
+    CodecType::AVHuffV5,
+    CodecType::HuffV5,
+];
+
+/// Returns the compression codecs that this build of chd-rs can decompress.
+pub fn supported_codecs() -> &'static [CodecType] {
+    SUPPORTED_CODECS
+}
+
+/// Returns the optional capabilities of chd-rs that were enabled when it was compiled.
+pub fn features() -> Features {
+    Features {
+        want_subcode: cfg!(feature = "want_subcode"),
+        want_raw_data_sector: cfg!(feature = "want_raw_data_sector"),
+        nonstandard_channel_count: cfg!(feature = "nonstandard_channel_count"),
+        verify_block_crc: cfg!(feature = "verify_block_crc"),
+        huffman_api: cfg!(feature = "huffman_api"),
+        unstable_lending_iterators: cfg!(feature = "unstable_lending_iterators"),
+        mmap: cfg!(feature = "mmap"),
+    }
+}
@@ -20,12 +20,11 @@ use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use once_cell::sync::OnceCell;
 use regex::bytes::Regex;
-use std::ffi::CStr;
 use std::io::{Cursor, Read, Seek, SeekFrom};
 
 /// The types of compression codecs supported in a CHD file.
 #[repr(u32)]
-#[derive(FromPrimitive, Debug)]
+#[derive(FromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CodecType {
     /// No compression.
     None = 0,
@@ -63,6 +62,35 @@ impl CodecType {
         }
     }
 
+    /// Returns whether or not the codec type is a CD-ROM codec, which compresses the sector
+    /// data and the subcode data of each frame as separate streams.
+    pub const fn is_cd(&self) -> bool {
+        matches!(
+            self,
+            CodecType::ZLibCdV5 | CodecType::LzmaCdV5 | CodecType::FlacCdV5
+        )
+    }
+
+    /// Returns an upper bound on the length of the compressed data of a hunk of the provided
+    /// hunk size compressed with this codec.
+    ///
+    /// See [`CodecImplementation::max_compressed_size`](crate::codecs::CodecImplementation::max_compressed_size).
+    pub fn max_compressed_size(&self, hunk_size: u32) -> usize {
+        match self {
+            CodecType::None => NoneCodec::max_compressed_size(hunk_size),
+            CodecType::Zlib | CodecType::ZlibPlus | CodecType::ZLibV5 => {
+                ZlibCodec::max_compressed_size(hunk_size)
+            }
+            CodecType::ZLibCdV5 => CdZlibCodec::max_compressed_size(hunk_size),
+            CodecType::LzmaCdV5 => CdLzmaCodec::max_compressed_size(hunk_size),
+            CodecType::FlacCdV5 => CdFlacCodec::max_compressed_size(hunk_size),
+            CodecType::LzmaV5 => LzmaCodec::max_compressed_size(hunk_size),
+            CodecType::FlacV5 => RawFlacCodec::max_compressed_size(hunk_size),
+            CodecType::HuffV5 => HuffmanCodec::max_compressed_size(hunk_size),
+            CodecType::AV | CodecType::AVHuffV5 => AVHuffCodec::max_compressed_size(hunk_size),
+        }
+    }
+
     /// Initializes the codec for the provided hunk size.
     pub(crate) fn init(&self, hunk_size: u32) -> Result<Box<dyn CompressionCodec>> {
         match self {
@@ -97,6 +125,27 @@ impl CodecType {
             _ => Err(ChdError::UnsupportedFormat),
         }
     }
+
+    /// Returns the numeric value of the codec type, as stored in the header of a CHD file.
+    ///
+    /// For V5 codecs, this is the `CHD_CODEC_*` constant of libchdr, which packs the 4 ASCII
+    /// bytes of the tag in big-endian order. For V1-4 codecs, this is the `CHDCOMPRESSION_*`
+    /// constant.
+    pub const fn as_u32(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl TryFrom<u32> for CodecType {
+    type Error = ChdError;
+
+    /// Converts the numeric value of a codec type, as returned by
+    /// [`CodecType::as_u32`](crate::header::CodecType::as_u32), into the codec type.
+    ///
+    /// If the value is not a known codec type, returns `ChdError::UnsupportedCodec`.
+    fn try_from(value: u32) -> Result<Self> {
+        CodecType::from_u32(value).ok_or(ChdError::UnsupportedCodec(value))
+    }
 }
 
 /// The CHD header version.
@@ -287,7 +336,7 @@ pub enum ChdHeader {
 }
 
 const MD5_BYTES: usize = 16;
-const SHA1_BYTES: usize = 20;
+pub(crate) const SHA1_BYTES: usize = 20;
 
 /// The CHD magic number.
 pub const CHD_MAGIC: &'static str = "MComprHD";
@@ -305,16 +354,48 @@ impl ChdHeader {
     /// Reads CHD header data from the provided stream.
     ///
     /// If the header is not valid, returns `ChdError::InvalidParameter`.
-    /// If the header indicates an unsupported compression format, returns `ChdError::UnsupportedFormat`
+    /// If the header declares a compression codec that is not supported,
+    /// returns `ChdError::UnsupportedCodec` with the tag of the codec.
     pub fn try_read_header<F: Read + Seek>(file: &mut F) -> Result<ChdHeader> {
-        let header = read_header(file)?;
-        if !header.validate() {
+        read_header(file)?.checked()
+    }
+
+    /// Reads CHD header data from the current position of the provided stream, without
+    /// seeking or reading the hunk map and metadata. Only as many bytes as the header of
+    /// the detected version are read.
+    ///
+    /// This is useful to quickly check whether a file is a CHD file. Since the unit size
+    /// of a V3-4 CHD file can only be determined from its metadata, it is assumed to be the
+    /// hunk size.
+    ///
+    /// If the stream does not begin with a CHD header, returns `ChdError::InvalidFile`.
+    /// If the CHD version is not supported, returns `ChdError::UnsupportedVersion` with the
+    /// version number. Otherwise the header is validated in the same way as
+    /// [`try_read_header`](ChdHeader::try_read_header).
+    pub fn from_reader<F: Read>(reader: &mut F) -> Result<ChdHeader> {
+        read_header_from(reader)?.checked()
+    }
+
+    /// Validates the header and the codecs it declares.
+    fn checked(self) -> Result<ChdHeader> {
+        if !self.validate() {
             return Err(ChdError::InvalidParameter);
         }
-        if !header.validate_compression() {
-            return Err(ChdError::UnsupportedFormat);
+        if let Some(tag) = self.unsupported_codec() {
+            return Err(ChdError::UnsupportedCodec(tag));
+        }
+        Ok(self)
+    }
+
+    /// Returns the version number of the CHD file.
+    pub fn version(&self) -> u32 {
+        match self {
+            ChdHeader::V1Header(c) => c.version as u32,
+            ChdHeader::V2Header(c) => c.version as u32,
+            ChdHeader::V3Header(c) => c.version as u32,
+            ChdHeader::V4Header(c) => c.version as u32,
+            ChdHeader::V5Header(c) => c.version as u32,
         }
-        Ok(header)
     }
 
     /// Returns whether or not the CHD file is compressed.
@@ -416,6 +497,39 @@ impl ChdHeader {
         }
     }
 
+    /// Returns the SHA1 hash of the CHD file, if the CHD version records one.
+    ///
+    /// For CHD V4 and above, this covers the raw data and all checksummed metadata.
+    pub fn sha1(&self) -> Option<[u8; SHA1_BYTES]> {
+        match self {
+            ChdHeader::V1Header(_) | ChdHeader::V2Header(_) => None,
+            ChdHeader::V3Header(c) => Some(c.sha1),
+            ChdHeader::V4Header(c) => Some(c.sha1),
+            ChdHeader::V5Header(c) => Some(c.sha1),
+        }
+    }
+
+    /// Returns the SHA1 hash of the parent CHD file, if the CHD version records one.
+    ///
+    /// This is only meaningful if [`has_parent`](ChdHeader::has_parent) returns true.
+    pub fn parent_sha1(&self) -> Option<[u8; SHA1_BYTES]> {
+        match self {
+            ChdHeader::V1Header(_) | ChdHeader::V2Header(_) => None,
+            ChdHeader::V3Header(c) => Some(c.parent_sha1),
+            ChdHeader::V4Header(c) => Some(c.parent_sha1),
+            ChdHeader::V5Header(c) => Some(c.parent_sha1),
+        }
+    }
+
+    /// Returns the SHA1 hash of the raw, uncompressed data, if the CHD version records one.
+    pub fn raw_sha1(&self) -> Option<[u8; SHA1_BYTES]> {
+        match self {
+            ChdHeader::V4Header(c) => Some(c.raw_sha1),
+            ChdHeader::V5Header(c) => Some(c.raw_sha1),
+            _ => None,
+        }
+    }
+
     /// Returns the length of the header.
     pub fn len(&self) -> u32 {
         match self {
@@ -427,6 +541,24 @@ impl ChdHeader {
         }
     }
 
+    /// Returns the compression codecs declared by the header, in the order they are declared.
+    ///
+    /// V1-4 CHD files declare a single codec. Unused codec slots of a V5 CHD file are skipped.
+    pub fn codec_types(&self) -> Vec<CodecType> {
+        match self {
+            ChdHeader::V1Header(c) => CodecType::from_u32(c.compression).into_iter().collect(),
+            ChdHeader::V2Header(c) => CodecType::from_u32(c.compression).into_iter().collect(),
+            ChdHeader::V3Header(c) => CodecType::from_u32(c.compression).into_iter().collect(),
+            ChdHeader::V4Header(c) => CodecType::from_u32(c.compression).into_iter().collect(),
+            ChdHeader::V5Header(c) => c
+                .compression
+                .iter()
+                .filter(|&&tag| tag != CodecType::None as u32)
+                .filter_map(|&tag| CodecType::from_u32(tag))
+                .collect(),
+        }
+    }
+
     pub(crate) fn create_compression_codecs(&self) -> Result<Vec<Box<dyn CompressionCodec>>> {
         match self {
             ChdHeader::V1Header(c) => CodecType::from_u32(c.compression)
@@ -513,18 +645,26 @@ impl ChdHeader {
         true
     }
 
-    /// Validate the compression types of the CHD file can be read.
-    fn validate_compression(&self) -> bool {
+    /// Returns the tag of the first compression codec of the CHD file that can not be read,
+    /// if any.
+    fn unsupported_codec(&self) -> Option<u32> {
         match self {
-            ChdHeader::V1Header(c) => ChdHeader::validate_legacy_compression(c.compression),
-            ChdHeader::V2Header(c) => ChdHeader::validate_legacy_compression(c.compression),
-            ChdHeader::V3Header(c) => ChdHeader::validate_legacy_compression(c.compression),
-            ChdHeader::V4Header(c) => ChdHeader::validate_legacy_compression(c.compression),
+            ChdHeader::V1Header(c) => {
+                Some(c.compression).filter(|&tag| !ChdHeader::validate_legacy_compression(tag))
+            }
+            ChdHeader::V2Header(c) => {
+                Some(c.compression).filter(|&tag| !ChdHeader::validate_legacy_compression(tag))
+            }
+            ChdHeader::V3Header(c) => {
+                Some(c.compression).filter(|&tag| !ChdHeader::validate_legacy_compression(tag))
+            }
+            ChdHeader::V4Header(c) => {
+                Some(c.compression).filter(|&tag| !ChdHeader::validate_legacy_compression(tag))
+            }
             ChdHeader::V5Header(c) => c
                 .compression
-                .map(ChdHeader::validate_v5_compression)
-                .iter()
-                .all(|&x| x),
+                .into_iter()
+                .find(|&tag| !ChdHeader::validate_v5_compression(tag)),
         }
     }
 
@@ -558,14 +698,40 @@ pub enum Flags {
 }
 
 fn read_header<T: Read + Seek>(chd: &mut T) -> Result<ChdHeader> {
+    chd.seek(SeekFrom::Start(0))?;
+    let mut header = read_header_from(chd)?;
+
+    // V3-4 headers do not record the unit size, so it has to be guessed from the metadata.
+    match &mut header {
+        ChdHeader::V3Header(v3) => {
+            if let Some(unit_bytes) = guess_unit_bytes(chd, v3.meta_offset) {
+                v3.unit_bytes = unit_bytes;
+                v3.unit_count = (v3.logical_bytes + unit_bytes as u64 - 1) / unit_bytes as u64;
+            }
+        }
+        ChdHeader::V4Header(v4) => {
+            if let Some(unit_bytes) = guess_unit_bytes(chd, v4.meta_offset) {
+                v4.unit_bytes = unit_bytes;
+                v4.unit_count = (v4.logical_bytes + unit_bytes as u64 - 1) / unit_bytes as u64;
+            }
+        }
+        _ => {}
+    }
+    Ok(header)
+}
+
+fn read_header_from<T: Read>(chd: &mut T) -> Result<ChdHeader> {
     let mut raw_header: [u8; CHD_MAX_HEADER_SIZE] = [0; CHD_MAX_HEADER_SIZE];
 
-    chd.seek(SeekFrom::Start(0))?;
-    chd.read_exact(&mut raw_header)?;
+    // A stream too short to hold a header is not a CHD file.
+    let read_exact = |chd: &mut T, buf: &mut [u8]| match chd.read_exact(buf) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(ChdError::InvalidFile),
+        res => res.map_err(ChdError::from),
+    };
 
-    let magic = CStr::from_bytes_with_nul(&raw_header[0..9])?.to_str()?;
-    if CHD_MAGIC != magic {
-        return Err(ChdError::InvalidData);
+    read_exact(chd, &mut raw_header[..16])?;
+    if &raw_header[0..8] != CHD_MAGIC.as_bytes() {
+        return Err(ChdError::InvalidFile);
     }
     let mut reader = Cursor::new(&raw_header);
     reader.seek(SeekFrom::Start(8))?;
@@ -574,29 +740,30 @@ fn read_header<T: Read + Seek>(chd: &mut T) -> Result<ChdHeader> {
 
     // ensure version is known and header size match up
     match (version, length) {
-        (1, CHD_V1_HEADER_SIZE) => Ok(ChdHeader::V1Header(read_v1_header(
+        (1, CHD_V1_HEADER_SIZE)
+        | (2, CHD_V2_HEADER_SIZE)
+        | (3, CHD_V3_HEADER_SIZE)
+        | (4, CHD_V4_HEADER_SIZE)
+        | (5, CHD_V5_HEADER_SIZE) => read_exact(chd, &mut raw_header[16..length as usize])?,
+        (1 | 2 | 3 | 4 | 5, _) => return Err(ChdError::InvalidData),
+        _ => return Err(ChdError::UnsupportedVersion(version)),
+    }
+
+    let mut reader = Cursor::new(&raw_header);
+    match version {
+        1 => Ok(ChdHeader::V1Header(read_v1_header(
             &mut reader,
             version,
             length,
         )?)),
-        (2, CHD_V2_HEADER_SIZE) => Ok(ChdHeader::V2Header(read_v1_header(
+        2 => Ok(ChdHeader::V2Header(read_v1_header(
             &mut reader,
             version,
             length,
         )?)),
-        (3, CHD_V3_HEADER_SIZE) => Ok(ChdHeader::V3Header(read_v3_header(
-            &mut reader,
-            length,
-            chd,
-        )?)),
-        (4, CHD_V4_HEADER_SIZE) => Ok(ChdHeader::V4Header(read_v4_header(
-            &mut reader,
-            length,
-            chd,
-        )?)),
-        (5, CHD_V5_HEADER_SIZE) => Ok(ChdHeader::V5Header(read_v5_header(&mut reader, length)?)),
-        (1 | 2 | 3 | 4 | 5, _) => Err(ChdError::InvalidData),
-        _ => Err(ChdError::UnsupportedVersion),
+        3 => Ok(ChdHeader::V3Header(read_v3_header(&mut reader, length)?)),
+        4 => Ok(ChdHeader::V4Header(read_v4_header(&mut reader, length)?)),
+        _ => Ok(ChdHeader::V5Header(read_v5_header(&mut reader, length)?)),
     }
 }
 
@@ -638,7 +805,7 @@ fn read_v1_header<T: Read + Seek>(header: &mut T, version: u32, length: u32) ->
         version: match version {
             1 => Version::ChdV1,
             2 => Version::ChdV2,
-            _ => return Err(ChdError::UnsupportedVersion),
+            _ => return Err(ChdError::UnsupportedVersion(version)),
         },
         length,
         flags,
@@ -658,11 +825,7 @@ fn read_v1_header<T: Read + Seek>(header: &mut T, version: u32, length: u32) ->
     })
 }
 
-fn read_v3_header<T: Read + Seek, F: Read + Seek>(
-    header: &mut T,
-    length: u32,
-    chd: &mut F,
-) -> Result<HeaderV3> {
+fn read_v3_header<T: Read + Seek>(header: &mut T, length: u32) -> Result<HeaderV3> {
     header.seek(SeekFrom::Start(16))?;
     let mut md5: [u8; MD5_BYTES] = [0; MD5_BYTES];
     let mut parent_md5: [u8; MD5_BYTES] = [0; MD5_BYTES];
@@ -680,7 +843,7 @@ fn read_v3_header<T: Read + Seek, F: Read + Seek>(
     header.seek(SeekFrom::Start(80))?;
     header.read_exact(&mut sha1)?;
     header.read_exact(&mut parent_sha1)?;
-    let unit_bytes = guess_unit_bytes(chd, meta_offset).unwrap_or(hunk_bytes);
+    let unit_bytes = hunk_bytes;
     let unit_count = (logical_bytes + (unit_bytes as u64) - 1) / unit_bytes as u64;
     Ok(HeaderV3 {
         version: Version::ChdV3,
@@ -700,11 +863,7 @@ fn read_v3_header<T: Read + Seek, F: Read + Seek>(
     })
 }
 
-fn read_v4_header<T: Read + Seek, F: Read + Seek>(
-    header: &mut T,
-    length: u32,
-    chd: &mut F,
-) -> Result<HeaderV4> {
+fn read_v4_header<T: Read + Seek>(header: &mut T, length: u32) -> Result<HeaderV4> {
     header.seek(SeekFrom::Start(16))?;
     let mut sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
     let mut parent_sha1: [u8; SHA1_BYTES] = [0; SHA1_BYTES];
@@ -722,7 +881,7 @@ fn read_v4_header<T: Read + Seek, F: Read + Seek>(
     header.read_exact(&mut parent_sha1)?;
     header.read_exact(&mut raw_sha1)?;
 
-    let unit_bytes = guess_unit_bytes(chd, meta_offset).unwrap_or(hunk_bytes);
+    let unit_bytes = hunk_bytes;
     let unit_count = (logical_bytes + unit_bytes as u64 - 1) / unit_bytes as u64;
     Ok(HeaderV4 {
         version: Version::ChdV4,
@@ -741,24 +900,28 @@ fn read_v4_header<T: Read + Seek, F: Read + Seek>(
     })
 }
 
-fn guess_unit_bytes<F: Read + Seek>(chd: &mut F, off: u64) -> Option<u32> {
+/// Parses the bytes per sector from the text of a hard disk geometry metadata entry.
+pub(crate) fn hard_disk_bps(value: &[u8]) -> Option<u32> {
     static RE_BPS: OnceCell<Regex> = OnceCell::new();
     let bps_regex: &'static Regex = RE_BPS.get_or_init(|| Regex::new(r"(?-u)(BPS:)(\d+)").unwrap());
 
+    bps_regex
+        .captures(value)
+        .and_then(|c| c.get(2))
+        .map(|c| c.as_bytes())
+        .and_then(|c| std::str::from_utf8(c).ok())
+        .and_then(|c| c.parse::<u32>().ok())
+}
+
+fn guess_unit_bytes<F: Read + Seek>(chd: &mut F, off: u64) -> Option<u32> {
     let metas: Vec<_> = MetadataRefIter::from_stream(chd, off).collect();
     if let Some(hard_disk) = metas
         .iter()
         .find(|&e| e.metatag() == KnownMetadata::HardDisk as u32)
     {
         if let Ok(text) = hard_disk.read(chd) {
-            let caps = bps_regex
-                .captures(&text.value)
-                .and_then(|c| c.get(1))
-                .map(|c| c.as_bytes())
-                .and_then(|c| std::str::from_utf8(c).ok())
-                .and_then(|c| c.parse::<u32>().ok());
             // Only return this if we can parse it properly. Fallback to cdrom otherwise.
-            if let Some(bps) = caps {
+            if let Some(bps) = hard_disk_bps(&text.value) {
                 return Some(bps);
             }
         }
@@ -799,7 +962,7 @@ fn read_v5_header<T: Read + Seek>(header: &mut T, length: u32) -> Result<HeaderV
         // uncompressed map entries are 4 bytes long
         Some(CodecType::None) => map::V5_UNCOMPRESSED_MAP_ENTRY_SIZE as u32,
         Some(_) => map::V5_COMPRESSED_MAP_ENTRY_SIZE as u32,
-        None => return Err(ChdError::UnsupportedFormat),
+        None => return Err(ChdError::UnsupportedCodec(compression[0])),
     };
 
     Ok(HeaderV5 {
@@ -2,6 +2,7 @@
 //! Implementation of the MAME CHD Huffman Decoder.
 //!
 //! For format descriptions, see [huffman.cpp](https://github.com/mamedev/mame/blob/master/src/lib/util/huffman.cpp).
+use crate::bitwriter::BitWriter;
 use crate::const_assert;
 use bitreader::{BitReader, BitReaderError};
 use std::cmp::Ordering;
@@ -135,14 +136,19 @@ const fn rle_full_bits<const NUM_CODES: usize>() -> u8 {
     full_bits
 }
 
-impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usize>
-    HuffmanDecoder<'a, NUM_CODES, MAX_BITS, LOOKUP_ARRAY_LEN>
-{
-    const RLE_NUM_BITS: u8 = match MAX_BITS {
+/// Get the number of bits used to encode each code length of an RLE encoded Huffman tree.
+const fn rle_num_bits(max_bits: u8) -> u8 {
+    match max_bits {
         0..=7 => 3,  // < 8
         8..=15 => 4, // >= 8
         _ => 5,      // >= 16
-    };
+    }
+}
+
+impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usize>
+    HuffmanDecoder<'a, NUM_CODES, MAX_BITS, LOOKUP_ARRAY_LEN>
+{
+    const RLE_NUM_BITS: u8 = rle_num_bits(MAX_BITS);
 
     const RLE_FULL_BITS: u8 = rle_full_bits::<NUM_CODES>();
 
@@ -191,7 +197,7 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         }
 
         let mut decoder = HuffmanDecoder::new();
-        Self::assign_canonical_codes(&mut huffnode_array)?;
+        assign_canonical_codes::<NUM_CODES, MAX_BITS>(&mut huffnode_array)?;
         decoder.build_lookup_table(&huffnode_array);
 
         Ok(decoder)
@@ -215,9 +221,7 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
             }
         }
 
-        HuffmanDecoder::<24, 6, { lookup_len::<6>() }>::assign_canonical_codes(
-            &mut huffnode_array,
-        )?;
+        assign_canonical_codes::<24, 6>(&mut huffnode_array)?;
         small_huf.build_lookup_table(&huffnode_array);
 
         // Process the rest of the data referring to the small tree.
@@ -251,7 +255,7 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
             return Err(HuffmanError::InvalidData);
         }
 
-        Self::assign_canonical_codes(&mut huffnode_array)?;
+        assign_canonical_codes::<NUM_CODES, MAX_BITS>(&mut huffnode_array)?;
         new_huffman.build_lookup_table(&huffnode_array);
 
         Ok(new_huffman)
@@ -275,47 +279,6 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         Ok(lookup as u32 >> 5)
     }
 
-    fn assign_canonical_codes(
-        huffnode_array: &mut [HuffmanNode<'a>; NUM_CODES],
-    ) -> Result<(), HuffmanError> {
-        let mut curr_start = 0;
-
-        // Since we're read-only we don't need to keep the histogram around
-        // once we're done here.
-        let mut histogram = [0u32; 33];
-
-        // Fill in histogram of bit lengths.
-        for curr_code in 0..NUM_CODES {
-            let node = &huffnode_array[curr_code];
-            if node.num_bits > MAX_BITS {
-                return Err(HuffmanError::InternalInconsistency);
-            }
-            if node.num_bits <= 32 {
-                histogram[node.num_bits as usize] += 1;
-            }
-        }
-
-        // Determine starting code number of code lengths.
-        for code_len in (1..33).rev() {
-            let next_start = (curr_start + histogram[code_len]) >> 1;
-            if code_len != 1 && next_start * 2 != (curr_start + histogram[code_len]) {
-                return Err(HuffmanError::InternalInconsistency);
-            }
-            histogram[code_len] = curr_start;
-            curr_start = next_start
-        }
-
-        // Assign codes.
-        for curr_code in 0..NUM_CODES {
-            let node = &mut huffnode_array[curr_code];
-            if node.num_bits > 0 {
-                node.bits = histogram[node.num_bits as usize];
-                histogram[node.num_bits as usize] += 1;
-            }
-        }
-        Ok(())
-    }
-
     const fn make_lookup(code: u16, bits: u8) -> LookupValue {
         ((code) << 5) | ((bits as u16) & 0x1f)
     }
@@ -339,3 +302,168 @@ impl<'a, const NUM_CODES: usize, const MAX_BITS: u8, const LOOKUP_ARRAY_LEN: usi
         }
     }
 }
+
+/// CHD Huffman encoder, the counterpart of [`HuffmanDecoder`].
+///
+/// `MAX_BITS` must be large enough to hold a code for each of the `NUM_CODES`.
+pub(crate) struct HuffmanEncoder<'a, const NUM_CODES: usize, const MAX_BITS: u8> {
+    huffnode_array: [HuffmanNode<'a>; NUM_CODES],
+}
+
+impl<'a, const NUM_CODES: usize, const MAX_BITS: u8> HuffmanEncoder<'a, NUM_CODES, MAX_BITS> {
+    const RLE_NUM_BITS: u8 = rle_num_bits(MAX_BITS);
+
+    pub(crate) fn new() -> Self {
+        HuffmanEncoder {
+            huffnode_array: [HuffmanNode::default(); NUM_CODES],
+        }
+    }
+
+    /// Count a single occurrence of the code in the histogram.
+    pub(crate) fn histo_one(&mut self, code: u32) {
+        self.huffnode_array[code as usize].weight += 1;
+    }
+
+    /// Compute the code lengths and canonical codes from the histogram.
+    pub(crate) fn compute_tree_from_histo(&mut self) -> Result<(), HuffmanError> {
+        let mut weights = [0u64; NUM_CODES];
+        for (weight, node) in weights.iter_mut().zip(self.huffnode_array.iter()) {
+            *weight = node.weight as u64;
+        }
+
+        // Flatten the histogram until the codes fit. This terminates at the latest once
+        // all weights are equal.
+        let code_lengths = loop {
+            let code_lengths = Self::code_lengths(&weights);
+            if code_lengths.iter().all(|&len| len <= MAX_BITS as usize) {
+                break code_lengths;
+            }
+            for weight in weights.iter_mut().filter(|weight| **weight > 0) {
+                *weight = (*weight + 1) / 2;
+            }
+        };
+
+        for (node, len) in self.huffnode_array.iter_mut().zip(code_lengths) {
+            node.num_bits = len as u8;
+        }
+        assign_canonical_codes::<NUM_CODES, MAX_BITS>(&mut self.huffnode_array)
+    }
+
+    /// Returns the code length of each code of an unlimited Huffman tree built from the weights.
+    fn code_lengths(weights: &[u64; NUM_CODES]) -> [usize; NUM_CODES] {
+        let mut code_lengths = [0; NUM_CODES];
+        let mut node_weights = weights.to_vec();
+        let mut parents = vec![None; NUM_CODES];
+        let mut active: Vec<usize> = (0..NUM_CODES).filter(|&code| weights[code] > 0).collect();
+
+        // A lone code still needs a bit to be decodable.
+        if let [code] = active[..] {
+            code_lengths[code] = 1;
+            return code_lengths;
+        }
+
+        // Join the two lightest nodes until only the root is left.
+        while active.len() > 1 {
+            active.sort_by(|&a, &b| node_weights[b].cmp(&node_weights[a]));
+            let (first, second) = (active.pop().unwrap(), active.pop().unwrap());
+            let node = node_weights.len();
+            node_weights.push(node_weights[first] + node_weights[second]);
+            parents.push(None);
+            parents[first] = Some(node);
+            parents[second] = Some(node);
+            active.push(node);
+        }
+
+        for (code, len) in code_lengths.iter_mut().enumerate() {
+            let mut node = code;
+            while let Some(parent) = parents[node] {
+                *len += 1;
+                node = parent;
+            }
+        }
+        code_lengths
+    }
+
+    /// Export the Huffman tree as RLE encoded code lengths, as read by
+    /// [`HuffmanDecoder::from_tree_rle`](HuffmanDecoder::from_tree_rle).
+    pub(crate) fn export_tree_rle(&self, writer: &mut BitWriter) {
+        let num_bits = Self::RLE_NUM_BITS;
+        let mut curr_code = 0;
+        while curr_code < NUM_CODES {
+            let node_bits = self.huffnode_array[curr_code].num_bits;
+            let rep_count = self.huffnode_array[curr_code..]
+                .iter()
+                .take_while(|node| node.num_bits == node_bits)
+                .count();
+
+            // 1 is an escape code, so repeats of 1 can not be encoded.
+            if node_bits == 1 {
+                writer.write(1, num_bits);
+                writer.write(1, num_bits);
+                curr_code += 1;
+            } else if rep_count >= 3 {
+                let rep_count = rep_count.min(3 + (1 << num_bits) - 1);
+                writer.write(1, num_bits);
+                writer.write(node_bits as u64, num_bits);
+                writer.write((rep_count - 3) as u64, num_bits);
+                curr_code += rep_count;
+            } else {
+                writer.write(node_bits as u64, num_bits);
+                curr_code += 1;
+            }
+        }
+    }
+
+    /// Encode a single code to the Huffman bitstream.
+    pub(crate) fn encode_one(&self, writer: &mut BitWriter, code: u32) -> Result<(), HuffmanError> {
+        let node = self
+            .huffnode_array
+            .get(code as usize)
+            .filter(|node| node.num_bits > 0)
+            .ok_or(HuffmanError::InvalidData)?;
+        writer.write(node.bits as u64, node.num_bits);
+        Ok(())
+    }
+}
+
+/// Assign canonical codes to the nodes of a Huffman tree from their code lengths.
+fn assign_canonical_codes<const NUM_CODES: usize, const MAX_BITS: u8>(
+    huffnode_array: &mut [HuffmanNode<'_>; NUM_CODES],
+) -> Result<(), HuffmanError> {
+    let mut curr_start = 0;
+
+    // Since we're read-only we don't need to keep the histogram around
+    // once we're done here.
+    let mut histogram = [0u32; 33];
+
+    // Fill in histogram of bit lengths.
+    for curr_code in 0..NUM_CODES {
+        let node = &huffnode_array[curr_code];
+        if node.num_bits > MAX_BITS {
+            return Err(HuffmanError::InternalInconsistency);
+        }
+        if node.num_bits <= 32 {
+            histogram[node.num_bits as usize] += 1;
+        }
+    }
+
+    // Determine starting code number of code lengths.
+    for code_len in (1..33).rev() {
+        let next_start = (curr_start + histogram[code_len]) >> 1;
+        if code_len != 1 && next_start * 2 != (curr_start + histogram[code_len]) {
+            return Err(HuffmanError::InternalInconsistency);
+        }
+        histogram[code_len] = curr_start;
+        curr_start = next_start
+    }
+
+    // Assign codes.
+    for curr_code in 0..NUM_CODES {
+        let node = &mut huffnode_array[curr_code];
+        if node.num_bits > 0 {
+            node.bits = histogram[node.num_bits as usize];
+            histogram[node.num_bits as usize] += 1;
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,8 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_cfg_hide))]
 // `unsound_owning_iterators` is quite literally unsound, so we don't need to condition on that.
-#![forbid(unsafe_code)]
+// Memory-mapping a file is the only unsafe operation, and is only allowed where it is done.
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
 
 //! An implementation of the MAME CHD (Compressed Hunks of Data) format in pure Safe Rust, with support
 //! for CHD V1-5.
@@ -73,12 +75,21 @@
 //! let entries = chd.metadata_refs()?;
 //! let metadatas: Vec<ChdMetadata> = entries.try_into()?;
 //!```
+//!
+//! ## Memory-mapped files
+//! With the `mmap` feature, [`ChdFile::open_path_mmap`](crate::ChdFile::open_path_mmap) opens a
+//! CHD file by memory-mapping it. Mapping a file requires unsafe code, so the feature is not
+//! enabled by default. The file must not be modified while it is mapped.
 mod error;
 
+mod bitwriter;
 mod block_hash;
-mod cdrom;
+mod cache;
 mod chdfile;
 mod compression;
+mod features;
+mod parent;
+mod readahead;
 
 #[cfg(feature = "huffman_api")]
 pub mod huffman;
@@ -86,7 +97,6 @@ pub mod huffman;
 #[cfg(not(feature = "huffman_api"))]
 mod huffman;
 
-#[cfg(feature = "codec_api")]
 /// Implementations of decompression codecs used in MAME CHD.
 ///
 /// Each codec may have restrictions on the hunk size, lengths and contents
@@ -98,7 +108,25 @@ mod huffman;
 /// CD-ROM wrapped codecs that use Deflate to decompress subcode data, the codec implementations
 /// do not check the length of the output buffer against the hunk size. It is up to the caller
 /// of [`decompress`](crate::codecs::CodecImplementation::decompress) to uphold length invariants.
-#[cfg_attr(docsrs, doc(cfg(codec_api)))]
+///
+/// Codecs can be used on their own to decompress hunks that were extracted from a CHD file.
+/// ```rust
+/// use chd::codecs::{CodecImplementation, LzmaCodec};
+///
+/// // A hunk of 4096 bytes compressed with LZMA.
+/// let hunk: [u8; 45] = [
+///     0, 0, 2, 13, 1, 50, 145, 32, 218, 112, 4, 224, 63, 181, 136, 209, 149, 180, 145, 254, 197,
+///     25, 225, 157, 168, 88, 160, 91, 207, 178, 60, 170, 66, 145, 197, 30, 179, 38, 114, 199,
+///     255, 254, 135, 184, 0,
+/// ];
+///
+/// let mut codec = LzmaCodec::new(4096)?;
+/// let mut output = vec![0u8; 4096];
+/// let res = codec.decompress(&hunk, &mut output)?;
+/// assert_eq!(res.total_out(), 4096);
+/// assert!(output.iter().enumerate().all(|(i, &b)| b == (i * 7 % 13) as u8));
+/// # Ok::<(), chd::ChdError>(())
+/// ```
 pub mod codecs {
     pub use crate::compression::codecs::*;
     pub use crate::compression::{
@@ -125,12 +153,17 @@ macro_rules! const_assert {
 
 pub(crate) use const_assert;
 
-pub use chdfile::{ChdFile, ChdHunk};
+pub use chdfile::{ChdFile, ChdHunk, ChdKind, OpenMode, ReadSeek};
+pub use compression::decompress_hunk_blob;
 pub use error::{ChdError, Result};
+pub use features::{features, supported_codecs, Features};
+pub use parent::find_parent;
+pub mod cdrom;
 pub mod header;
 pub mod map;
 pub mod metadata;
 pub mod read;
+pub mod verify;
 
 #[cfg(feature = "unstable_lending_iterators")]
 #[cfg_attr(docsrs, doc(cfg(unstable_lending_iterators)))]
@@ -138,16 +171,45 @@ pub mod iter;
 
 #[cfg(test)]
 mod tests {
-    use crate::metadata::ChdMetadata;
-    use crate::read::{ChdFileReader, ChdHunkBufReader};
-    use crate::ChdFile;
+    use crate::cdrom::{
+        deinterleave_subcode, sector_type, track_layout, verify_edc, CdSubcodeType, CdToc,
+        CdTrackType, Sector, SectorType, TrackPlacement, CD_FRAME_SIZE, CD_MAX_SECTOR_DATA,
+        CD_MAX_SUBCODE_DATA, CD_SYNC_HEADER,
+    };
+    use crate::compression::codecs::{
+        CdFlacCodec, CdZlibCodec, LzmaCodec, RawFlacCodec, ZlibCodec,
+    };
+    use crate::compression::flac::FlacCodec;
+    use crate::compression::{CodecImplementation, DecompressResult};
+    use crate::header::{ChdHeader, CodecType, Flags};
+    use crate::map::{ChdMap, CodecStats, HunkCompression, V5CompressionType};
+    use crate::metadata::{ChdMetadata, ChdMetadataTag, KnownMetadata};
+    use crate::read::{CachedReader, ChdFileReader, ChdHunkBufReader};
+    use crate::verify::{SubcodeChecker, SubcodeReport, VerifyReport};
+    use crate::{
+        decompress_hunk_blob, features, find_parent, make_tag, supported_codecs, ChdError, ChdFile,
+        ChdKind, OpenMode, ReadSeek,
+    };
+    use byteorder::BigEndian;
+    use crc::{Crc, CRC_16_GSM, CRC_16_UMTS, CRC_32_ISO_HDLC, CRC_8_SMBUS};
+    use sha1::{Digest, Sha1};
     use std::convert::TryInto;
     use std::fs::File;
-    use std::io::{BufReader, Read, Write};
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
 
     #[cfg(feature = "unstable_lending_iterators")]
     use crate::iter::LendingIterator;
 
+    #[test]
+    fn send_test() {
+        fn assert_send<T: Send>() {}
+        assert_send::<ChdFile<BufReader<File>>>();
+        assert_send::<ChdFile<Cursor<Vec<u8>>>>();
+        assert_send::<LzmaCodec>();
+        assert_send::<CdFlacCodec>();
+        assert_send::<RawFlacCodec>();
+    }
+
     #[test]
     fn read_metas_test() {
         let mut f = File::open(".testimages/Test.chd").expect("");
@@ -161,6 +223,85 @@ mod tests {
         println!("{:?}", meta_datas);
     }
 
+    #[test]
+    fn metadata_by_tag_test() {
+        let mut f = File::open(".testimages/Test.chd").expect("");
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+
+        let track_count = chd
+            .metadata_refs()
+            .filter(|m| m.metatag() == KnownMetadata::CdRomTrack.metatag())
+            .count();
+        let tracks: Vec<_> = chd.metadata_by_tag(*b"CHTR").collect();
+        assert_eq!(tracks.len(), track_count);
+        for (index, track) in tracks.iter().enumerate() {
+            let track = track
+                .read(chd.inner())
+                .expect("could not read track metadata");
+            assert_eq!(track.index as usize, index);
+        }
+    }
+
+    #[test]
+    fn oversized_output_test() {
+        let (chd, data) = uncompressed_chd(2 * 4096, 4096);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut cmp_buf = Vec::new();
+        let mut out = vec![0xaa; 4096 + 100];
+        let read = chd
+            .hunk(1)
+            .expect("hunk")
+            .read_hunk_in(&mut cmp_buf, &mut out)
+            .expect("could not read hunk");
+        assert_eq!(read, 4096);
+        assert_eq!(out[..4096], data[4096..]);
+        assert!(out[4096..].iter().all(|&b| b == 0xaa));
+
+        // A buffer shorter than the hunk is still rejected.
+        let mut out = vec![0u8; 4095];
+        assert_eq!(
+            chd.hunk(1)
+                .expect("hunk")
+                .read_hunk_in(&mut cmp_buf, &mut out),
+            Err(ChdError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn metadata_by_tag_index_test() {
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        let meta_offset = chd.len();
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHTR", b"TRACK:1\0"),
+                (b"IDNT", b"IDENTIFY"),
+                (b"CHTR", b"TRACK:2\0"),
+                (b"CHTR", b"TRACK:3\0"),
+            ],
+        );
+        // Only the second track entry is checksummed.
+        chd[meta_offset + 2 * 24 + 4] = 0x01;
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let flags: Vec<_> = chd
+            .metadata_by_tag(*b"CHTR")
+            .map(|metadata| (metadata.index(), metadata.flags()))
+            .collect();
+        assert_eq!(flags, [(0, 0), (1, 0x01), (2, 0)]);
+
+        let track = chd
+            .metadata_by_tag_index(*b"CHTR", 1)
+            .expect("could not read track metadata");
+        assert_eq!(track.value, b"TRACK:2\0");
+        assert_eq!(track.flags, 0x01);
+        assert_eq!(track.index, 1);
+        assert_eq!(
+            chd.metadata_by_tag_index(*b"CHTR", 3).err(),
+            Some(ChdError::MetadataNotFound(*b"CHTR"))
+        );
+    }
+
     #[test]
     fn read_hunk_buffer_test() {
         let mut f = BufReader::new(File::open(".testimages/cliffhgr.chd").expect(""));
@@ -193,6 +334,2917 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_hunk_range_test() {
+        let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+        let hunk_size = chd.header().hunk_size() as usize;
+
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        let mut hunk = chd.hunk(0).expect("could not acquire hunk");
+        hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+            .expect("could not read_hunk 0");
+
+        let mut range = [0u8; 64];
+        chd.read_hunk_range(0, 100, &mut range)
+            .expect("could not read hunk range");
+        assert_eq!(&hunk_buf[100..164], &range);
+
+        // second read within the same hunk comes from the cache.
+        chd.read_hunk_range(0, hunk_size - 64, &mut range)
+            .expect("could not read hunk range");
+        assert_eq!(&hunk_buf[hunk_size - 64..], &range);
+
+        assert!(chd.read_hunk_range(0, hunk_size - 32, &mut range).is_err());
+    }
+
+    #[test]
+    fn flac_error_detail_test() {
+        let mut codec = RawFlacCodec::new(4096).expect("codec");
+        let mut output = vec![0u8; 4096];
+        // 'L' selects little endian output, the remainder is not a FLAC frame.
+        let err = codec
+            .decompress(b"L\x00\x01\x02\x03 not a flac frame", &mut output)
+            .expect_err("corrupt FLAC data should not decompress");
+        match err {
+            ChdError::Flac(detail) => assert!(!detail.is_empty()),
+            e => panic!("expected a FLAC error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn lzma_error_detail_test() {
+        let mut codec = LzmaCodec::new(4096).expect("codec");
+        let mut output = vec![0u8; 4096];
+        // The range coder starts at the top of its range, which decodes a repeated match
+        // before any data has been decoded.
+        let mut corrupt = vec![0x00, 0xff, 0xff, 0xff, 0xfe];
+        corrupt.resize(64, 0xff);
+        let err = codec
+            .decompress(&corrupt, &mut output)
+            .expect_err("corrupt LZMA data should not decompress");
+        match &err {
+            ChdError::Lzma(detail) => assert!(!detail.is_empty()),
+            e => panic!("expected an LZMA error, got {:?}", e),
+        }
+        assert!(err.to_string().starts_with("LZMA decode error: "));
+    }
+
+    /// Builds a FLAC frame of `block_size` stereo 16-bit samples with constant subframes.
+    fn flac_constant_frame(frame_num: u8, block_size: u16, left: i16, right: i16) -> Vec<u8> {
+        const FLAC_CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+        const FLAC_CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_UMTS);
+
+        // sync code, 8 bit block size at end of header, 44.1kHz,
+        // 2 independent channels, 16 bits per sample.
+        let mut frame = vec![0xff, 0xf8, 0x69, 0x18, frame_num, (block_size - 1) as u8];
+        frame.push(FLAC_CRC8.checksum(&frame));
+        for sample in [left, right] {
+            // constant subframe, no wasted bits.
+            frame.push(0x00);
+            frame.extend_from_slice(&sample.to_be_bytes());
+        }
+        let crc = FLAC_CRC16.checksum(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        frame
+    }
+
+    /// Builds a FLAC frame of stereo 16-bit samples with verbatim subframes.
+    fn flac_verbatim_frame(frame_num: u8, samples: &[(i16, i16)]) -> Vec<u8> {
+        const FLAC_CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+        const FLAC_CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_UMTS);
+
+        let mut frame = vec![0xff, 0xf8, 0x69, 0x18, frame_num, (samples.len() - 1) as u8];
+        frame.push(FLAC_CRC8.checksum(&frame));
+        // verbatim subframe, no wasted bits.
+        frame.push(0x02);
+        frame.extend(samples.iter().flat_map(|(l, _)| l.to_be_bytes()));
+        frame.push(0x02);
+        frame.extend(samples.iter().flat_map(|(_, r)| r.to_be_bytes()));
+        let crc = FLAC_CRC16.checksum(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn flac_channels_test() {
+        // A single channel takes 2 bytes per sample.
+        assert!(FlacCodec::<BigEndian, 1>::new(4096).is_ok());
+        assert!(matches!(
+            FlacCodec::<BigEndian, 1>::new(4095),
+            Err(ChdError::CodecError)
+        ));
+        assert!(matches!(
+            FlacCodec::<BigEndian, 2>::new(4094),
+            Err(ChdError::CodecError)
+        ));
+    }
+
+    #[cfg(not(feature = "nonstandard_channel_count"))]
+    #[test]
+    fn flac_mono_test() {
+        const FLAC_CRC8: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+        const FLAC_CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_UMTS);
+
+        // sync code, 8 bit block size at end of header, 44.1kHz,
+        // 1 channel, 16 bits per sample.
+        let mut frame = vec![b'B', 0xff, 0xf8, 0x69, 0x08, 0x00, 0xff];
+        frame.push(FLAC_CRC8.checksum(&frame[1..]));
+        // constant subframe, no wasted bits.
+        frame.push(0x00);
+        frame.extend_from_slice(&0x1234i16.to_be_bytes());
+        let crc = FLAC_CRC16.checksum(&frame[1..]);
+        frame.extend_from_slice(&crc.to_be_bytes());
+
+        let mut codec = RawFlacCodec::new(1024).expect("codec");
+        let mut output = vec![0u8; 1024];
+        assert_eq!(
+            codec.decompress(&frame, &mut output).err(),
+            Some(ChdError::UnsupportedFeature("nonstandard_channel_count"))
+        );
+    }
+
+    #[test]
+    fn flac_interleave_test() {
+        let samples: Vec<(i16, i16)> = (0..768i32)
+            .map(|i| ((i * 337 - 20000) as i16, (i * -91 + 3) as i16))
+            .collect();
+        let frames: Vec<u8> = samples
+            .chunks(256)
+            .enumerate()
+            .flat_map(|(frame_num, block)| flac_verbatim_frame(frame_num as u8, block))
+            .collect();
+
+        let mut codec = RawFlacCodec::new(768 * 4).expect("codec");
+        for (order, to_bytes) in [
+            (b'B', i16::to_be_bytes as fn(i16) -> [u8; 2]),
+            (b'L', i16::to_le_bytes as fn(i16) -> [u8; 2]),
+        ] {
+            let expected: Vec<u8> = samples
+                .iter()
+                .flat_map(|&(l, r)| to_bytes(l).into_iter().chain(to_bytes(r)))
+                .collect();
+            let mut input = vec![order];
+            input.extend_from_slice(&frames);
+            let mut output = vec![0u8; 768 * 4];
+            let res = codec.decompress(&input, &mut output).expect("decompress");
+            assert_eq!(res.total_out(), output.len());
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn flac_short_input_test() {
+        let mut codec = RawFlacCodec::new(4096).expect("codec");
+        let mut input = vec![b'B'];
+        input.extend(flac_constant_frame(0, 256, 0x1234, -2));
+        input.extend(flac_constant_frame(1, 256, 0x1234, -2));
+
+        // 512 samples are only enough for half of the hunk.
+        let mut output = vec![0u8; 4096];
+        match codec.decompress(&input, &mut output) {
+            Err(ChdError::TruncatedInput) => {}
+            res => panic!("expected truncated input, got {:?}", res),
+        }
+
+        // A short final hunk is decompressed up to its logical length.
+        let res = codec
+            .decompress(&input, &mut output[..2048])
+            .expect("short stream should fill its logical length");
+        assert_eq!(res.total_out(), 2048);
+        assert_eq!(res.total_in(), input.len());
+        assert!(output[..2048]
+            .chunks_exact(4)
+            .all(|sample| sample == [0x12, 0x34, 0xff, 0xfe]));
+
+        // A frame cut off in the middle is truncated regardless of the output length.
+        input.truncate(input.len() - 4);
+        match codec.decompress(&input, &mut output[..2048]) {
+            Err(ChdError::TruncatedInput) => {}
+            res => panic!("expected truncated input, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn flac_short_final_hunk_test() {
+        // The final hunk holds 500 samples, which end in the middle of the second FLAC block,
+        // and the third block is cut off.
+        let logical_len = 2000;
+        let mut compressed = vec![b'B'];
+        compressed.extend(flac_constant_frame(0, 256, 0x1234, -2));
+        compressed.extend(flac_constant_frame(1, 256, 0x1234, -2));
+        compressed.extend(&flac_constant_frame(2, 256, 0x1234, -2)[..8]);
+
+        let mut expected = vec![0u8; 4096];
+        expected[..logical_len]
+            .chunks_exact_mut(4)
+            .for_each(|sample| sample.copy_from_slice(&[0x12, 0x34, 0xff, 0xfe]));
+        let data = vec![0x5au8; 4096];
+
+        let mut raw_map = Vec::new();
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionNone,
+            4096,
+            124,
+            crate::block_hash::CRC16.checksum(&data),
+        );
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType0,
+            compressed.len() as u32,
+            124 + 4096,
+            crate::block_hash::CRC16.checksum(&expected),
+        );
+
+        let mut chd = v5_header(
+            [make_tag(b"flac"), 0, 0, 0],
+            (4096 + logical_len) as u64,
+            4096,
+        );
+        chd.extend_from_slice(&data);
+        chd.extend_from_slice(&compressed);
+        append_map_v5(&mut chd, &raw_map);
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut output = vec![0xffu8; 4096];
+        chd.hunk(1)
+            .expect("hunk")
+            .read_hunk_in(&mut Vec::new(), &mut output)
+            .expect("short final hunk should decompress");
+        assert_eq!(output, expected);
+
+        // The cut off block is not used for the logical length of the hunk.
+        chd.set_strict(true);
+        assert_eq!(
+            chd.hunk(1)
+                .expect("hunk")
+                .read_hunk_in(&mut Vec::new(), &mut output),
+            Err(ChdError::TrailingData)
+        );
+    }
+
+    #[test]
+    fn full_final_hunk_test() {
+        // The final hunk is compressed in full, so it is decompressed in full even in strict
+        // mode, and the hunk checksum covers the data past the logical size.
+        let logical_len = 2000;
+        let data: Vec<u8> = (0..4096).map(|i| (i * 7 % 251) as u8).collect();
+        let mut flac = vec![b'B'];
+        (0..4).for_each(|frame| flac.extend(flac_constant_frame(frame, 256, 0x1234, -2)));
+        let flac_data = [0x12, 0x34, 0xff, 0xfe].repeat(1024);
+
+        for (tag, compressed, data) in [
+            (b"zlib", stored_deflate(&data), &data),
+            (b"lzma", lzma_literal_stream(&data, 3, 0, 2), &data),
+            (b"flac", flac.clone(), &flac_data),
+        ] {
+            let mut raw_map = Vec::new();
+            push_map_entry(
+                &mut raw_map,
+                V5CompressionType::CompressionType0,
+                compressed.len() as u32,
+                124,
+                crate::block_hash::CRC16.checksum(data),
+            );
+
+            let mut chd = v5_header([make_tag(tag), 0, 0, 0], logical_len as u64, 4096);
+            chd.extend_from_slice(&compressed);
+            append_map_v5(&mut chd, &raw_map);
+
+            let mut chd = ChdFile::from_bytes(&chd).expect("file");
+            chd.set_strict(true);
+            let mut output = vec![0u8; 4096];
+            let read = chd
+                .hunk(0)
+                .expect("hunk")
+                .read_hunk_in(&mut Vec::new(), &mut output)
+                .expect("full final hunk should decompress");
+            assert_eq!(read, 4096);
+            assert_eq!(&output, data);
+        }
+    }
+
+    #[test]
+    fn cdfl_output_length_test() {
+        let frame_size = CD_FRAME_SIZE as usize;
+        let mut codec = CdFlacCodec::new(4 * CD_FRAME_SIZE).expect("codec");
+        let input = flac_constant_frame(0, 255, 0, 0);
+
+        let mut misaligned = vec![0u8; 2 * frame_size + 1];
+        assert_eq!(
+            codec.decompress(&input, &mut misaligned).err(),
+            Some(ChdError::CodecError)
+        );
+
+        let mut oversized = vec![0u8; 8 * frame_size];
+        assert_eq!(
+            codec.decompress(&input, &mut oversized).err(),
+            Some(ChdError::CodecError)
+        );
+    }
+
+    #[test]
+    fn cdfl_subcode_offset_test() {
+        const FRAMES: usize = 4;
+        let mut codec = CdFlacCodec::new(FRAMES as u32 * CD_FRAME_SIZE).expect("codec");
+
+        // 588 samples in each frame, split into FLAC frames of at most 256 samples.
+        let mut input = Vec::new();
+        let mut samples = FRAMES * 588;
+        let mut frame_num = 0;
+        while samples > 0 {
+            let block_size = std::cmp::min(samples, 256);
+            input.extend(flac_constant_frame(
+                frame_num,
+                block_size as u16,
+                0x1234,
+                -2,
+            ));
+            samples -= block_size;
+            frame_num += 1;
+        }
+
+        // The subcode data immediately follows as a single stored Deflate block.
+        let subcode: Vec<u8> = (0..FRAMES * CD_MAX_SUBCODE_DATA as usize)
+            .map(|i| (i % 7) as u8)
+            .collect();
+        input.extend(stored_deflate(&subcode));
+
+        let mut output = vec![0u8; FRAMES * CD_FRAME_SIZE as usize];
+        let res = codec
+            .decompress(&input, &mut output)
+            .expect("could not decompress");
+        assert_eq!(res.total_in(), input.len());
+        for (frame, subcode) in output
+            .chunks_exact(CD_FRAME_SIZE as usize)
+            .zip(subcode.chunks_exact(CD_MAX_SUBCODE_DATA as usize))
+        {
+            assert!(frame[..CD_MAX_SECTOR_DATA as usize]
+                .chunks_exact(4)
+                .all(|sample| sample == [0x12, 0x34, 0xff, 0xfe]));
+            assert_eq!(&frame[CD_MAX_SECTOR_DATA as usize..], subcode);
+        }
+
+        // The same frames decode into an output shorter than the hunk of the codec.
+        let mut codec = CdFlacCodec::new(2 * FRAMES as u32 * CD_FRAME_SIZE).expect("codec");
+        let mut short_output = vec![0u8; FRAMES * CD_FRAME_SIZE as usize];
+        codec
+            .decompress(&input, &mut short_output)
+            .expect("could not decompress");
+        assert_eq!(short_output, output);
+    }
+
+    #[test]
+    fn cdzl_short_output_test() {
+        // A single cdzl frame without ECC, decoded by a codec for a hunk of 4 frames.
+        let sector = [0x12u8; CD_MAX_SECTOR_DATA as usize];
+        let subcode = [0x5au8; CD_MAX_SUBCODE_DATA as usize];
+        let sector_block = stored_deflate(&sector);
+        let mut input = vec![0x00];
+        input.extend_from_slice(&(sector_block.len() as u16).to_be_bytes());
+        input.extend(sector_block);
+        input.extend(stored_deflate(&subcode));
+        let mut codec = CdZlibCodec::new(4 * CD_FRAME_SIZE).expect("codec");
+        let mut output = vec![0u8; CD_FRAME_SIZE as usize];
+        codec
+            .decompress(&input, &mut output)
+            .expect("could not decompress");
+        assert_eq!(output[..CD_MAX_SECTOR_DATA as usize], sector);
+        assert_eq!(output[CD_MAX_SECTOR_DATA as usize..], subcode);
+    }
+
+    #[test]
+    fn codec_reset_test() {
+        let mut codec = RawFlacCodec::new(1024).expect("codec");
+        let mut output = vec![0u8; 1024];
+
+        let mut input = vec![b'B'];
+        input.extend(flac_constant_frame(0, 256, 0x1234, -2));
+        codec
+            .decompress(&input, &mut output)
+            .expect("could not decompress");
+        assert!(output
+            .chunks_exact(4)
+            .all(|sample| sample == [0x12, 0x34, 0xff, 0xfe]));
+
+        codec.reset();
+        let mut input = vec![b'L'];
+        input.extend(flac_constant_frame(0, 256, 7, 9));
+        codec
+            .decompress(&input, &mut output)
+            .expect("could not decompress");
+        assert!(output.chunks_exact(4).all(|sample| sample == [7, 0, 9, 0]));
+    }
+
+    #[test]
+    fn read_all_test() {
+        let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+        let all = chd.read_all().expect("could not read all hunks");
+        assert_eq!(all.len() as u64, chd.header().logical_bytes());
+
+        let mut hunks = Vec::new();
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        for hunk_num in 0..chd.header().hunk_count() {
+            let mut hunk = chd.hunk(hunk_num).expect("could not acquire hunk");
+            hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+                .expect(format!("could not read_hunk {}", hunk_num).as_str());
+            hunks.extend_from_slice(&hunk_buf);
+        }
+        hunks.truncate(all.len());
+        assert_eq!(all, hunks);
+    }
+
+    /// Builds an uncompressed CHD V5 header for an image of the given logical size.
+    fn v5_header(compression: [u32; 4], logical_bytes: u64, hunk_bytes: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(b"MComprHD");
+        header.extend_from_slice(&124u32.to_be_bytes());
+        header.extend_from_slice(&5u32.to_be_bytes());
+        for tag in compression {
+            header.extend_from_slice(&tag.to_be_bytes());
+        }
+        header.extend_from_slice(&logical_bytes.to_be_bytes());
+        // map offset, metadata offset
+        header.extend_from_slice(&124u64.to_be_bytes());
+        header.extend_from_slice(&0u64.to_be_bytes());
+        header.extend_from_slice(&hunk_bytes.to_be_bytes());
+        // unit bytes
+        header.extend_from_slice(&512u32.to_be_bytes());
+        // raw sha1, sha1, parent sha1
+        header.resize(124, 0);
+        header
+    }
+
+    /// Builds a writable V3 or V4 CHD file that stores the data of each hunk in order
+    /// uncompressed, and returns it with the data it holds.
+    fn legacy_chd(version: u32, hunk_count: usize, hunk_bytes: usize) -> (Vec<u8>, Vec<u8>) {
+        let (header_len, hunk_bytes_offset) = match version {
+            3 => (120, 76),
+            4 => (108, 44),
+            _ => panic!("unsupported version"),
+        };
+        let logical_bytes = (hunk_count * hunk_bytes) as u64;
+        let mut chd = Vec::new();
+        chd.extend_from_slice(b"MComprHD");
+        chd.extend_from_slice(&(header_len as u32).to_be_bytes());
+        chd.extend_from_slice(&version.to_be_bytes());
+        // Writable, with Zlib compression.
+        chd.extend_from_slice(&(Flags::IsWritable as u32).to_be_bytes());
+        chd.extend_from_slice(&(CodecType::Zlib as u32).to_be_bytes());
+        chd.extend_from_slice(&(hunk_count as u32).to_be_bytes());
+        chd.extend_from_slice(&logical_bytes.to_be_bytes());
+        chd.resize(header_len, 0);
+        chd[hunk_bytes_offset..][..4].copy_from_slice(&(hunk_bytes as u32).to_be_bytes());
+
+        // Each map entry stores an uncompressed hunk with its CRC.
+        const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        let data: Vec<u8> = (0..logical_bytes).map(|i| (i % 251) as u8 + 1).collect();
+        let data_offset = header_len + (hunk_count + 1) * 16;
+        for (hunk_num, hunk) in data.chunks_exact(hunk_bytes).enumerate() {
+            chd.extend_from_slice(&((data_offset + hunk_num * hunk_bytes) as u64).to_be_bytes());
+            chd.extend_from_slice(&CRC32.checksum(hunk).to_be_bytes());
+            chd.extend_from_slice(&(hunk_bytes as u16).to_be_bytes());
+            chd.push((hunk_bytes >> 16) as u8);
+            chd.push(0x12);
+        }
+        chd.extend_from_slice(b"EndOfListCookie\0");
+        chd.extend_from_slice(&data);
+        (chd, data)
+    }
+
+    /// Builds an uncompressed CHD V5 file that stores the data of each hunk in order, and
+    /// returns it with the data it holds.
+    fn uncompressed_chd(logical_bytes: usize, hunk_bytes: usize) -> (Vec<u8>, Vec<u8>) {
+        let mut chd = v5_header([0; 4], logical_bytes as u64, hunk_bytes as u32);
+        // The uncompressed map holds the offset of each hunk in units of the hunk size.
+        let hunk_count = (logical_bytes + hunk_bytes - 1) / hunk_bytes;
+        for hunk_num in 1..=hunk_count as u32 {
+            chd.extend_from_slice(&hunk_num.to_be_bytes());
+        }
+        chd.resize(hunk_bytes, 0);
+        let data: Vec<u8> = (0..logical_bytes).map(|i| (i % 251) as u8 + 1).collect();
+        chd.extend_from_slice(&data);
+        (chd, data)
+    }
+
+    /// Appends the given raw V5 map to a CHD V5 file built by [`v5_header`] as a compressed map,
+    /// and points the header at it.
+    fn append_map_v5(chd: &mut Vec<u8>, raw_map: &[u8]) {
+        let mut header = match ChdHeader::try_read_header(&mut Cursor::new(&chd[..])) {
+            Ok(ChdHeader::V5Header(header)) => header,
+            _ => panic!("expected a V5 header"),
+        };
+        header.map_offset = chd.len() as u64;
+        chd[40..48].copy_from_slice(&header.map_offset.to_be_bytes());
+        ChdMap::write_compressed_v5(&header, raw_map, chd).expect("encode map");
+    }
+
+    /// Appends an entry for a hunk with the given compression type, length, offset and CRC16
+    /// to a raw V5 map.
+    fn push_map_entry(
+        raw_map: &mut Vec<u8>,
+        hunk_type: V5CompressionType,
+        len: u32,
+        offset: u64,
+        crc: u16,
+    ) {
+        raw_map.push(hunk_type as u8);
+        raw_map.extend_from_slice(&len.to_be_bytes()[1..]);
+        raw_map.extend_from_slice(&offset.to_be_bytes()[2..]);
+        raw_map.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    /// Returns the given data as a single stored Deflate block, which zlib decompresses
+    /// without compression.
+    fn stored_deflate(data: &[u8]) -> Vec<u8> {
+        let len = data.len() as u16;
+        let mut block = vec![0x01];
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(&(!len).to_le_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    /// Appends the given metadata entries to a CHD V5 file built by [`v5_header`], and points
+    /// the header at the first entry.
+    fn append_metadata(chd: &mut Vec<u8>, entries: &[(&[u8; 4], &[u8])]) {
+        let meta_offset = chd.len() as u64;
+        chd[48..56].copy_from_slice(&meta_offset.to_be_bytes());
+        for (index, (tag, value)) in entries.iter().enumerate() {
+            let next = if index + 1 < entries.len() {
+                chd.len() + 16 + value.len()
+            } else {
+                0
+            };
+            chd.extend_from_slice(&make_tag(tag).to_be_bytes());
+            chd.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            chd.extend_from_slice(&(next as u64).to_be_bytes());
+            chd.extend_from_slice(value);
+        }
+    }
+
+    #[test]
+    fn truncated_file_test() {
+        let (mut chd, _) = uncompressed_chd(3 * 4096, 4096);
+        chd.truncate(chd.len() - 1);
+        match ChdFile::open(Cursor::new(chd), None) {
+            Err(ChdError::TruncatedFile { expected, actual }) => {
+                assert_eq!(expected, 4 * 4096);
+                assert_eq!(actual, 4 * 4096 - 1);
+            }
+            Err(e) => panic!("expected a truncated file error, got {:?}", e),
+            Ok(_) => panic!("expected a truncated file error"),
+        }
+
+        // The map itself is cut off.
+        let (mut chd, _) = uncompressed_chd(3 * 4096, 4096);
+        chd.truncate(130);
+        assert!(matches!(
+            ChdFile::open(Cursor::new(chd), None),
+            Err(ChdError::TruncatedFile {
+                expected: 136,
+                actual: 130
+            })
+        ));
+    }
+
+    #[test]
+    fn short_final_hunk_test() {
+        const HUNK_BYTES: usize = 4096;
+        let logical_bytes = 3 * HUNK_BYTES - 123;
+        let (chd, data) = uncompressed_chd(logical_bytes, HUNK_BYTES);
+
+        let mut chd = ChdFile::open(Cursor::new(chd), None).expect("file");
+        assert_eq!(chd.header().hunk_count(), 3);
+        assert_eq!(chd.read_all().expect("could not read all hunks"), data);
+
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        hunk_buf.fill(0xff);
+        let mut cmp_buf = Vec::new();
+        let mut hunk = chd.hunk(2).expect("could not acquire hunk");
+        let read = hunk
+            .read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+            .expect("could not read final hunk");
+        assert_eq!(read, HUNK_BYTES);
+        assert_eq!(hunk_buf[..HUNK_BYTES - 123], data[2 * HUNK_BYTES..]);
+        assert!(hunk_buf[HUNK_BYTES - 123..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn hunk_reader_test() {
+        const HUNK_BYTES: usize = 4096;
+        let (chd, data) = uncompressed_chd(3 * HUNK_BYTES - 123, HUNK_BYTES);
+        let mut chd = ChdFile::open(Cursor::new(chd), None).expect("file");
+
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        for hunk_num in 0..3 {
+            let mut copied = Vec::new();
+            let mut reader = chd.hunk_reader(hunk_num).expect("hunk reader");
+            std::io::copy(&mut reader, &mut copied).expect("could not copy hunk");
+
+            chd.hunk(hunk_num)
+                .and_then(|mut hunk| hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf))
+                .expect("could not read hunk");
+            let logical_len = chd.hunk_logical_len(hunk_num).expect("logical len");
+            assert_eq!(copied.len(), logical_len);
+            assert_eq!(copied, hunk_buf[..logical_len]);
+            assert_eq!(
+                copied,
+                data[hunk_num as usize * HUNK_BYTES..][..logical_len]
+            );
+        }
+        assert_eq!(chd.hunk_reader(3).err(), Some(ChdError::HunkOutOfRange));
+    }
+
+    #[test]
+    fn from_bytes_test() {
+        let (chd, data) = uncompressed_chd(2 * 4096, 4096);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        let mut hunk = chd.hunk(0).expect("could not acquire hunk");
+        hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+            .expect("could not read hunk");
+        assert_eq!(hunk_buf, data[..4096]);
+    }
+
+    #[test]
+    fn open_boxed_test() {
+        let (chd, data) = uncompressed_chd(2 * 4096, 4096);
+        // Readers of different types can be held as the same type.
+        let readers: Vec<Box<dyn ReadSeek>> = vec![
+            Box::new(Cursor::new(chd.clone())),
+            Box::new(BufReader::new(Cursor::new(chd))),
+        ];
+        for reader in readers {
+            let mut chd = ChdFile::open_boxed(reader, None).expect("file");
+            assert_eq!(chd.read_all().expect("read all"), data);
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn open_path_mmap_test() {
+        let dir = std::env::temp_dir().join("chd-rs-open-path-mmap-test");
+        std::fs::create_dir_all(&dir).expect("could not create directory");
+        let path = dir.join("image.chd");
+        let (chd, data) = uncompressed_chd(4 * 4096, 4096);
+        std::fs::write(&path, &chd).expect("write");
+
+        let mut chd = ChdFile::open_path_mmap(&path).expect("could not map file");
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        let mut hunk = chd.hunk(2).expect("could not acquire hunk");
+        hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+            .expect("could not read hunk");
+        assert_eq!(hunk_buf, data[2 * 4096..][..4096]);
+
+        // Dropping the file unmaps it, after which it can be removed on every platform.
+        drop(chd);
+        std::fs::remove_dir_all(&dir).expect("could not remove mapped file");
+        assert_eq!(
+            ChdFile::open_path_mmap(&path).err(),
+            Some(ChdError::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn read_hd_sector_test() {
+        let (mut chd, data) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(&mut chd, &[(b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:512\0")]);
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut mbr = [0u8; 512];
+        chd.read_hd_sector(0, &mut mbr)
+            .expect("could not read sector");
+        assert_eq!(mbr, data[..512]);
+        chd.read_hd_sector(15, &mut mbr)
+            .expect("could not read sector");
+        assert_eq!(mbr, data[15 * 512..]);
+
+        assert!(matches!(
+            chd.read_hd_sector(16, &mut mbr),
+            Err(ChdError::HunkOutOfRange)
+        ));
+        assert!(matches!(
+            chd.read_hd_sector(0, &mut [0u8; 256]),
+            Err(ChdError::InvalidParameter)
+        ));
+        assert!(matches!(
+            chd.read_hd_sector(u64::MAX / 512, &mut mbr),
+            Err(ChdError::InvalidParameter)
+        ));
+        assert!(matches!(
+            chd.read_hd_sector(u64::MAX, &mut mbr),
+            Err(ChdError::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    fn metadata_error_test() {
+        let (chd, _) = uncompressed_chd(2 * 4096, 4096);
+        let mut missing = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(
+            missing.read_hd_sector(0, &mut [0u8; 512]),
+            Err(ChdError::MetadataNotFound(*b"GDDD"))
+        );
+
+        let mut malformed = chd.clone();
+        append_metadata(
+            &mut malformed,
+            &[(b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:abc\0")],
+        );
+        let mut malformed = ChdFile::from_bytes(&malformed).expect("file");
+        assert!(matches!(
+            malformed.read_hd_sector(0, &mut [0u8; 512]),
+            Err(ChdError::MetadataParse { tag, .. }) if &tag == b"GDDD"
+        ));
+
+        let track: &[u8] = b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:many";
+        match CdToc::from_metadata([(make_tag(b"CHTR"), track)]) {
+            Err(ChdError::MetadataParse { tag, detail }) => {
+                assert_eq!(&tag, b"CHTR");
+                assert!(detail.contains("FRAMES:many"), "{}", detail);
+            }
+            res => panic!("expected a metadata parse error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn find_parent_test() {
+        let dir = std::env::temp_dir().join("chd-rs-find-parent-test");
+        std::fs::create_dir_all(&dir).expect("could not create directory");
+
+        let with_sha1 = |sha1: [u8; 20], parent_sha1: [u8; 20]| {
+            let mut header = v5_header([0; 4], 4096, 4096);
+            header[84..104].copy_from_slice(&sha1);
+            header[104..124].copy_from_slice(&parent_sha1);
+            header
+        };
+        std::fs::write(dir.join("a.chd"), with_sha1([1; 20], [0; 20])).expect("write");
+        std::fs::write(dir.join("b.chd"), with_sha1([2; 20], [0; 20])).expect("write");
+        std::fs::write(dir.join("c.chd"), b"not a chd file").expect("write");
+        std::fs::write(dir.join("d.txt"), with_sha1([3; 20], [0; 20])).expect("write");
+
+        let child = |parent_sha1| {
+            ChdHeader::from_reader(&mut Cursor::new(with_sha1([4; 20], parent_sha1)))
+                .expect("header")
+        };
+        assert_eq!(
+            find_parent(&dir, &child([2; 20])).expect("could not search"),
+            Some(dir.join("b.chd"))
+        );
+        assert_eq!(
+            find_parent(&dir, &child([3; 20])).expect("could not search"),
+            None
+        );
+        assert_eq!(
+            find_parent(&dir, &child([0; 20])).expect("could not search"),
+            None
+        );
+    }
+
+    #[test]
+    fn extract_to_test() {
+        let logical_bytes = 3 * 4096 - 123;
+        let (mut chd, data) = uncompressed_chd(logical_bytes, 4096);
+        chd[64..84].copy_from_slice(&Sha1::digest(&data));
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut out = Vec::new();
+        let written = chd.extract_to(&mut out).expect("could not extract");
+        assert_eq!(written, logical_bytes as u64);
+        assert_eq!(chd.header().raw_sha1(), Some(Sha1::digest(&out).into()));
+    }
+
+    #[test]
+    fn has_subcode_test() {
+        let (mut chd, _) = uncompressed_chd(4 * 2448, 4 * 2448);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:2 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0"),
+                (b"CHT2", b"TRACK:2 TYPE:AUDIO SUBTYPE:RW_RAW FRAMES:2 PREGAP:0 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0"),
+            ],
+        );
+        assert!(ChdFile::from_bytes(&chd).expect("file").has_subcode());
+
+        let (mut chd, _) = uncompressed_chd(4 * 2448, 4 * 2448);
+        append_metadata(
+            &mut chd,
+            &[(b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0")],
+        );
+        assert!(!ChdFile::from_bytes(&chd).expect("file").has_subcode());
+
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(&mut chd, &[(b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:512\0")]);
+        assert!(!ChdFile::from_bytes(&chd).expect("file").has_subcode());
+    }
+
+    #[test]
+    fn codec_type_u32_test() {
+        for &codec in supported_codecs() {
+            assert_eq!(CodecType::try_from(codec.as_u32()), Ok(codec));
+        }
+        // V5 codecs are packed from their tag in big-endian order, as with CHD_MAKE_TAG.
+        assert_eq!(CodecType::ZLibV5.as_u32(), 0x7a6c6962);
+        assert_eq!(CodecType::FlacCdV5.as_u32(), make_tag(b"cdfl"));
+        assert_eq!(CodecType::None.as_u32(), 0);
+        assert_eq!(
+            CodecType::try_from(make_tag(b"bogu")),
+            Err(ChdError::UnsupportedCodec(make_tag(b"bogu")))
+        );
+    }
+
+    #[test]
+    fn hunk_is_populated_test() {
+        let (mut chd, _) = uncompressed_chd(6 * 4096, 4096);
+        // Hunks with an uncompressed map entry of 0 are not stored in the file.
+        for hunk_num in [1, 2, 4] {
+            chd[124 + hunk_num * 4..][..4].copy_from_slice(&0u32.to_be_bytes());
+        }
+        let chd = ChdFile::from_bytes(&chd).expect("file");
+        let populated: Vec<_> = (0..6)
+            .map(|index| chd.hunk_is_populated(index).expect("hunk"))
+            .collect();
+        assert_eq!(populated, [true, false, false, true, false, true]);
+        assert_eq!(chd.hunk_is_populated(6), Err(ChdError::HunkOutOfRange));
+    }
+
+    #[test]
+    fn read_full_frame_test() {
+        // The first track is padded to 4 frames, and the pregap of the second track is not
+        // stored in the file.
+        let (mut chd, data) = uncompressed_chd(8 * 2448, 4 * 2448);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:RW_RAW FRAMES:3 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0"),
+                (b"CHT2", b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:4 PREGAP:2 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let frame = chd.read_full_frame(1).expect("could not read frame");
+        assert_eq!(frame[..], data[2448..2 * 2448]);
+
+        // The second track begins at LBA 5 in the fifth frame of the file.
+        assert_eq!(chd.read_full_frame(5), Err(ChdError::NotSupported));
+        let frame = chd
+            .read_full_frame_zero_subcode(5)
+            .expect("could not read frame");
+        assert_eq!(frame[..2352], data[4 * 2448..][..2352]);
+        assert!(frame[2352..].iter().all(|&b| b == 0));
+
+        assert_eq!(chd.read_full_frame(3), Err(ChdError::HunkOutOfRange));
+        assert_eq!(
+            chd.read_full_frame_zero_subcode(9),
+            Err(ChdError::HunkOutOfRange)
+        );
+    }
+
+    #[test]
+    fn track_hashes_test() {
+        // No test image with a Redump entry is available, so the reference hashes were
+        // computed independently in Python from the byte pattern of `uncompressed_chd`:
+        //
+        //     data = bytes(i % 251 + 1 for i in range(8 * 2448))
+        //     track1 = b"".join(data[f * 2448:][:2352] for f in range(4))
+        //     track2 = b"".join(data[f * 2448:][:2352] for f in range(4, 8))
+        //     track2 = bytes(track2[i ^ 1] for i in range(len(track2)))
+        //     zlib.crc32(track), hashlib.md5(track).digest(), hashlib.sha1(track).digest()
+        let (mut chd, data) = uncompressed_chd(8 * 2448, 4 * 2448);
+        assert!(data
+            .iter()
+            .enumerate()
+            .all(|(i, &b)| b == (i % 251) as u8 + 1));
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:RW_RAW FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0"),
+                (b"CHT2", b"TRACK:2 TYPE:AUDIO SUBTYPE:RW_RAW FRAMES:4 PREGAP:0 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let hashes = chd.track_hashes(1).expect("track 1");
+        assert_eq!(hashes.crc32, 0xdc9a4234);
+        assert_eq!(
+            hashes.md5,
+            [
+                0x88, 0x8f, 0x32, 0x70, 0xf2, 0xbc, 0x1f, 0xbd, 0x1a, 0x31, 0xdc, 0x95, 0xf6, 0x8c,
+                0x62, 0xd7
+            ]
+        );
+        assert_eq!(
+            hashes.sha1,
+            [
+                0x5a, 0xbe, 0xb6, 0x3c, 0x31, 0x8e, 0x44, 0x9d, 0x72, 0x2f, 0x30, 0xcd, 0x52, 0xc8,
+                0x13, 0xc6, 0xad, 0x13, 0xb6, 0x90
+            ]
+        );
+
+        // Audio tracks are hashed with their samples in little-endian byte order.
+        let hashes = chd.track_hashes(2).expect("track 2");
+        assert_eq!(hashes.crc32, 0x4811e150);
+        assert_eq!(
+            hashes.md5,
+            [
+                0x6d, 0x41, 0xdb, 0x69, 0x1e, 0x98, 0xc8, 0x73, 0x18, 0xf2, 0x96, 0xf4, 0xb1, 0x9b,
+                0xe4, 0x56
+            ]
+        );
+        assert_eq!(
+            hashes.sha1,
+            [
+                0x1e, 0x12, 0xa5, 0x74, 0xda, 0xd5, 0xf1, 0xdc, 0xdb, 0x93, 0x20, 0x04, 0x26, 0x50,
+                0x84, 0xba, 0x72, 0x50, 0x90, 0x85
+            ]
+        );
+
+        assert_eq!(chd.track_hashes(3).err(), Some(ChdError::InvalidParameter));
+    }
+
+    #[test]
+    fn creator_info_test() {
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"GDDD", b"CYLS:16,HEADS:1,SECS:1,BPS:512\0"),
+                (b"CRTR", b"chdman 0.262\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.creator_info().as_deref(), Some("chdman 0.262"));
+    }
+
+    #[test]
+    fn creator_info_missing_test() {
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(&mut chd, &[(b"GDDD", b"CYLS:16,HEADS:1,SECS:1,BPS:512\0")]);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.creator_info(), None);
+    }
+
+    #[test]
+    fn metadata_text_test() {
+        let (mut chd, _) = uncompressed_chd(8 * 2448, 4 * 2448);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0"),
+                (b"CHT2", b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:4 PREGAP:150 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0"),
+                (b"IDNT", &[0x40, 0x00, 0x01, 0x0a]),
+                // Text that is not printable is rendered as hex.
+                (b"GDDD", b"CYLS:1\n\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let text = chd.metadata_text().expect("metadata text");
+        assert_eq!(
+            text,
+            [
+                (
+                    "CHT2",
+                    "TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0"
+                ),
+                (
+                    "CHT2",
+                    "TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:4 PREGAP:150 PGTYPE:AUDIO PGSUB:RW POSTGAP:0"
+                ),
+                ("IDNT", "40 00 01 0a"),
+                ("GDDD", "43 59 4c 53 3a 31 0a 00"),
+            ]
+            .iter()
+            .map(|&(tag, value)| (String::from(tag), String::from(value)))
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unsupported_feature_test() {
+        let (mut chd, _) = uncompressed_chd(4 * 2448, 4 * 2448);
+        append_metadata(
+            &mut chd,
+            &[(b"CHT2", b"TRACK:1 TYPE:AUDIO SUBTYPE:RW_RAW FRAMES:4 PREGAP:0 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0")],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let res = chd.verify_with_subcode();
+        if cfg!(feature = "want_subcode") {
+            assert!(res.is_ok());
+        } else {
+            assert_eq!(
+                res.err(),
+                Some(ChdError::UnsupportedFeature("want_subcode"))
+            );
+        }
+
+        // The message tells the user which feature to enable.
+        let message = ChdError::UnsupportedFeature("want_subcode").to_string();
+        assert!(message.contains("'want_subcode'"));
+    }
+
+    #[test]
+    fn error_eq_test() {
+        let (chd, _) = uncompressed_chd(2 * 4096, 4096);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.hunk(2).err(), Some(ChdError::HunkOutOfRange));
+        assert_eq!(
+            chd.cd_toc().err(),
+            Some(ChdError::MetadataNotFound(*b"CHT2"))
+        );
+        assert_eq!(
+            ChdFile::from_bytes(&[0u8; 64]).err(),
+            Some(ChdError::InvalidFile)
+        );
+
+        assert_eq!(
+            ChdError::UnsupportedVersion(6),
+            ChdError::UnsupportedVersion(6)
+        );
+        assert_ne!(
+            ChdError::UnsupportedVersion(6),
+            ChdError::UnsupportedVersion(7)
+        );
+        assert_ne!(ChdError::ReadError, ChdError::WriteError);
+    }
+
+    #[test]
+    fn export_track_wav_test() {
+        let frame_size = CD_FRAME_SIZE as usize;
+        let (mut chd, data) = uncompressed_chd(8 * frame_size, 4 * frame_size);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0"),
+                (b"CHT2", b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:3 PREGAP:0 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let mut wav = Vec::new();
+        chd.export_track_wav(2, &mut wav)
+            .expect("could not export track");
+        let data_len = 3 * 588 * 4;
+        assert_eq!(wav.len(), 44 + data_len);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(wav[4..8], ((36 + data_len) as u32).to_le_bytes());
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(wav[40..44], (data_len as u32).to_le_bytes());
+        // The track begins at the fifth frame, with its samples byte swapped.
+        let first_frame = &data[4 * frame_size..];
+        assert_eq!(
+            wav[44..48],
+            [
+                first_frame[1],
+                first_frame[0],
+                first_frame[3],
+                first_frame[2]
+            ]
+        );
+
+        assert_eq!(
+            chd.export_track_wav(1, &mut Vec::new()).err(),
+            Some(ChdError::NotSupported)
+        );
+        assert_eq!(
+            chd.export_track_wav(3, &mut Vec::new()).err(),
+            Some(ChdError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn cd_unit_bytes_test() {
+        // Frames of 2352 bytes without subcode data, as declared by the unit size.
+        let frame_size = CD_MAX_SECTOR_DATA as usize;
+        let (mut chd, data) = uncompressed_chd(8 * frame_size, 4 * frame_size);
+        chd[60..64].copy_from_slice(&CD_MAX_SECTOR_DATA.to_be_bytes());
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0"),
+                (b"CHT2", b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:3 PREGAP:0 PGTYPE:AUDIO PGSUB:RW POSTGAP:0\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.header().unit_bytes(), CD_MAX_SECTOR_DATA);
+
+        let mut wav = Vec::new();
+        chd.export_track_wav(2, &mut wav)
+            .expect("could not export track");
+        // The track begins at the fifth frame of 2352 bytes, with its samples byte swapped.
+        let mut expected = data[4 * frame_size..][..3 * frame_size].to_vec();
+        expected
+            .chunks_exact_mut(2)
+            .for_each(|sample| sample.swap(0, 1));
+        assert_eq!(wav[44..], expected[..]);
+    }
+
+    /// A stream that counts the number of reads made from it, standing in for a stream
+    /// where every read is slow.
+    struct CountingReader<R> {
+        inner: R,
+        reads: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    /// A stream that fails every read into a buffer longer than `max_read` bytes.
+    struct FailingReader<R> {
+        inner: R,
+        max_read: usize,
+        reads: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl<R: Read> Read for FailingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            if buf.len() > self.max_read {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "read too long",
+                ));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for FailingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn readahead_test() {
+        let (chd, data) = uncompressed_chd(16 * 4096, 4096);
+        let reads_for = |readahead: u32, hunks: &[u32]| {
+            let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+            let reader = CountingReader {
+                inner: Cursor::new(&chd),
+                reads: reads.clone(),
+            };
+            let mut chd = ChdFile::open(reader, None).expect("file");
+            chd.set_readahead(readahead);
+            reads.set(0);
+
+            let mut hunk_buf = chd.get_hunksized_buffer();
+            let mut cmp_buf = Vec::new();
+            for &hunk_num in hunks {
+                let mut hunk = chd.hunk(hunk_num).expect("could not acquire hunk");
+                hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+                    .expect("could not read hunk");
+                assert_eq!(hunk_buf, data[hunk_num as usize * 4096..][..4096]);
+            }
+            reads.get()
+        };
+
+        let sequential: Vec<u32> = (0..16).collect();
+        assert_eq!(reads_for(0, &sequential), 16);
+        // Each read covers the current hunk and the next 3 hunks.
+        assert_eq!(reads_for(3, &sequential), 4);
+        // Out of order reads do not read ahead.
+        assert_eq!(reads_for(3, &[9, 2, 12, 5]), 4);
+    }
+
+    #[test]
+    fn readahead_error_test() {
+        // Every read ahead fails, so each hunk is read directly after the failed read ahead.
+        let (chd, data) = uncompressed_chd(16 * 4096, 4096);
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = FailingReader {
+            inner: Cursor::new(&chd),
+            max_read: usize::MAX,
+            reads: reads.clone(),
+        };
+        let mut chd = ChdFile::open(reader, None).expect("file");
+        chd.inner().max_read = 4096;
+        chd.set_readahead(3);
+        reads.set(0);
+
+        let mut hunk_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        for hunk_num in 0..16 {
+            let mut hunk = chd.hunk(hunk_num).expect("could not acquire hunk");
+            hunk.read_hunk_in(&mut cmp_buf, &mut hunk_buf)
+                .expect("could not read hunk");
+            assert_eq!(hunk_buf, data[hunk_num as usize * 4096..][..4096]);
+        }
+        // The read ahead of the last hunk only covers that hunk, and succeeds.
+        assert_eq!(reads.get(), 2 * 15 + 1);
+    }
+
+    #[test]
+    fn cached_reader_test() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CachedReader<File>>();
+
+        let (chd, data) = uncompressed_chd(16 * 4096, 4096);
+        let reads_for = |block_size: Option<usize>| {
+            let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+            let reader = CountingReader {
+                inner: Cursor::new(&chd),
+                reads: reads.clone(),
+            };
+            let reader: Box<dyn ReadSeek + '_> = match block_size {
+                Some(block_size) => Box::new(CachedReader::new(reader, block_size, 4)),
+                None => Box::new(reader),
+            };
+            let mut chd = ChdFile::open(reader, None).expect("file");
+            let mut buf = vec![0u8; 16 * 4096];
+            chd.read_bytes(0, &mut buf).expect("read");
+            assert_eq!(buf, data);
+            reads.get()
+        };
+        let direct = reads_for(None);
+        // The whole file is read with two 64KiB blocks.
+        assert!(reads_for(Some(64 * 1024)) <= 3);
+        assert!(reads_for(Some(64 * 1024)) < direct);
+
+        // Reads spanning block boundaries are assembled from every block.
+        let mut reader = CachedReader::new(Cursor::new(&chd), 7, 2);
+        for &(offset, len) in &[(0usize, 3usize), (5, 30), (100, 7), (13, 1), (3000, 2000)] {
+            let mut buf = vec![0u8; len];
+            reader.seek(SeekFrom::Start(offset as u64)).expect("seek");
+            reader.read_exact(&mut buf).expect("read");
+            assert_eq!(buf, chd[offset..][..len]);
+        }
+        let end = reader.seek(SeekFrom::End(-10)).expect("seek");
+        assert_eq!(end, chd.len() as u64 - 10);
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).expect("read");
+        assert_eq!(tail, chd[chd.len() - 10..]);
+        assert!(reader
+            .seek(SeekFrom::Current(-(chd.len() as i64) - 1))
+            .is_err());
+    }
+
+    #[test]
+    fn cached_reader_eviction_test() {
+        // A read of 6 blocks through a cache of 2 blocks evicts blocks while it is assembled.
+        let data: Vec<u8> = (0..100).collect();
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CachedReader::new(
+            CountingReader {
+                inner: Cursor::new(&data),
+                reads: reads.clone(),
+            },
+            8,
+            2,
+        );
+        let mut buf = [0u8; 40];
+        assert_eq!(reader.read_at(4, &mut buf).expect("read"), 40);
+        assert_eq!(buf, data[4..44]);
+        assert_eq!(reads.get(), 6);
+
+        // Only the last two blocks of the read are held.
+        assert_eq!(reader.read_at(32, &mut buf[..16]).expect("read"), 16);
+        assert_eq!(buf[..16], data[32..48]);
+        assert_eq!(reads.get(), 6);
+        assert_eq!(reader.read_at(0, &mut buf[..8]).expect("read"), 8);
+        assert_eq!(buf[..8], data[..8]);
+        assert_eq!(reads.get(), 7);
+    }
+
+    #[test]
+    fn cached_reader_shared_test() {
+        // Clones share the cache between threads, but each has its own position.
+        let data: Vec<u8> = (0..100).collect();
+        let reader = CachedReader::new(Cursor::new(data.clone()), 8, 4);
+        let threads: Vec<_> = (0..4)
+            .map(|start| {
+                let mut reader = reader.clone();
+                let data = data.clone();
+                std::thread::spawn(move || {
+                    for offset in (start..90).step_by(7) {
+                        let mut buf = [0u8; 10];
+                        reader.seek(SeekFrom::Start(offset as u64)).expect("seek");
+                        reader.read_exact(&mut buf).expect("read");
+                        assert_eq!(buf, data[offset..][..10]);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().expect("reader thread panicked");
+        }
+
+        let mut clone = reader.clone();
+        clone.seek(SeekFrom::Start(50)).expect("seek");
+        let mut buf = [0u8; 4];
+        clone.read_exact(&mut buf).expect("read");
+        assert_eq!(buf, data[50..54]);
+        assert!(reader.into_inner().is_none());
+        assert!(clone.into_inner().is_some());
+    }
+
+    #[test]
+    fn cache_bytes_test() {
+        let (chd, data) = uncompressed_chd(32 * 4096, 4096);
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(&chd),
+            reads: reads.clone(),
+        };
+        let mut chd = ChdFile::open(reader, None).expect("file");
+        chd.set_cache_bytes(3 * 4096 + 100);
+
+        let mut buf = [0u8; 16];
+        for i in 0..200u32 {
+            let hunk_num = (i * 7) % 32;
+            chd.read_hunk_range(hunk_num, 100, &mut buf)
+                .expect("read hunk range");
+            assert_eq!(buf, data[hunk_num as usize * 4096 + 100..][..16]);
+            assert!(chd.cached_bytes() <= 3 * 4096 + 100);
+        }
+        assert_eq!(chd.cached_bytes(), 3 * 4096);
+
+        // The three most recently used hunks are cached.
+        reads.set(0);
+        for hunk_num in [(199 * 7) % 32, (198 * 7) % 32, (197 * 7) % 32] {
+            chd.read_hunk_range(hunk_num, 0, &mut buf)
+                .expect("read hunk range");
+        }
+        assert_eq!(reads.get(), 0);
+
+        // A hunk larger than the limit is still read, and held on its own.
+        chd.set_cache_bytes(1000);
+        assert_eq!(chd.cached_bytes(), 0);
+        for hunk_num in [4, 5, 5] {
+            chd.read_hunk_range(hunk_num, 4000, &mut buf)
+                .expect("read hunk range");
+            assert_eq!(buf, data[hunk_num as usize * 4096 + 4000..][..16]);
+            assert_eq!(chd.cached_bytes(), 4096);
+        }
+
+        chd.set_cache_size(2);
+        for hunk_num in 0..8 {
+            chd.read_hunk_range(hunk_num, 0, &mut buf)
+                .expect("read hunk range");
+        }
+        assert_eq!(chd.cached_bytes(), 2 * 4096);
+    }
+
+    #[test]
+    fn clone_config_test() {
+        let (chd, data) = uncompressed_chd(8 * 4096, 4096);
+        let mut base = ChdFile::open(Cursor::new(&chd[..]), None).expect("file");
+        base.set_cache_size(2);
+        base.set_strict(true);
+
+        let mut first = base
+            .clone_config(Cursor::new(&chd[..]), None)
+            .expect("clone");
+        let mut second = base
+            .clone_config(Cursor::new(&chd[..]), None)
+            .expect("clone");
+        assert_eq!(first.header().hunk_count(), 8);
+
+        let mut buf = [0u8; 64];
+        for round in 0..4u32 {
+            let (a, b) = (round, 7 - round);
+            first.read_hunk_range(a, 10, &mut buf).expect("read first");
+            assert_eq!(buf, data[a as usize * 4096 + 10..][..64]);
+            second
+                .read_hunk_range(b, 20, &mut buf)
+                .expect("read second");
+            assert_eq!(buf, data[b as usize * 4096 + 20..][..64]);
+        }
+        // Each clone only caches its own hunks, with the cache size of the original.
+        assert_eq!(first.cached_bytes(), 2 * 4096);
+        assert_eq!(second.cached_bytes(), 2 * 4096);
+        assert_eq!(base.cached_bytes(), 0);
+
+        let mut hunk_buf = base.get_hunksized_buffer();
+        second
+            .hunk(3)
+            .and_then(|mut hunk| hunk.read_hunk_in(&mut Vec::new(), &mut hunk_buf))
+            .expect("read hunk");
+        assert_eq!(hunk_buf, data[3 * 4096..][..4096]);
+    }
+
+    #[test]
+    fn locate_test() {
+        let logical_bytes = 5 * 4096 + 300;
+        let (chd, _) = uncompressed_chd(logical_bytes, 4096);
+        let chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.locate(0), Ok((0, 0)));
+        assert_eq!(chd.locate(4095), Ok((0, 4095)));
+        assert_eq!(chd.locate(4096), Ok((1, 0)));
+        assert_eq!(chd.locate(3 * 4096 + 17), Ok((3, 17)));
+        // The last byte lies within the short final hunk.
+        assert_eq!(chd.locate(logical_bytes as u64 - 1), Ok((5, 299)));
+        assert_eq!(
+            chd.locate(logical_bytes as u64),
+            Err(ChdError::InvalidParameter)
+        );
+        assert_eq!(chd.locate(u64::MAX), Err(ChdError::InvalidParameter));
+    }
+
+    #[test]
+    fn read_bytes_test() {
+        let (chd, data) = uncompressed_chd(5 * 4096 + 300, 4096);
+        let full = ChdFile::open(Cursor::new(&chd), None)
+            .and_then(|mut chd| chd.read_all())
+            .expect("read all");
+        assert_eq!(full, data);
+
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(&chd),
+            reads: reads.clone(),
+        };
+        let mut chd = ChdFile::open(reader, None).expect("file");
+        reads.set(0);
+
+        // A range spanning the end of hunk 0, all of hunk 1 and the start of hunk 2.
+        let mut buf = vec![0u8; 4096 + 200];
+        chd.read_bytes(4000, &mut buf).expect("read bytes");
+        assert_eq!(buf, full[4000..][..buf.len()]);
+        assert_eq!(reads.get(), 3);
+
+        // The final hunk is only partially within the logical size.
+        let mut buf = vec![0u8; 400];
+        chd.read_bytes(full.len() as u64 - 400, &mut buf)
+            .expect("read bytes");
+        assert_eq!(buf, full[full.len() - 400..]);
+        assert_eq!(
+            chd.read_bytes(full.len() as u64 - 399, &mut buf),
+            Err(ChdError::InvalidParameter)
+        );
+        assert_eq!(
+            chd.read_bytes(u64::MAX, &mut buf),
+            Err(ChdError::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn ata_identify_test() {
+        let identify: Vec<u8> = (0..512).map(|i| (i % 256) as u8).collect();
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:512\0"),
+                (b"IDNT", &identify),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(
+            chd.ata_identify().expect("could not read identify data"),
+            Some(identify.as_slice().try_into().expect("512 bytes"))
+        );
+
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(&mut chd, &[(b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:512\0")]);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.ata_identify(), Ok(None));
+
+        let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+        append_metadata(&mut chd, &[(b"IDNT", &identify[..256])]);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        assert_eq!(chd.ata_identify(), Err(ChdError::InvalidMetadataSize));
+    }
+
+    #[test]
+    fn kind_test() {
+        let kind_of = |entries: &[(&[u8; 4], &[u8])]| {
+            let (mut chd, _) = uncompressed_chd(2 * 4096, 4096);
+            append_metadata(&mut chd, entries);
+            let mut chd = ChdFile::from_bytes(&chd).expect("file");
+            (chd.kind(), chd.sector_size())
+        };
+
+        assert_eq!(
+            kind_of(&[(b"CHT2", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0")]),
+            (ChdKind::CdRom, Ok(CD_MAX_SECTOR_DATA))
+        );
+        assert_eq!(
+            kind_of(&[(b"CHTR", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:RW_RAW FRAMES:4\0")]),
+            (ChdKind::CdRom, Ok(CD_FRAME_SIZE))
+        );
+        assert_eq!(
+            kind_of(&[(b"CHGD", b"TRACK:1 TYPE:MODE1 SUBTYPE:NONE FRAMES:4 PAD:0 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0\0")]),
+            (ChdKind::GdRom, Ok(CD_MAX_SECTOR_DATA))
+        );
+        assert_eq!(kind_of(&[(b"DVD ", b"")]), (ChdKind::Dvd, Ok(2048)));
+        assert_eq!(
+            kind_of(&[(b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:512\0")]),
+            (ChdKind::HardDisk, Ok(512))
+        );
+        assert_eq!(
+            kind_of(&[(b"GDDD", b"CYLS:1,HEADS:1,SECS:4,BPS:2048\0")]),
+            (ChdKind::HardDisk, Ok(2048))
+        );
+        // Files without known metadata fall back to the unit size of the header.
+        assert_eq!(kind_of(&[]), (ChdKind::Unknown, Ok(512)));
+    }
+
+    #[test]
+    fn cd_toc_cache_test() {
+        let cd_chd = |subtype: &str| {
+            let (mut chd, _) = uncompressed_chd(4 * 2448, 4 * 2448);
+            let track = format!(
+                "TRACK:1 TYPE:MODE1_RAW SUBTYPE:{} FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0",
+                subtype
+            );
+            append_metadata(&mut chd, &[(b"CHT2", track.as_bytes())]);
+            chd
+        };
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(cd_chd("NONE")),
+            reads: reads.clone(),
+        };
+        let mut chd = ChdFile::open(reader, None).expect("file");
+        assert_eq!(chd.cd_toc().expect("could not read toc").tracks.len(), 1);
+
+        // The table of contents is only read once.
+        reads.set(0);
+        assert_eq!(chd.kind(), ChdKind::CdRom);
+        assert!(!chd.has_subcode());
+        assert_eq!(chd.sector_size(), Ok(CD_MAX_SECTOR_DATA));
+        chd.cd_toc().expect("could not read toc");
+        assert_eq!(reads.get(), 0);
+
+        // Reopening reads the table of contents of the new file.
+        let reader = CountingReader {
+            inner: Cursor::new(cd_chd("RW_RAW")),
+            reads: reads.clone(),
+        };
+        chd.reopen(reader).expect("could not reopen");
+        assert!(chd.has_subcode());
+        assert_eq!(chd.sector_size(), Ok(CD_FRAME_SIZE));
+    }
+
+    #[test]
+    fn cd_layout_cache_test() {
+        let cd_chd = |frames: u32| {
+            let (mut chd, data) = uncompressed_chd(4 * 2448, 4 * 2448);
+            let track = format!(
+                "TRACK:1 TYPE:MODE1_RAW SUBTYPE:RW_RAW FRAMES:{} PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0",
+                frames
+            );
+            append_metadata(&mut chd, &[(b"CHT2", track.as_bytes())]);
+            (chd, data)
+        };
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let (chd, data) = cd_chd(4);
+        let reader = CountingReader {
+            inner: Cursor::new(chd),
+            reads: reads.clone(),
+        };
+        let mut chd = ChdFile::open(reader, None).expect("file");
+        let frame = chd.read_full_frame(3).expect("could not read frame");
+        assert_eq!(&frame[..], &data[3 * 2448..][..2448]);
+
+        // Neither the table of contents nor the hunk is read again for the same frame.
+        reads.set(0);
+        assert_eq!(chd.read_full_frame(3), Ok(frame));
+        assert_eq!(chd.read_full_frame_zero_subcode(3), Ok(frame));
+        chd.read_subcode(3).expect("could not read subcode");
+        assert_eq!(reads.get(), 0);
+
+        // Reopening computes the layout from the table of contents of the new file.
+        let reader = CountingReader {
+            inner: Cursor::new(cd_chd(2).0),
+            reads: reads.clone(),
+        };
+        chd.reopen(reader).expect("could not reopen");
+        assert!(chd.read_full_frame(1).is_ok());
+        assert_eq!(chd.read_full_frame(3), Err(ChdError::HunkOutOfRange));
+    }
+
+    #[test]
+    fn decode_hunk_into_test() {
+        let (chd, data) = uncompressed_chd(3 * 4096, 4096);
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let mut buf: Box<[u8]> = vec![0u8; 4096].into_boxed_slice();
+        chd.decode_hunk_into(1, &mut buf)
+            .expect("could not decode hunk");
+        assert_eq!(&buf[..], &data[4096..][..4096]);
+
+        assert_eq!(
+            chd.decode_hunk_into(1, &mut buf[..4095]),
+            Err(ChdError::InvalidParameter)
+        );
+        assert_eq!(
+            chd.decode_hunk_into(3, &mut buf),
+            Err(ChdError::HunkOutOfRange)
+        );
+    }
+
+    #[test]
+    fn verify_single_pass_test() {
+        let (mut chd, data) = uncompressed_chd(16 * 4096, 4096);
+        let meta_offset = chd.len();
+        append_metadata(
+            &mut chd,
+            &[
+                (b"GDDD", b"CYLS:1,HEADS:1,SECS:128,BPS:512\0"),
+                (b"IDNT", &[0u8; 512]),
+            ],
+        );
+        // Flag both metadata entries to be checksummed.
+        chd[meta_offset + 4] = 0x01;
+        chd[meta_offset + 16 + 32 + 4] = 0x01;
+
+        // The metadata hashes are sorted by tag, regardless of their order in the file.
+        let raw_sha1 = Sha1::digest(&data);
+        let mut overall = Sha1::new();
+        overall.update(&raw_sha1);
+        overall.update(b"GDDD");
+        overall.update(&Sha1::digest(b"CYLS:1,HEADS:1,SECS:128,BPS:512\0"));
+        overall.update(b"IDNT");
+        overall.update(&Sha1::digest(&[0u8; 512]));
+        chd[64..84].copy_from_slice(&raw_sha1);
+        chd[84..104].copy_from_slice(&overall.finalize());
+
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(&chd),
+            reads: reads.clone(),
+        };
+        let mut chd = ChdFile::open(reader, None).expect("file");
+        reads.set(0);
+        assert_eq!(chd.verify_report(), Ok(VerifyReport::Ok));
+        // Each hunk is read once, and each metadata entry is read as its header and its value.
+        let hunk_count = chd.header().hunk_count() as usize;
+        assert_eq!(reads.get(), hunk_count + 2 * 2);
+    }
+
+    #[test]
+    fn verify_report_test() {
+        let (mut chd, data) = uncompressed_chd(2 * 4096, 4096);
+        let meta_offset = chd.len();
+        append_metadata(&mut chd, &[(b"GDDD", b"CYLS:1,HEADS:1,SECS:16,BPS:512\0")]);
+        // Flag the metadata entry to be checksummed.
+        chd[meta_offset + 4] = 0x01;
+
+        // The overall hash is the hash of the raw data, followed by the tag and hash
+        // of each checksummed metadata entry.
+        let raw_sha1 = Sha1::digest(&data);
+        let mut overall = Sha1::new();
+        overall.update(&raw_sha1);
+        overall.update(b"GDDD");
+        overall.update(&Sha1::digest(b"CYLS:1,HEADS:1,SECS:16,BPS:512\0"));
+        chd[64..84].copy_from_slice(&raw_sha1);
+        chd[84..104].copy_from_slice(&overall.finalize());
+
+        let report = |chd: &[u8]| {
+            ChdFile::from_bytes(chd)
+                .expect("file")
+                .verify_report()
+                .expect("could not verify")
+        };
+        assert_eq!(report(&chd), VerifyReport::Ok);
+
+        let mut tampered = chd.clone();
+        *tampered.last_mut().expect("metadata") = b'!';
+        assert_eq!(report(&tampered), VerifyReport::MetadataMismatch);
+
+        let mut tampered = chd.clone();
+        tampered[4096] ^= 0xff;
+        assert_eq!(report(&tampered), VerifyReport::RawDataMismatch);
+    }
+
+    #[test]
+    fn version_test() {
+        for version in [3, 4] {
+            let (chd, data) = legacy_chd(version, 2, 4096);
+            let mut chd =
+                ChdFile::open_with_mode(Cursor::new(chd), None, OpenMode::ReadWrite).expect("file");
+            assert_eq!(chd.version(), version);
+            let mut hunk = vec![0u8; 4096];
+            chd.read_hunk_range(1, 0, &mut hunk)
+                .expect("could not read hunk");
+            assert_eq!(hunk, data[4096..]);
+            assert_eq!(
+                chd.write_hunk(1, &hunk),
+                Err(ChdError::UnsupportedForVersion {
+                    version,
+                    feature: "hunk writing"
+                })
+            );
+        }
+
+        let (chd, _) = uncompressed_chd(2 * 4096, 4096);
+        let mut chd =
+            ChdFile::open_with_mode(Cursor::new(chd), None, OpenMode::ReadWrite).expect("file");
+        assert_eq!(chd.version(), 5);
+        assert_eq!(chd.write_hunk(1, &[0u8; 4096]), Ok(()));
+    }
+
+    #[test]
+    fn open_mode_test() {
+        let (chd, data) = uncompressed_chd(2 * 4096, 4096);
+        let replacement = vec![0xa5u8; 4096];
+
+        let mut read_only = ChdFile::open(Cursor::new(chd.clone()), None).expect("file");
+        assert!(!read_only.is_writable());
+        assert_eq!(
+            read_only.write_hunk(1, &replacement),
+            Err(ChdError::ReadOnly)
+        );
+
+        let mut read_write =
+            ChdFile::open_with_mode(Cursor::new(chd), None, OpenMode::ReadWrite).expect("file");
+        assert!(read_write.is_writable());
+        let mut hunk = vec![0u8; 4096];
+        read_write
+            .read_hunk_range(1, 0, &mut hunk)
+            .expect("could not read hunk");
+        assert_eq!(hunk, data[4096..]);
+        read_write
+            .write_hunk(1, &replacement)
+            .expect("could not write hunk");
+        read_write
+            .read_hunk_range(1, 0, &mut hunk)
+            .expect("could not read hunk");
+        assert_eq!(hunk, replacement);
+
+        // Compressed CHD files can not be opened read-write.
+        let mut compressed = v5_header([make_tag(b"zlib"), 0, 0, 0], 4096, 4096);
+        compressed.resize(4096 * 2, 0);
+        assert_eq!(
+            ChdFile::open_with_mode(Cursor::new(compressed), None, OpenMode::ReadWrite).err(),
+            Some(ChdError::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn read_compressed_hunk_test() {
+        // Hunk 0 is a single stored Deflate block, hunk 1 refers to hunk 0,
+        // and hunk 2 is stored uncompressed.
+        let data: Vec<u8> = (0..3 * 4096).map(|i| (i % 251) as u8).collect();
+        let compressed = stored_deflate(&data[..4096]);
+
+        let mut raw_map = Vec::new();
+        let crc0 = crate::block_hash::CRC16.checksum(&data[..4096]);
+        let crc2 = crate::block_hash::CRC16.checksum(&data[2 * 4096..]);
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType0,
+            compressed.len() as u32,
+            124,
+            crc0,
+        );
+        push_map_entry(&mut raw_map, V5CompressionType::CompressionSelf, 0, 0, 0);
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionNone,
+            4096,
+            124 + compressed.len() as u64,
+            crc2,
+        );
+
+        let mut chd = v5_header([make_tag(b"zlib"), 0, 0, 0], 3 * 4096, 4096);
+        chd.extend_from_slice(&compressed);
+        chd.extend_from_slice(&data[2 * 4096..]);
+        append_map_v5(&mut chd, &raw_map);
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut expected = vec![0u8; 4096];
+        let mut cmp = Vec::new();
+
+        let (compression, stored) = chd.read_compressed_hunk(0).expect("read hunk 0");
+        assert_eq!(compression, HunkCompression::Compressed(CodecType::ZLibV5));
+        assert_eq!(stored, compressed);
+        let mut output = vec![0u8; 4096];
+        let mut codec = ZlibCodec::new(4096).expect("codec");
+        codec.decompress(&stored, &mut output).expect("decompress");
+        chd.hunk(0)
+            .expect("hunk")
+            .read_hunk_in(&mut cmp, &mut expected)
+            .expect("read hunk");
+        assert_eq!(output, expected);
+
+        assert_eq!(
+            chd.read_compressed_hunk(1).expect("read hunk 1"),
+            (HunkCompression::SelfHunk(0), Vec::new())
+        );
+
+        let (compression, stored) = chd.read_compressed_hunk(2).expect("read hunk 2");
+        assert_eq!(compression, HunkCompression::Uncompressed);
+        chd.hunk(2)
+            .expect("hunk")
+            .read_hunk_in(&mut cmp, &mut expected)
+            .expect("read hunk");
+        assert_eq!(stored, expected);
+
+        assert_eq!(chd.read_compressed_hunk(3), Err(ChdError::HunkOutOfRange));
+    }
+
+    #[test]
+    fn codec_index_test() {
+        // Hunk 0 uses the second codec, hunk 1 the first codec, and hunk 2 an empty slot.
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let zlib = stored_deflate(&data);
+        let mut flac = vec![b'B'];
+        for frame_num in 0..4 {
+            flac.extend(flac_constant_frame(frame_num, 256, 0x1234, -2));
+        }
+        let mut samples = vec![0u8; 4096];
+        samples
+            .chunks_exact_mut(4)
+            .for_each(|sample| sample.copy_from_slice(&[0x12, 0x34, 0xff, 0xfe]));
+
+        let mut raw_map = Vec::new();
+        let crc = |data: &[u8]| crate::block_hash::CRC16.checksum(data);
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType1,
+            zlib.len() as u32,
+            124,
+            crc(&data),
+        );
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType0,
+            flac.len() as u32,
+            124 + zlib.len() as u64,
+            crc(&samples),
+        );
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType2,
+            zlib.len() as u32,
+            124 + (zlib.len() + flac.len()) as u64,
+            crc(&data),
+        );
+
+        let mut chd = v5_header([make_tag(b"flac"), make_tag(b"zlib"), 0, 0], 3 * 4096, 4096);
+        chd.extend_from_slice(&zlib);
+        chd.extend_from_slice(&flac);
+        chd.extend_from_slice(&zlib);
+        append_map_v5(&mut chd, &raw_map);
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut output = vec![0u8; 4096];
+        let mut read_hunk = |chd: &mut ChdFile<_>, hunk_num| {
+            chd.hunk(hunk_num)
+                .and_then(|mut hunk| hunk.read_hunk_in(&mut Vec::new(), &mut output))
+                .map(|_| output.clone())
+        };
+        assert_eq!(read_hunk(&mut chd, 0), Ok(data));
+        assert_eq!(read_hunk(&mut chd, 1), Ok(samples));
+        assert_eq!(read_hunk(&mut chd, 2), Err(ChdError::InvalidData));
+
+        assert_eq!(
+            chd.read_compressed_hunk(0)
+                .map(|(compression, _)| compression),
+            Ok(HunkCompression::Compressed(CodecType::ZLibV5))
+        );
+        assert_eq!(
+            chd.read_compressed_hunk(1)
+                .map(|(compression, _)| compression),
+            Ok(HunkCompression::Compressed(CodecType::FlacV5))
+        );
+        assert_eq!(
+            chd.read_compressed_hunk(2)
+                .map(|(compression, _)| compression),
+            Err(ChdError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn compression_stats_test() {
+        let mut raw_map = Vec::new();
+        let mut offset = 124u64;
+        let mut push_entry = |hunk_type: V5CompressionType, len: u32| {
+            let off = if hunk_type == V5CompressionType::CompressionSelf {
+                0
+            } else {
+                offset
+            };
+            push_map_entry(&mut raw_map, hunk_type, len, off, 0);
+            offset += len as u64;
+        };
+        push_entry(V5CompressionType::CompressionType0, 1000);
+        push_entry(V5CompressionType::CompressionType0, 3000);
+        push_entry(V5CompressionType::CompressionType1, 2048);
+        push_entry(V5CompressionType::CompressionNone, 4096);
+        push_entry(V5CompressionType::CompressionSelf, 0);
+        drop(push_entry);
+
+        let mut chd = v5_header([make_tag(b"zlib"), make_tag(b"lzma"), 0, 0], 5 * 4096, 4096);
+        chd.resize(offset as usize, 0);
+        append_map_v5(&mut chd, &raw_map);
+
+        let chd = ChdFile::from_bytes(&chd).expect("file");
+        let stats = chd.compression_stats().expect("stats");
+        assert_eq!(stats.uncompressed_hunks, 1);
+        assert_eq!(stats.copied_hunks, 1);
+        assert_eq!(stats.zero_hunks, 0);
+        assert_eq!(
+            stats.codecs,
+            vec![
+                CodecStats {
+                    codec: CodecType::ZLibV5,
+                    hunks: 2,
+                    logical_bytes: 2 * 4096,
+                    compressed_bytes: 4000,
+                },
+                CodecStats {
+                    codec: CodecType::LzmaV5,
+                    hunks: 1,
+                    logical_bytes: 4096,
+                    compressed_bytes: 2048,
+                },
+            ]
+        );
+
+        // The uncompressed and copied hunks are not counted towards any codec.
+        let zlib = stats.codec(CodecType::ZLibV5).expect("zlib stats");
+        assert!((zlib.ratio() - 4000.0 / 8192.0).abs() < 1e-9);
+        let lzma = stats.codec(CodecType::LzmaV5).expect("lzma stats");
+        assert!((lzma.ratio() - 0.5).abs() < 1e-9);
+        assert!(stats.codec(CodecType::None).is_none());
+    }
+
+    #[test]
+    fn strict_trailing_data_test() {
+        // A single stored Deflate block followed by unused bytes.
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let mut compressed = stored_deflate(&data);
+        compressed.extend_from_slice(&[0xaa; 16]);
+
+        let mut raw_map = Vec::new();
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType0,
+            compressed.len() as u32,
+            124,
+            crate::block_hash::CRC16.checksum(&data),
+        );
+
+        let mut chd = v5_header([make_tag(b"zlib"), 0, 0, 0], 4096, 4096);
+        chd.extend_from_slice(&compressed);
+        append_map_v5(&mut chd, &raw_map);
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut buf = vec![0u8; 4096];
+        chd.decode_hunk_into(0, &mut buf)
+            .expect("could not decode hunk");
+        assert_eq!(buf, data);
+
+        chd.set_strict(true);
+        assert_eq!(
+            chd.decode_hunk_into(0, &mut buf),
+            Err(ChdError::TrailingData)
+        );
+    }
+
+    #[cfg(feature = "codec_self_test")]
+    #[test]
+    fn codec_self_test_test() {
+        use crate::codecs::self_test;
+        for hunk_size in [4096, 8 * CD_FRAME_SIZE] {
+            assert_eq!(self_test(CodecType::None, hunk_size), Ok(()));
+            assert_eq!(self_test(CodecType::ZLibV5, hunk_size), Ok(()));
+            assert_eq!(self_test(CodecType::Zlib, hunk_size), Ok(()));
+            assert_eq!(self_test(CodecType::LzmaV5, hunk_size), Ok(()));
+        }
+        assert_eq!(
+            self_test(CodecType::HuffV5, 4096),
+            Err(ChdError::NotSupported)
+        );
+    }
+
+    #[test]
+    fn features_test() {
+        // Every codec type is supported by this build, and every supported codec can be
+        // initialized for a hunk of CD-ROM frames.
+        let all = [
+            CodecType::None,
+            CodecType::Zlib,
+            CodecType::ZlibPlus,
+            CodecType::AV,
+            CodecType::ZLibV5,
+            CodecType::ZLibCdV5,
+            CodecType::LzmaCdV5,
+            CodecType::FlacCdV5,
+            CodecType::FlacV5,
+            CodecType::LzmaV5,
+            CodecType::AVHuffV5,
+            CodecType::HuffV5,
+        ];
+        let codecs = supported_codecs();
+        assert_eq!(codecs.len(), all.len());
+        for codec in all {
+            assert!(codecs.contains(&codec), "{:?} is not supported", codec);
+            assert!(
+                codec.init(8 * CD_FRAME_SIZE).is_ok(),
+                "{:?} can not be initialized",
+                codec
+            );
+        }
+
+        // A cdzl frame with ECC and subcode data is only decompressed in full if the
+        // corresponding features are reported.
+        let features = features();
+        let sector = [0u8; CD_MAX_SECTOR_DATA as usize];
+        let subcode = [0x5au8; CD_MAX_SUBCODE_DATA as usize];
+        let sector_block = stored_deflate(&sector);
+        let mut input = vec![0x01];
+        input.extend_from_slice(&(sector_block.len() as u16).to_be_bytes());
+        input.extend(sector_block);
+        input.extend(stored_deflate(&subcode));
+        let mut codec = CdZlibCodec::new(CD_FRAME_SIZE).expect("codec");
+        let mut output = vec![0u8; CD_FRAME_SIZE as usize];
+        codec
+            .decompress(&input, &mut output)
+            .expect("could not decompress");
+        assert_eq!(
+            output[..CD_SYNC_HEADER.len()] == CD_SYNC_HEADER,
+            features.want_raw_data_sector
+        );
+        assert_eq!(
+            output[CD_MAX_SECTOR_DATA as usize..] == subcode,
+            features.want_subcode
+        );
+    }
+
+    #[test]
+    fn reopen_test() {
+        let (chd, data) = uncompressed_chd(2 * 4096, 4096);
+        let mut file = ChdFile::open(Cursor::new(chd.clone()), None).expect("file");
+        let mut hunk = vec![0u8; 4096];
+        file.read_hunk_range(1, 0, &mut hunk)
+            .expect("could not read hunk");
+        assert_eq!(hunk, data[4096..]);
+
+        // The replaced file has the same geometry, so cached hunks are read again.
+        let mut replaced = chd.clone();
+        let len = replaced.len();
+        replaced[len - 4096..].fill(0x5a);
+        file.reopen(Cursor::new(replaced))
+            .expect("could not reopen");
+        file.read_hunk_range(1, 0, &mut hunk)
+            .expect("could not read hunk");
+        assert_eq!(hunk, vec![0x5a; 4096]);
+
+        let (resized, _) = uncompressed_chd(3 * 4096, 4096);
+        assert_eq!(
+            file.reopen(Cursor::new(resized)),
+            Err(ChdError::InvalidFile)
+        );
+        assert_eq!(file.header().logical_bytes(), 2 * 4096);
+        file.read_hunk_range(1, 0, &mut hunk)
+            .expect("could not read hunk");
+        assert_eq!(hunk, vec![0x5a; 4096]);
+    }
+
+    #[test]
+    fn zero_length_hunk_test() {
+        let data = vec![0xc3u8; 4096];
+        let mut raw_map = Vec::new();
+        // Decompressing the empty input of hunk 0 with LZMA would fail.
+        let zero_crc = crate::block_hash::CRC16.checksum(&[0u8; 4096]);
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType0,
+            0,
+            124,
+            zero_crc,
+        );
+        let data_crc = crate::block_hash::CRC16.checksum(&data);
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionNone,
+            4096,
+            124,
+            data_crc,
+        );
+
+        let mut chd = v5_header([make_tag(b"lzma"), 0, 0, 0], 2 * 4096, 4096);
+        chd.extend_from_slice(&data);
+        append_map_v5(&mut chd, &raw_map);
+
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut buf = vec![0xffu8; 4096];
+        chd.decode_hunk_into(0, &mut buf)
+            .expect("could not decode hunk");
+        assert!(buf.iter().all(|&b| b == 0));
+        chd.decode_hunk_into(1, &mut buf)
+            .expect("could not decode hunk");
+        assert_eq!(buf, data);
+
+        assert_eq!(
+            chd.read_compressed_hunk(0).expect("read hunk 0"),
+            (HunkCompression::Zero, Vec::new())
+        );
+        let stats = chd.compression_stats().expect("stats");
+        assert_eq!(stats.zero_hunks, 1);
+        assert!(stats.codecs.is_empty());
+    }
+
+    #[test]
+    fn hunk_logical_len_test() {
+        let (chd, _) = uncompressed_chd(3 * 4096 + 100, 4096);
+        let chd = ChdFile::open(Cursor::new(chd), None).expect("open");
+        assert_eq!(chd.header().hunk_count(), 4);
+        assert_eq!(chd.hunk_logical_len(0), Ok(4096));
+        assert_eq!(chd.hunk_logical_len(2), Ok(4096));
+        assert_eq!(chd.hunk_logical_len(3), Ok(100));
+        assert!(chd.hunk_logical_len(3).unwrap() < chd.header().hunk_size() as usize);
+        assert_eq!(chd.hunk_logical_len(4), Err(ChdError::HunkOutOfRange));
+    }
+
+    /// A minimal raw LZMA encoder that only emits literals, for producing
+    /// streams with arbitrary `lc/lp/pb` properties.
+    fn lzma_literal_stream(data: &[u8], lc: u32, lp: u32, pb: u32) -> Vec<u8> {
+        struct RangeEncoder {
+            low: u64,
+            range: u32,
+            cache: u8,
+            cache_size: u64,
+            out: Vec<u8>,
+        }
+
+        impl RangeEncoder {
+            fn shift_low(&mut self) {
+                if (self.low as u32) < 0xFF00_0000 || (self.low >> 32) != 0 {
+                    let carry = (self.low >> 32) as u8;
+                    let mut temp = self.cache;
+                    loop {
+                        self.out.push(temp.wrapping_add(carry));
+                        temp = 0xFF;
+                        self.cache_size -= 1;
+                        if self.cache_size == 0 {
+                            break;
+                        }
+                    }
+                    self.cache = (self.low >> 24) as u8;
+                }
+                self.cache_size += 1;
+                self.low = (self.low & 0x00FF_FFFF) << 8;
+            }
+
+            fn encode_bit(&mut self, prob: &mut u16, bit: u32) {
+                let bound = (self.range >> 11) * (*prob as u32);
+                if bit == 0 {
+                    self.range = bound;
+                    *prob += (2048 - *prob) >> 5;
+                } else {
+                    self.low += bound as u64;
+                    self.range -= bound;
+                    *prob -= *prob >> 5;
+                }
+                while self.range < (1 << 24) {
+                    self.range <<= 8;
+                    self.shift_low();
+                }
+            }
+        }
+
+        let mut rc = RangeEncoder {
+            low: 0,
+            range: 0xFFFF_FFFF,
+            cache: 0,
+            cache_size: 1,
+            out: Vec::new(),
+        };
+        let mut is_match = [1024u16; 12 << 4];
+        let mut literal = vec![1024u16; 0x300 << (lc + lp)];
+        let mut prev = 0u8;
+        for (pos, &byte) in data.iter().enumerate() {
+            // Only literals are emitted, so the state stays at 0.
+            rc.encode_bit(&mut is_match[pos & ((1 << pb) - 1)], 0);
+            let lit_state = ((pos & ((1 << lp) - 1)) << lc) + ((prev as usize) >> (8 - lc));
+            let probs = &mut literal[lit_state * 0x300..][..0x300];
+            let mut symbol = 1usize;
+            for i in (0..8).rev() {
+                let bit = ((byte >> i) & 1) as u32;
+                rc.encode_bit(&mut probs[symbol], bit);
+                symbol = (symbol << 1) | bit as usize;
+            }
+            prev = byte;
+        }
+        for _ in 0..5 {
+            rc.shift_low();
+        }
+        rc.out
+    }
+
+    #[test]
+    fn lzma_with_props_test() {
+        let data: Vec<u8> = (0..4096).map(|i| (i * 7 % 251) as u8).collect();
+        let stream = lzma_literal_stream(&data, 3, 0, 0);
+
+        let mut codec = LzmaCodec::with_props(3, 0, 0, 4096).expect("lzma codec");
+        let mut output = vec![0u8; 4096];
+        let res = codec.decompress(&stream, &mut output).expect("decompress");
+        assert_eq!(res.total_out(), 4096);
+        assert_eq!(output, data);
+
+        // The standard properties can not decode the stream.
+        let mut standard = LzmaCodec::new(4096).expect("lzma codec");
+        let mut output = vec![0u8; 4096];
+        assert!(standard.decompress(&stream, &mut output).is_err() || output != data);
+
+        assert!(matches!(
+            LzmaCodec::with_props(9, 0, 2, 4096),
+            Err(ChdError::CodecError)
+        ));
+        assert!(matches!(
+            LzmaCodec::with_props(3, 0, 5, 4096),
+            Err(ChdError::CodecError)
+        ));
+    }
+
+    #[test]
+    fn codec_types_test() {
+        let header = v5_header([make_tag(b"lzma"), make_tag(b"zlib"), 0, 0], 4096, 4096);
+        let header = ChdHeader::try_read_header(&mut Cursor::new(header)).expect("header");
+        assert_eq!(
+            header.codec_types(),
+            vec![CodecType::LzmaV5, CodecType::ZLibV5]
+        );
+    }
+
+    #[test]
+    fn map_v5_roundtrip_test() {
+        const HUNK_COUNT: u32 = 64;
+        let header = v5_header([make_tag(b"zlib"), 0, 0, 0], HUNK_COUNT as u64 * 4096, 4096);
+        let mut header = match ChdHeader::try_read_header(&mut Cursor::new(header)) {
+            Ok(ChdHeader::V5Header(header)) => header,
+            _ => panic!("expected a V5 header"),
+        };
+        header.map_offset = 0;
+
+        let mut raw_map = Vec::new();
+        let mut offset = 0x1000u64;
+        let mut push_data = |hunk_type: V5CompressionType, len: u32, crc: u16| {
+            push_map_entry(&mut raw_map, hunk_type, len, offset, crc);
+            offset += len as u64;
+        };
+
+        // Long and short runs of the same type.
+        for hunk_num in 0..25u32 {
+            push_data(
+                V5CompressionType::CompressionType0,
+                100 + hunk_num * 7,
+                hunk_num as u16 * 31,
+            );
+        }
+        for hunk_num in 25..30u32 {
+            push_data(V5CompressionType::CompressionNone, 4096, hunk_num as u16);
+        }
+        push_data(V5CompressionType::CompressionType1, 1000, 0xbeef);
+        drop(push_data);
+
+        // Self references to an arbitrary, the same and consecutive hunks.
+        push_map_entry(&mut raw_map, V5CompressionType::CompressionSelf, 0, 3, 0);
+        push_map_entry(&mut raw_map, V5CompressionType::CompressionSelf, 0, 3, 0);
+        for hunk_num in 4..11 {
+            push_map_entry(
+                &mut raw_map,
+                V5CompressionType::CompressionSelf,
+                0,
+                hunk_num,
+                0,
+            );
+        }
+
+        // Parent references to the same position, an arbitrary, the same and the next hunk.
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionParent,
+            0,
+            40 * 8,
+            0,
+        );
+        push_map_entry(&mut raw_map, V5CompressionType::CompressionParent, 0, 7, 0);
+        push_map_entry(&mut raw_map, V5CompressionType::CompressionParent, 0, 7, 0);
+        push_map_entry(&mut raw_map, V5CompressionType::CompressionParent, 0, 15, 0);
+
+        let mut offset = 0x1000 + (0..25).map(|n| 100 + n * 7).sum::<u64>() + 5 * 4096 + 1000;
+        for hunk_num in 44..HUNK_COUNT {
+            push_map_entry(
+                &mut raw_map,
+                V5CompressionType::CompressionType2,
+                hunk_num * 13,
+                offset,
+                hunk_num as u16,
+            );
+            offset += hunk_num as u64 * 13;
+        }
+        assert_eq!(raw_map.len(), HUNK_COUNT as usize * 12);
+
+        let mut compressed = Vec::new();
+        let written =
+            ChdMap::write_compressed_v5(&header, &raw_map, &mut compressed).expect("encode map");
+        assert_eq!(written, compressed.len() as u64);
+        // The file must be long enough to hold the hunk data referenced by the map.
+        compressed.resize(offset as usize, 0);
+
+        let header = ChdHeader::V5Header(header);
+        match ChdMap::try_read_map(&header, Cursor::new(compressed)) {
+            Ok(ChdMap::V5(map)) => assert_eq!(Vec::<u8>::from(map), raw_map),
+            Ok(_) => panic!("expected a V5 map"),
+            Err(e) => panic!("could not decode map: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn header_from_reader_test() {
+        let header = v5_header([make_tag(b"zlib"), 0, 0, 0], 8192, 4096);
+        match ChdHeader::from_reader(&mut header.as_slice()) {
+            Ok(ChdHeader::V5Header(header)) => {
+                assert_eq!(header.hunk_bytes, 4096);
+                assert_eq!(header.hunk_count, 2);
+            }
+            Ok(_) => panic!("expected a V5 header"),
+            Err(e) => panic!("could not read header: {:?}", e),
+        }
+
+        let mut wrong_magic = header.clone();
+        wrong_magic[..8].copy_from_slice(b"NotAChd!");
+        assert!(matches!(
+            ChdHeader::from_reader(&mut wrong_magic.as_slice()),
+            Err(ChdError::InvalidFile)
+        ));
+
+        assert!(matches!(
+            ChdHeader::from_reader(&mut &header[..10]),
+            Err(ChdError::InvalidFile)
+        ));
+        assert!(matches!(
+            ChdHeader::from_reader(&mut &header[..100]),
+            Err(ChdError::InvalidFile)
+        ));
+
+        let mut future_version = header;
+        future_version[12..16].copy_from_slice(&6u32.to_be_bytes());
+        assert!(matches!(
+            ChdHeader::from_reader(&mut future_version.as_slice()),
+            Err(ChdError::UnsupportedVersion(6))
+        ));
+    }
+
+    /// Builds a CD frame with the given Q subchannel interleaved into its subcode data.
+    fn cd_frame_with_q(q: &[u8; 12]) -> Vec<u8> {
+        let mut frame = vec![0u8; CD_FRAME_SIZE as usize];
+        for (bit, byte) in frame[CD_MAX_SECTOR_DATA as usize..].iter_mut().enumerate() {
+            *byte = ((q[bit / 8] >> (7 - bit % 8)) & 1) << 6;
+        }
+        frame
+    }
+
+    #[test]
+    fn subcode_report_test() {
+        let mut q = [
+            0x41, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0, 0,
+        ];
+        let crc = Crc::<u16>::new(&CRC_16_GSM).checksum(&q[..10]);
+        q[10..].copy_from_slice(&crc.to_be_bytes());
+        let good = cd_frame_with_q(&q);
+        q[3] ^= 0x10;
+        let bad = cd_frame_with_q(&q);
+
+        // The last frame is a padding frame that is not checked.
+        let mut checker = SubcodeChecker::new(vec![(0..2, CdSubcodeType::Normal)], CD_FRAME_SIZE);
+        checker.push(&[good, bad.clone(), bad].concat());
+        assert_eq!(
+            checker.finish(),
+            SubcodeReport {
+                has_subcode: true,
+                bad_frames: vec![1]
+            }
+        );
+
+        let mut checker = SubcodeChecker::new(vec![(0..2, CdSubcodeType::Normal)], CD_FRAME_SIZE);
+        checker.push(&vec![0u8; 2 * CD_FRAME_SIZE as usize]);
+        assert_eq!(checker.finish(), SubcodeReport::default());
+    }
+
+    #[test]
+    fn hard_disk_unit_bytes_test() {
+        // A V4 header, which does not record the unit size.
+        let mut chd = Vec::new();
+        chd.extend_from_slice(b"MComprHD");
+        chd.extend_from_slice(&108u32.to_be_bytes());
+        chd.extend_from_slice(&4u32.to_be_bytes());
+        // flags, compression, total hunks
+        chd.extend_from_slice(&0u32.to_be_bytes());
+        chd.extend_from_slice(&(CodecType::Zlib as u32).to_be_bytes());
+        chd.extend_from_slice(&2u32.to_be_bytes());
+        chd.extend_from_slice(&8192u64.to_be_bytes());
+        // metadata offset, hunk bytes
+        chd.extend_from_slice(&108u64.to_be_bytes());
+        chd.extend_from_slice(&4096u32.to_be_bytes());
+        // sha1, parent sha1, raw sha1
+        chd.resize(108, 0);
+
+        let geometry = b"CYLS:16,HEADS:1,SECS:1,BPS:512\0";
+        chd.extend_from_slice(&make_tag(b"GDDD").to_be_bytes());
+        chd.extend_from_slice(&(geometry.len() as u32 | 0x01000000).to_be_bytes());
+        chd.extend_from_slice(&0u64.to_be_bytes());
+        chd.extend_from_slice(geometry);
+
+        match ChdHeader::try_read_header(&mut Cursor::new(chd)) {
+            Ok(ChdHeader::V4Header(header)) => {
+                assert_eq!(header.unit_bytes, 512);
+                assert_eq!(header.unit_count, 16);
+            }
+            Ok(_) => panic!("expected a V4 header"),
+            Err(e) => panic!("could not read header: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn subcode_type_test() {
+        let mut q = [
+            0x41, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0, 0,
+        ];
+        let crc = Crc::<u16>::new(&CRC_16_GSM).checksum(&q[..10]);
+        q[10..].copy_from_slice(&crc.to_be_bytes());
+        // The P subchannel is set, Q holds a valid position and R a fixed pattern.
+        let mut channels = [0u8; CD_MAX_SUBCODE_DATA as usize];
+        channels[..12].fill(0xff);
+        channels[12..24].copy_from_slice(&q);
+        channels[24..36].fill(0xa5);
+        let mut interleaved = [0u8; CD_MAX_SUBCODE_DATA as usize];
+        for (bit, byte) in interleaved.iter_mut().enumerate() {
+            for channel in 0..8 {
+                let set = (channels[channel * 12 + bit / 8] >> (7 - bit % 8)) & 1;
+                *byte |= set << (7 - channel);
+            }
+        }
+        assert_eq!(
+            deinterleave_subcode(&interleaved, CdSubcodeType::Normal),
+            channels
+        );
+        assert_eq!(
+            deinterleave_subcode(&channels, CdSubcodeType::Raw),
+            channels
+        );
+
+        for (subtype, stored) in [("RW", interleaved), ("RW_RAW", channels)] {
+            let (mut chd, _) = uncompressed_chd(4 * 2448, 4 * 2448);
+            for frame in 0..4 {
+                let offset = 4 * 2448 + frame * 2448 + 2352;
+                chd[offset..][..96].copy_from_slice(&stored);
+            }
+            let track = format!(
+                "TRACK:1 TYPE:MODE1_RAW SUBTYPE:{} FRAMES:4 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0",
+                subtype
+            );
+            append_metadata(&mut chd, &[(b"CHT2", track.as_bytes())]);
+            let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+            for lba in 0..4 {
+                assert_eq!(chd.read_subcode(lba), Ok(channels), "{}", subtype);
+                let frame = chd.read_full_frame(lba).expect("could not read frame");
+                assert_eq!(frame[2352..], stored[..], "{}", subtype);
+            }
+            if cfg!(feature = "want_subcode") {
+                let (_, report) = chd.verify_with_subcode().expect("could not verify");
+                assert_eq!(
+                    report,
+                    SubcodeReport {
+                        has_subcode: true,
+                        bad_frames: vec![]
+                    },
+                    "{}",
+                    subtype
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decompress_result_add_test() {
+        let frames = DecompressResult::from_parts(2352 * 8, 1000);
+        let subcode = DecompressResult::from_parts(96 * 8, 200);
+        let total = frames + subcode;
+        assert_eq!(total.total_out(), 2448 * 8);
+        assert_eq!(total.total_in(), 1200);
+
+        let mut sum = DecompressResult::default();
+        sum += frames;
+        sum += subcode;
+        assert_eq!(sum, total);
+
+        // Additions saturate instead of overflowing.
+        let huge = DecompressResult::from_parts(usize::MAX, usize::MAX - 1);
+        let total = huge + DecompressResult::from_parts(1, 2);
+        assert_eq!(total.total_out(), usize::MAX);
+        assert_eq!(total.total_in(), usize::MAX);
+    }
+
+    #[test]
+    fn unsupported_codec_test() {
+        let bogus = make_tag(b"bogu");
+        let header = v5_header([make_tag(b"zlib"), bogus, 0, 0], 4096, 4096);
+        match ChdFile::open(Cursor::new(header), None) {
+            Err(ChdError::UnsupportedCodec(tag)) => assert_eq!(tag, bogus),
+            Err(e) => panic!("expected an unsupported codec error, got {:?}", e),
+            Ok(_) => panic!("expected an unsupported codec error"),
+        }
+
+        let mut output = vec![0u8; 4096];
+        assert_eq!(
+            decompress_hunk_blob(*b"bogu", 4096, &[0u8; 16], &mut output),
+            Err(ChdError::UnsupportedCodec(bogus))
+        );
+    }
+
+    #[test]
+    fn max_compressed_size_test() {
+        let hunk_size = 8 * CD_FRAME_SIZE;
+        for codec in supported_codecs() {
+            assert!(codec.max_compressed_size(hunk_size) >= hunk_size as usize);
+        }
+
+        for path in [".testimages/Test.chd", ".testimages/cliffhgr.chd"] {
+            let mut f = BufReader::new(File::open(path).expect(""));
+            let mut chd = ChdFile::open(&mut f, None).expect("file");
+            let hunk_size = chd.header().hunk_size();
+            let max = chd.max_compressed_size();
+            for index in 0..chd.header().hunk_count() {
+                let (compression, data) = chd.read_compressed_hunk(index).expect("hunk");
+                assert!(data.len() <= max);
+                if let HunkCompression::Compressed(codec) = compression {
+                    assert!(data.len() <= codec.max_compressed_size(hunk_size));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn verify_test() {
+        let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+        let digests = chd.verify().expect("could not verify");
+        assert_eq!(Some(digests.raw_sha1), chd.header().raw_sha1());
+        assert_eq!(Some(digests.sha1), chd.header().sha1());
+    }
+
+    #[test]
+    fn read_hunk_without_subcode_test() {
+        let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+
+        let mut full_buf = chd.get_hunksized_buffer();
+        let mut sector_buf = chd.get_hunksized_buffer();
+        let mut cmp_buf = Vec::new();
+        let mut hunk = chd.hunk(0).expect("could not acquire hunk");
+        hunk.read_hunk_without_subcode_in(&mut cmp_buf, &mut sector_buf)
+            .expect("could not read_hunk 0 without subcode");
+        hunk.read_hunk_in(&mut cmp_buf, &mut full_buf)
+            .expect("could not read_hunk 0");
+
+        for (full, sector) in full_buf
+            .chunks_exact(CD_FRAME_SIZE as usize)
+            .zip(sector_buf.chunks_exact(CD_FRAME_SIZE as usize))
+        {
+            let (full_data, _) = full.split_at(CD_MAX_SECTOR_DATA as usize);
+            let (sector_data, subcode) = sector.split_at(CD_MAX_SECTOR_DATA as usize);
+            assert_eq!(full_data, sector_data);
+            assert!(subcode.iter().all(|&b| b == 0));
+        }
+
+        // ranged reads still see the subcode data.
+        let mut subcode = [0u8; CD_MAX_SUBCODE_DATA as usize];
+        chd.read_hunk_range(0, CD_MAX_SECTOR_DATA as usize, &mut subcode)
+            .expect("could not read hunk range");
+        assert_eq!(
+            &full_buf[CD_MAX_SECTOR_DATA as usize..CD_FRAME_SIZE as usize],
+            &subcode
+        );
+    }
+
+    #[cfg(feature = "verify_block_crc")]
+    #[test]
+    fn read_hunk_without_subcode_crc_test() {
+        // A single stored Deflate block with a checksum that does not match its data.
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let compressed = stored_deflate(&data);
+
+        let mut raw_map = Vec::new();
+        push_map_entry(
+            &mut raw_map,
+            V5CompressionType::CompressionType0,
+            compressed.len() as u32,
+            124,
+            !crate::block_hash::CRC16.checksum(&data),
+        );
+
+        let mut chd = v5_header([make_tag(b"zlib"), 0, 0, 0], 4096, 4096);
+        chd.extend_from_slice(&compressed);
+        append_map_v5(&mut chd, &raw_map);
+
+        // Only CD-ROM codecs skip the checksum without subcode data.
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+        let mut buf = vec![0u8; 4096];
+        let mut cmp_buf = Vec::new();
+        let mut hunk = chd.hunk(0).expect("could not acquire hunk");
+        assert_eq!(
+            hunk.read_hunk_without_subcode_in(&mut cmp_buf, &mut buf),
+            Err(ChdError::DecompressionError)
+        );
+    }
+
+    #[test]
+    fn export_gdi_test() {
+        let mut f = BufReader::new(File::open(".testimages/gdrom.chd").expect(""));
+        let mut chd = ChdFile::open(&mut f, None).expect("file");
+        let track_count = chd.cd_toc().expect("could not read toc").tracks.len();
+
+        let dir = std::env::temp_dir().join("chd-rs-export-gdi-test");
+        std::fs::create_dir_all(&dir).expect("could not create output directory");
+        chd.export_gdi(&dir).expect("could not export gdi");
+
+        let gdi = std::fs::read_to_string(dir.join("disc.gdi")).expect("could not read gdi");
+        let mut lines = gdi.lines();
+        assert_eq!(lines.next(), Some(track_count.to_string().as_str()));
+        assert_eq!(lines.count(), track_count);
+    }
+
+    #[test]
+    fn sector_edc_test() {
+        // The EDC polynomial x^32 + x^31 + x^16 + x^15 + x^4 + x^3 + x + 1, bit-reversed.
+        fn edc(data: &[u8]) -> u32 {
+            data.iter().fold(0u32, |mut edc, &byte| {
+                edc ^= byte as u32;
+                for _ in 0..8 {
+                    edc = (edc >> 1) ^ if edc & 1 != 0 { 0xd801_8001 } else { 0 };
+                }
+                edc
+            })
+        }
+
+        // A Mode 1 sector at 00:02:00.
+        let mut sector = [0u8; CD_MAX_SECTOR_DATA as usize];
+        sector[..12].copy_from_slice(&CD_SYNC_HEADER);
+        sector[12..16].copy_from_slice(&[0x00, 0x02, 0x00, 0x01]);
+        for (i, byte) in sector[16..0x810].iter_mut().enumerate() {
+            *byte = (i * 7 % 256) as u8;
+        }
+        let checksum = edc(&sector[..0x810]);
+        sector[0x810..0x814].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(sector_type(&sector), SectorType::Mode1);
+        assert!(verify_edc(&sector));
+
+        let mut corrupted = sector;
+        corrupted[0x100] ^= 0x01;
+        assert!(!verify_edc(&corrupted));
+
+        // A Mode 2 Form 1 sector covers the subheader, but not the header.
+        let mut form1 = sector;
+        form1[15] = 2;
+        form1[16..24].copy_from_slice(&[0, 0, 0x08, 0, 0, 0, 0x08, 0]);
+        let checksum = edc(&form1[0x10..0x818]);
+        form1[0x818..0x81c].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(sector_type(&form1), SectorType::Mode2Form1);
+        assert!(verify_edc(&form1));
+
+        // Form 2 sectors may leave the EDC empty.
+        let mut form2 = form1;
+        form2[0x12] = 0x20;
+        form2[0x92c..].fill(0);
+        assert_eq!(sector_type(&form2), SectorType::Mode2Form2);
+        assert!(verify_edc(&form2));
+
+        let audio = [0x55u8; CD_MAX_SECTOR_DATA as usize];
+        assert_eq!(sector_type(&audio), SectorType::Audio);
+        assert!(!verify_edc(&audio));
+    }
+
+    #[test]
+    fn sector_test() {
+        // A Mode 1 sector at 12:34:56.
+        let mut raw = [0u8; CD_MAX_SECTOR_DATA as usize];
+        raw[..12].copy_from_slice(&CD_SYNC_HEADER);
+        raw[12..16].copy_from_slice(&[0x12, 0x34, 0x56, 0x01]);
+        for (i, byte) in raw[16..].iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+        let mode1 = Sector::new(raw, None);
+        assert_eq!(mode1.mode(), SectorType::Mode1);
+        assert_eq!(mode1.data(), &raw[16..0x810]);
+        assert_eq!(mode1.msf(), Some((12, 34, 56)));
+        assert_eq!(mode1.subheader(), None);
+        assert_eq!(mode1.subcode(), None);
+
+        let mut form1 = raw;
+        form1[15] = 2;
+        form1[16..24].copy_from_slice(&[1, 2, 0x08, 0, 1, 2, 0x08, 0]);
+        let form1 = Sector::new(form1, None);
+        assert_eq!(form1.mode(), SectorType::Mode2Form1);
+        assert_eq!(form1.data().len(), 2048);
+        assert_eq!(form1.data(), &form1.raw()[24..0x818]);
+        assert_eq!(form1.subheader(), Some([1, 2, 0x08, 0]));
+
+        let mut form2 = *form1.raw();
+        form2[0x12] = 0x20;
+        let form2 = Sector::new(form2, None);
+        assert_eq!(form2.mode(), SectorType::Mode2Form2);
+        assert_eq!(form2.data().len(), 2324);
+        assert_eq!(form2.data(), &form2.raw()[24..0x92c]);
+        assert_eq!(form2.subheader(), Some([1, 2, 0x20, 0]));
+
+        let mut mode0 = raw;
+        mode0[15] = 0;
+        mode0[16..].fill(0);
+        let mode0 = Sector::new(mode0, None);
+        assert_eq!(mode0.mode(), SectorType::Mode0);
+        assert_eq!(mode0.data(), &[0u8; 2336][..]);
+
+        let mut frame = [0x55u8; CD_FRAME_SIZE as usize];
+        frame[CD_MAX_SECTOR_DATA as usize..].fill(0xaa);
+        let audio = Sector::from_frame(&frame);
+        assert_eq!(audio.mode(), SectorType::Audio);
+        assert_eq!(audio.data(), &frame[..CD_MAX_SECTOR_DATA as usize]);
+        assert_eq!(audio.msf(), None);
+        assert_eq!(audio.subheader(), None);
+        assert_eq!(audio.subcode(), Some(&[0xaa; CD_MAX_SUBCODE_DATA as usize]));
+    }
+
+    #[test]
+    fn leadout_test() {
+        // FILE "disc.bin" BINARY
+        //   TRACK 01 MODE1/2352
+        //     INDEX 01 00:00:00
+        //   TRACK 02 AUDIO
+        //     INDEX 00 00:15:25
+        //     INDEX 01 00:17:25
+        //   TRACK 03 AUDIO
+        //     PREGAP 00:02:00
+        //     INDEX 01 00:28:53
+        // The pregap of track 3 is not stored in the file, which moves the lead-out from the
+        // end of the file at 01:23:05 to LBA 6380, at 01:25:05.
+        let tracks: [&[u8]; 3] = [
+            b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:1150 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0",
+            b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:1003 PREGAP:150 PGTYPE:VAUDIO PGSUB:RW POSTGAP:0",
+            b"TRACK:3 TYPE:AUDIO SUBTYPE:NONE FRAMES:4077 PREGAP:150 PGTYPE:AUDIO PGSUB:RW POSTGAP:0",
+        ];
+        let toc = CdToc::from_metadata(tracks.iter().map(|&t| (make_tag(b"CHT2"), t)))
+            .expect("could not parse toc");
+        assert_eq!(toc.leadout_lba(), 6380);
+        assert_eq!(toc.total_frames(), 6380 + 150);
+
+        // A postgap after the last track is part of the disc.
+        let tracks: [&[u8]; 1] =
+            [b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:1000 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:150"];
+        let toc = CdToc::from_metadata(tracks.iter().map(|&t| (make_tag(b"CHT2"), t)))
+            .expect("could not parse toc");
+        assert_eq!(toc.leadout_lba(), 1150);
+        assert_eq!(toc.total_frames(), 1300);
+    }
+
+    #[test]
+    fn cue_roundtrip_test() {
+        let tracks: [&[u8]; 2] = [
+            b"TRACK:1 TYPE:MODE1 SUBTYPE:NONE FRAMES:1150 PREGAP:0 PGSUB:NONE POSTGAP:0",
+            b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:1003 PREGAP:150 PGTYPE:VAUDIO PGSUB:NONE POSTGAP:150",
+        ];
+        let toc = CdToc::from_metadata(tracks.iter().map(|&t| (make_tag(b"CHT2"), t)))
+            .expect("could not parse toc");
+
+        let cue = toc.to_cue("disc.bin");
+        assert_eq!(
+            cue,
+            "FILE \"disc.bin\" BINARY\n\
+             \x20 TRACK 01 MODE1/2048\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:15:25\n\
+             \x20   INDEX 01 00:17:25\n\
+             \x20   POSTGAP 00:02:00\n"
+        );
+        let bin_len = 1150 * 2048 + 1003 * 2352;
+        assert_eq!(CdToc::from_cue(&cue, bin_len), Ok(toc));
+
+        // A pregap that is not stored in the file, with a later index that is ignored.
+        let cue = "FILE \"disc.bin\" BINARY\n\
+                   TRACK 01 MODE2/2336\n\
+                   INDEX 01 00:00:00\n\
+                   TRACK 02 MODE2/2352\n\
+                   PREGAP 00:02:00\n\
+                   INDEX 01 00:10:00\n\
+                   INDEX 02 00:11:00\n";
+        let bin_len = 750 * 2336 + 750 * 2352;
+        let toc = CdToc::from_cue(cue, bin_len).expect("could not parse cue");
+        assert_eq!(toc.tracks.len(), 2);
+        assert_eq!(toc.tracks[0].track_type, CdTrackType::Mode2);
+        assert_eq!(toc.tracks[0].frames, 750);
+        assert_eq!(toc.tracks[1].track_type, CdTrackType::Mode2Raw);
+        assert_eq!(toc.tracks[1].frames, 750);
+        assert_eq!(toc.tracks[1].pregap, 150);
+        assert!(!toc.tracks[1].pregap_in_file);
+        assert_eq!(track_layout(&toc)[1].lba, 900);
+        assert_eq!(CdToc::from_cue(&toc.to_cue("disc.bin"), bin_len), Ok(toc));
+    }
+
+    #[test]
+    fn cue_bin_length_test() {
+        // The final track extends to the end of the binary file, which must end on a whole
+        // frame of the final track after the other tracks.
+        let cue = "FILE \"disc.bin\" BINARY\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n";
+        assert_eq!(
+            CdToc::from_cue(cue, 100 * 2352).map(|toc| toc.tracks[0].frames),
+            Ok(100)
+        );
+        assert_eq!(
+            CdToc::from_cue(cue, 100 * 2352 + 1),
+            Err(ChdError::InvalidData)
+        );
+        let cue = "FILE \"disc.bin\" BINARY\n\
+                   TRACK 01 MODE1/2048\n\
+                   INDEX 01 00:00:00\n\
+                   TRACK 02 AUDIO\n\
+                   INDEX 01 00:10:00\n";
+        assert_eq!(CdToc::from_cue(cue, 700 * 2048), Err(ChdError::InvalidData));
+    }
+
+    #[test]
+    fn track_layout_test() {
+        let tracks: [&[u8]; 3] = [
+            b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:1150 PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0",
+            b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:1003 PREGAP:150 PGTYPE:VAUDIO PGSUB:RW POSTGAP:0",
+            b"TRACK:3 TYPE:AUDIO SUBTYPE:NONE FRAMES:4077 PREGAP:150 PGTYPE:AUDIO PGSUB:RW POSTGAP:0",
+        ];
+        let toc = CdToc::from_metadata(tracks.iter().map(|&t| (make_tag(b"CHT2"), t)))
+            .expect("could not parse toc");
+
+        assert_eq!(
+            track_layout(&toc),
+            vec![
+                TrackPlacement {
+                    lba: 0,
+                    chd_frame: 0,
+                    frames: 1150,
+                    pad_frames: 2
+                },
+                TrackPlacement {
+                    lba: 1150,
+                    chd_frame: 1152,
+                    frames: 1003,
+                    pad_frames: 1
+                },
+                TrackPlacement {
+                    lba: 2303,
+                    chd_frame: 2156,
+                    frames: 4077,
+                    pad_frames: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn export_gdi_layout_test() {
+        let (mut chd, _) = uncompressed_chd(12 * 2448, 4 * 2448);
+        append_metadata(
+            &mut chd,
+            &[
+                (b"CHGD", b"TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PAD:0 PREGAP:0 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0\0"),
+                (b"CHGD", b"TRACK:2 TYPE:AUDIO SUBTYPE:NONE FRAMES:4 PAD:0 PREGAP:150 PGTYPE:AUDIO PGSUB:NONE POSTGAP:0\0"),
+                (b"CHGD", b"TRACK:3 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:4 PAD:0 PREGAP:150 PGTYPE:MODE1 PGSUB:NONE POSTGAP:0\0"),
+            ],
+        );
+        let mut chd = ChdFile::from_bytes(&chd).expect("file");
+
+        let dir = std::env::temp_dir().join("chd-rs-export-gdi-layout-test");
+        std::fs::create_dir_all(&dir).expect("could not create output directory");
+        chd.export_gdi(&dir).expect("could not export gdi");
+
+        let gdi = std::fs::read_to_string(dir.join("disc.gdi")).expect("could not read gdi");
+        assert_eq!(
+            gdi.lines().collect::<Vec<_>>(),
+            [
+                "3",
+                "1 0 4 2352 track01.bin 0",
+                "2 154 0 2352 track02.raw 0",
+                "3 45000 4 2352 track03.bin 0",
+            ]
+        );
+        let track = std::fs::read(dir.join("track03.bin")).expect("could not read track");
+        assert_eq!(track.len(), 4 * 2352);
+    }
+
     #[test]
     fn read_file_test() {
         let mut f = BufReader::new(File::open(".testimages/Test.chd").expect(""));
@@ -1,17 +1,18 @@
 //! Types and methods relating to the CHD hunk map.
 
 use std::convert::TryFrom;
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
 
 use bitreader::BitReader;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
 
+use crate::bitwriter::BitWriter;
 use crate::const_assert;
 use crate::error::{ChdError, Result};
-use crate::header::{ChdHeader, HeaderV5};
-use crate::huffman::{lookup_len, HuffmanDecoder};
+use crate::header::{ChdHeader, CodecType, HeaderV5};
+use crate::huffman::{lookup_len, HuffmanDecoder, HuffmanEncoder};
 
 pub(crate) const V5_UNCOMPRESSED_MAP_ENTRY_SIZE: usize = 4;
 pub(crate) const V5_COMPRESSED_MAP_ENTRY_SIZE: usize = 12;
@@ -22,7 +23,7 @@ const MAP_ENTRY_FLAG_NO_CRC: u8 = 0x10; // no crc is present
 
 /// The types of compression allowed for a CHD V5 hunk.
 #[repr(u8)]
-#[derive(FromPrimitive, ToPrimitive)]
+#[derive(FromPrimitive, ToPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum V5CompressionType {
     CompressionType0 = 0,
     CompressionType1 = 1,
@@ -60,6 +61,71 @@ pub enum LegacyEntryType {
     ExternalCompressed = 6,
 }
 
+/// How the data of a hunk is stored in a CHD file, as reported by
+/// [`ChdFile::read_compressed_hunk`](crate::ChdFile::read_compressed_hunk).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HunkCompression {
+    /// The hunk is compressed with the given codec.
+    Compressed(CodecType),
+    /// The hunk is stored uncompressed.
+    Uncompressed,
+    /// The hunk is a V1-4 mini hunk, which repeats a 64-bit big-endian value.
+    Mini,
+    /// The hunk is a copy of the hunk with the given number in the same CHD file.
+    SelfHunk(u32),
+    /// The hunk is a copy of data in the parent CHD file. For V5 CHD files, this is the
+    /// number of the unit the copy begins at, and for V1-4 CHD files, the number of the hunk.
+    Parent(u64),
+    /// The hunk is not stored in the file, or is stored compressed without any data, and is
+    /// filled with zeroes.
+    Zero,
+}
+
+/// Statistics about how the hunks of a CHD file are stored, as returned by
+/// [`ChdFile::compression_stats`](crate::ChdFile::compression_stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// The statistics of each codec used by compressed hunks, in order of first use.
+    pub codecs: Vec<CodecStats>,
+    /// The number of hunks stored uncompressed, including mini hunks.
+    pub uncompressed_hunks: u32,
+    /// The number of hunks that are copies of another hunk or of the parent.
+    pub copied_hunks: u32,
+    /// The number of hunks that are not stored in the file.
+    pub zero_hunks: u32,
+}
+
+impl CompressionStats {
+    /// Returns the statistics of the given codec, if any hunk is compressed with it.
+    pub fn codec(&self, codec: CodecType) -> Option<&CodecStats> {
+        self.codecs.iter().find(|stats| stats.codec == codec)
+    }
+}
+
+/// Statistics about the hunks compressed with a single codec.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CodecStats {
+    /// The codec the hunks are compressed with.
+    pub codec: CodecType,
+    /// The number of hunks compressed with the codec.
+    pub hunks: u32,
+    /// The number of bytes the hunks decompress to.
+    pub logical_bytes: u64,
+    /// The number of bytes the hunks take up in the file.
+    pub compressed_bytes: u64,
+}
+
+impl CodecStats {
+    /// Returns the ratio of compressed bytes to logical bytes for the hunks compressed with
+    /// the codec, where a smaller ratio means better compression.
+    pub fn ratio(&self) -> f64 {
+        if self.logical_bytes == 0 {
+            return 0.0;
+        }
+        self.compressed_bytes as f64 / self.logical_bytes as f64
+    }
+}
+
 /// Opaque type for a V5 map.
 pub struct V5MapData(Vec<u8>, bool, u32);
 /// Opaque type for a legacy map.
@@ -226,6 +292,33 @@ pub enum MapEntry<'a> {
     LegacyEntry(&'a LegacyMapEntry),
 }
 
+impl MapEntry<'_> {
+    /// Returns the offset and length of the data of the hunk that is stored in the file,
+    /// if the hunk is stored in the file as compressed or uncompressed data.
+    pub(crate) fn stored_extent(&self) -> Option<(u64, u32)> {
+        match self {
+            MapEntry::V5Compressed(entry) => match entry.prove_compressed() {
+                Ok(proof) => Some((proof.block_offset(), proof.block_size())),
+                Err(_) => entry
+                    .prove_uncompressed()
+                    .ok()
+                    .map(|proof| (proof.block_offset(), proof.block_size())),
+            },
+            MapEntry::V5Uncompressed(entry) => match entry.block_offset() {
+                Ok(0) | Err(_) => None,
+                Ok(offset) => Some((offset, entry.block_size())),
+            },
+            MapEntry::LegacyEntry(entry) => match entry.prove_compressed() {
+                Ok(proof) => Some((proof.block_offset(), proof.block_size())),
+                Err(_) => entry
+                    .prove_uncompressed()
+                    .ok()
+                    .map(|proof| (proof.block_offset(), proof.block_size())),
+            },
+        }
+    }
+}
+
 /// A proof that a hunk is compressed.
 /// An instance of this type can only be constructed from an compressed hunk.
 pub(crate) struct CompressedEntryProof(u64, u32);
@@ -323,15 +416,30 @@ impl ChdMap {
         MapEntryIter { map: self, curr: 0 }
     }
 
+    /// Compresses a raw CHD V5 hunk map and writes it to the provided stream, as read by
+    /// [`try_read_map`](ChdMap::try_read_map) for the given header.
+    ///
+    /// The raw map has the same layout as the decompressed V5 map data. Each 12 byte entry holds
+    /// the hunk type, the 24-bit length and 48-bit offset of the hunk data, and the CRC16 of the
+    /// hunk. The data of compressed and uncompressed hunks must be stored in order of their hunk
+    /// number without gaps, since the compressed map only records their lengths. Self references
+    /// hold the referenced hunk number and parent references hold the referenced unit number of
+    /// the parent.
+    ///
+    /// Returns the number of bytes written.
+    pub fn write_compressed_v5<W: Write>(header: &HeaderV5, raw_map: &[u8], out: W) -> Result<u64> {
+        write_map_v5(header, raw_map, out)
+    }
+
     /// Reads the hunk map from the provided stream given the parameters in the header,
     /// which must have the same stream provenance as the input header.
     pub fn try_read_map<F: Read + Seek>(header: &ChdHeader, mut file: F) -> Result<ChdMap> {
         match header {
-            ChdHeader::V5Header(v5) => Ok(ChdMap::V5(read_map_v5(
-                v5,
-                &mut file,
-                header.is_compressed(),
-            )?)),
+            ChdHeader::V5Header(v5) => {
+                let map = ChdMap::V5(read_map_v5(v5, &mut file, header.is_compressed())?);
+                ensure_file_len(max_hunk_end_v5(v5, &map)?, file.seek(SeekFrom::End(0))?)?;
+                Ok(map)
+            }
             ChdHeader::V3Header(_) | ChdHeader::V4Header(_) => {
                 Ok(ChdMap::Legacy(LegacyMapData(read_map_legacy::<
                     _,
@@ -403,8 +511,12 @@ fn read_map_legacy<F: Read + Seek, const MAP_ENTRY_SIZE: usize>(
         return Err(ChdError::InvalidFile);
     }
 
-    if max_off > file.seek(SeekFrom::End(0))? {
-        return Err(ChdError::InvalidFile);
+    let file_len = file.seek(SeekFrom::End(0))?;
+    if max_off > file_len {
+        return Err(ChdError::TruncatedFile {
+            expected: max_off,
+            actual: file_len,
+        });
     }
 
     Ok(map)
@@ -451,14 +563,17 @@ fn read_map_v5<F: Read + Seek>(
 ) -> Result<V5MapData> {
     let map_size = header.hunk_count as usize * header.map_entry_bytes as usize;
     let mut raw_map = vec![0u8; map_size];
+    let file_len = file.seek(SeekFrom::End(0))?;
 
     if !is_compressed {
+        ensure_file_len(header.map_offset + map_size as u64, file_len)?;
         file.seek(SeekFrom::Start(header.map_offset))?;
         file.read_exact(&mut raw_map[..])?;
         return Ok(V5MapData(raw_map, is_compressed, header.hunk_bytes));
     }
 
     // Read compressed map parameters.
+    ensure_file_len(header.map_offset + 16, file_len)?;
     file.seek(SeekFrom::Start(header.map_offset))?;
 
     let map_bytes = file.read_u32::<BigEndian>()?;
@@ -469,6 +584,7 @@ fn read_map_v5<F: Read + Seek>(
     let parent_bits = file.read_u8()?;
 
     // Read the map data
+    ensure_file_len(header.map_offset + 16 + map_bytes as u64, file_len)?;
     let mut compressed: Vec<u8> = vec![0u8; map_bytes as usize];
     file.seek(SeekFrom::Start(header.map_offset + 16))?;
     file.read_exact(&mut compressed[..])?;
@@ -558,7 +674,9 @@ fn read_map_v5<F: Read + Seek>(
                 last_parent = off;
             }
             V5CompressionType::CompressionParent1 => {
-                last_parent += (header.hunk_bytes / header.unit_bytes) as u64
+                last_parent += (header.hunk_bytes / header.unit_bytes) as u64;
+                map_slice[0] = V5CompressionType::CompressionParent as u8;
+                off = last_parent;
             }
             V5CompressionType::CompressionParent0 => {
                 map_slice[0] = V5CompressionType::CompressionParent as u8;
@@ -580,3 +698,241 @@ fn read_map_v5<F: Read + Seek>(
 
     Ok(V5MapData(raw_map, is_compressed, header.hunk_bytes))
 }
+
+/// Returns `ChdError::TruncatedFile` if the file is shorter than the expected length.
+fn ensure_file_len(expected: u64, actual: u64) -> Result<()> {
+    if expected > actual {
+        return Err(ChdError::TruncatedFile { expected, actual });
+    }
+    Ok(())
+}
+
+/// Returns the end of the hunk data stored in the file that lies furthest into the file.
+///
+/// An uncompressed final hunk only has to be stored up to the logical size of the CHD file.
+fn max_hunk_end_v5(header: &HeaderV5, map: &ChdMap) -> Result<u64> {
+    let mut max_end = 0;
+    let final_hunk_len = header.logical_bytes
+        - (header.hunk_count as u64).saturating_sub(1) * header.hunk_bytes as u64;
+    for hunk_num in 0..header.hunk_count {
+        let len = |len: u32| {
+            if hunk_num == header.hunk_count - 1 {
+                std::cmp::min(len as u64, final_hunk_len)
+            } else {
+                len as u64
+            }
+        };
+        let end = match map.get_entry(hunk_num as usize) {
+            Some(MapEntry::V5Compressed(entry)) => match entry.hunk_type()? {
+                V5CompressionType::CompressionType0
+                | V5CompressionType::CompressionType1
+                | V5CompressionType::CompressionType2
+                | V5CompressionType::CompressionType3 => {
+                    entry.block_offset()? + entry.block_size()? as u64
+                }
+                V5CompressionType::CompressionNone => {
+                    entry.block_offset()? + len(entry.block_size()?)
+                }
+                _ => 0,
+            },
+            Some(MapEntry::V5Uncompressed(entry)) => match entry.block_offset()? {
+                0 => 0,
+                offset => offset + len(entry.block_size()),
+            },
+            _ => 0,
+        };
+        max_end = std::cmp::max(max_end, end);
+    }
+    Ok(max_end)
+}
+
+/// Returns the number of bits needed to hold the value.
+fn bits_for_value(value: u64) -> u8 {
+    (u64::BITS - value.leading_zeros()) as u8
+}
+
+fn write_map_v5<W: Write>(header: &HeaderV5, raw_map: &[u8], mut out: W) -> Result<u64> {
+    if raw_map.len() != header.hunk_count as usize * V5_COMPRESSED_MAP_ENTRY_SIZE {
+        return Err(ChdError::InvalidParameter);
+    }
+    let units_in_hunk = (header.hunk_bytes / header.unit_bytes) as u64;
+
+    // The map CRC is computed over the map as it is decompressed.
+    let mut expanded_map = vec![0u8; raw_map.len()];
+    let mut types = Vec::with_capacity(header.hunk_count as usize);
+
+    let mut first_offs = None;
+    let mut curr_off = 0;
+    let mut last_self = 0;
+    let mut last_parent = 0;
+    let (mut max_len, mut max_self, mut max_parent) = (0, 0, 0);
+
+    // Pick the type of each entry, turning references into the pseudo types where possible.
+    for (hunk_num, (map_slice, expanded_slice)) in raw_map
+        .chunks_exact(V5_COMPRESSED_MAP_ENTRY_SIZE)
+        .zip(expanded_map.chunks_exact_mut(V5_COMPRESSED_MAP_ENTRY_SIZE))
+        .enumerate()
+    {
+        let entry = V5CompressedEntry(
+            <&[u8; V5_COMPRESSED_MAP_ENTRY_SIZE]>::try_from(map_slice)
+                .map_err(|_| ChdError::InvalidParameter)?,
+        );
+        let mut len = 0;
+        let mut crc = 0;
+        let off = entry.block_offset()?;
+
+        let hunk_type = match entry.hunk_type().map_err(|_| ChdError::InvalidParameter)? {
+            hunk_type @ (V5CompressionType::CompressionType0
+            | V5CompressionType::CompressionType1
+            | V5CompressionType::CompressionType2
+            | V5CompressionType::CompressionType3
+            | V5CompressionType::CompressionNone) => {
+                len = entry.block_size()?;
+                crc = entry.hunk_crc()?;
+                if let V5CompressionType::CompressionNone = hunk_type {
+                    if len != header.hunk_bytes {
+                        return Err(ChdError::InvalidParameter);
+                    }
+                } else {
+                    max_len = max_len.max(len);
+                }
+
+                let expected_off = *first_offs.get_or_insert(off) + curr_off;
+                if off != expected_off {
+                    return Err(ChdError::InvalidParameter);
+                }
+                curr_off += len as u64;
+                hunk_type
+            }
+            V5CompressionType::CompressionSelf => {
+                let hunk_type = if off == last_self {
+                    V5CompressionType::CompressionSelf0
+                } else if off == last_self + 1 {
+                    V5CompressionType::CompressionSelf1
+                } else {
+                    max_self = max_self.max(off);
+                    V5CompressionType::CompressionSelf
+                };
+                last_self = off;
+                hunk_type
+            }
+            V5CompressionType::CompressionParent => {
+                let hunk_type = if off == hunk_num as u64 * units_in_hunk {
+                    V5CompressionType::CompressionParentSelf
+                } else if off == last_parent {
+                    V5CompressionType::CompressionParent0
+                } else if off == last_parent + units_in_hunk {
+                    V5CompressionType::CompressionParent1
+                } else {
+                    max_parent = max_parent.max(off);
+                    V5CompressionType::CompressionParent
+                };
+                last_parent = off;
+                hunk_type
+            }
+            _ => return Err(ChdError::InvalidParameter),
+        };
+        types.push(hunk_type);
+
+        expanded_slice[0] = map_slice[0];
+        let mut cursor = Cursor::new(&mut expanded_slice[1..]);
+        cursor.write_u24::<BigEndian>(len)?;
+        cursor.write_u48::<BigEndian>(off)?;
+        cursor.write_u16::<BigEndian>(crc)?;
+    }
+
+    // Collapse runs of the same type into RLE codes.
+    let mut codes = Vec::with_capacity(types.len());
+    let mut curr = 0;
+    while curr < types.len() {
+        let hunk_type = types[curr] as u32;
+        let run_len = types[curr..]
+            .iter()
+            .take_while(|&&t| t as u32 == hunk_type)
+            .count();
+        curr += run_len;
+
+        // An RLE code repeats the previous type for its own entry, and for the count of
+        // entries following it.
+        codes.push(hunk_type);
+        let mut remaining = run_len - 1;
+        while remaining > 0 {
+            if remaining >= 1 + 2 + 16 {
+                let rep_count = remaining.min(1 + 2 + 16 + 0xff) - (1 + 2 + 16);
+                codes.push(V5CompressionType::CompressionRleLarge as u32);
+                codes.push((rep_count >> 4) as u32);
+                codes.push((rep_count & 0xf) as u32);
+                remaining -= 1 + 2 + 16 + rep_count;
+            } else if remaining >= 1 + 2 {
+                codes.push(V5CompressionType::CompressionRleSmall as u32);
+                codes.push((remaining - (1 + 2)) as u32);
+                remaining = 0;
+            } else {
+                codes.push(hunk_type);
+                remaining -= 1;
+            }
+        }
+    }
+
+    let mut encoder = HuffmanEncoder::<16, 8>::new();
+    for &code in &codes {
+        encoder.histo_one(code);
+    }
+    encoder
+        .compute_tree_from_histo()
+        .map_err(|_| ChdError::CompressionError)?;
+
+    let length_bits = bits_for_value(max_len as u64);
+    let self_bits = bits_for_value(max_self);
+    let parent_bits = bits_for_value(max_parent);
+
+    let mut bitstream = BitWriter::new();
+    encoder.export_tree_rle(&mut bitstream);
+    for &code in &codes {
+        encoder
+            .encode_one(&mut bitstream, code)
+            .map_err(|_| ChdError::CompressionError)?;
+    }
+
+    for (map_slice, hunk_type) in expanded_map
+        .chunks_exact(V5_COMPRESSED_MAP_ENTRY_SIZE)
+        .zip(types)
+    {
+        let entry = V5CompressedEntry(
+            <&[u8; V5_COMPRESSED_MAP_ENTRY_SIZE]>::try_from(map_slice)
+                .map_err(|_| ChdError::InvalidParameter)?,
+        );
+        match hunk_type {
+            V5CompressionType::CompressionType0
+            | V5CompressionType::CompressionType1
+            | V5CompressionType::CompressionType2
+            | V5CompressionType::CompressionType3 => {
+                bitstream.write(entry.block_size()? as u64, length_bits);
+                bitstream.write(entry.hunk_crc()? as u64, 16);
+            }
+            V5CompressionType::CompressionNone => {
+                bitstream.write(entry.hunk_crc()? as u64, 16);
+            }
+            V5CompressionType::CompressionSelf => {
+                bitstream.write(entry.block_offset()?, self_bits);
+            }
+            V5CompressionType::CompressionParent => {
+                bitstream.write(entry.block_offset()?, parent_bits);
+            }
+            _ => {}
+        }
+    }
+    let compressed = bitstream.finish();
+
+    // Write compressed map parameters.
+    out.write_u32::<BigEndian>(compressed.len() as u32)?;
+    out.write_u48::<BigEndian>(first_offs.unwrap_or(0))?;
+    out.write_u16::<BigEndian>(crate::block_hash::CRC16.checksum(&expanded_map))?;
+    out.write_u8(length_bits)?;
+    out.write_u8(self_bits)?;
+    out.write_u8(parent_bits)?;
+    out.write_u8(0)?;
+    out.write_all(&compressed)?;
+
+    Ok(16 + compressed.len() as u64)
+}
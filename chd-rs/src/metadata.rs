@@ -7,6 +7,8 @@ use std::convert::TryInto;
 use std::io::{Cursor, Read, Seek, SeekFrom};
 
 const METADATA_HEADER_SIZE: usize = 16;
+// Metadata entries with this flag are included in the SHA1 hash of the CHD file.
+pub(crate) const METADATA_FLAG_CHECKSUM: u8 = 0x01;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
@@ -26,6 +28,7 @@ pub enum KnownMetadata {
     GdRomTrack = make_tag(b"CHGD"),
     AudioVideo = make_tag(b"AVAV"),
     AudioVideoLaserDisc = make_tag(b"AVLD"),
+    Dvd = make_tag(b"DVD "),
 }
 
 impl KnownMetadata {
@@ -78,6 +81,35 @@ impl ChdMetadataTag for ChdMetadata {
     }
 }
 
+impl ChdMetadata {
+    /// Returns the contents of this metadata entry in the human-readable form printed by
+    /// `chdman info`.
+    ///
+    /// Entries with a tag of a known text format, such as hard disk geometry and CD-ROM or
+    /// GD-ROM tracks, are returned as text without the terminating NUL. The contents of all
+    /// other entries, as well as text entries that are not printable ASCII, are returned as
+    /// space-separated hexadecimal bytes.
+    pub fn to_text(&self) -> String {
+        let is_text = matches!(
+            KnownMetadata::from_u32(self.metatag),
+            Some(KnownMetadata::HardDisk)
+                | Some(KnownMetadata::CdRomTrack)
+                | Some(KnownMetadata::CdRomTrack2)
+                | Some(KnownMetadata::GdRomTrack)
+                | Some(KnownMetadata::AudioVideo)
+        );
+        let text = self.value.strip_suffix(b"\0").unwrap_or(&self.value);
+        if is_text && text.iter().all(|&b| b == b' ' || b.is_ascii_graphic()) {
+            return String::from_utf8_lossy(text).into_owned();
+        }
+        self.value
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
 /// A reference to a metadata entry within the CHD file.
 #[derive(Clone)]
 pub struct MetadataRef {
@@ -108,6 +140,16 @@ impl MetadataRef {
             length: self.length,
         })
     }
+
+    /// Returns the flags of the referenced metadata entry.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the index of the referenced metadata entry among the entries with the same tag.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
 }
 
 impl ChdMetadataTag for MetadataRef {
@@ -180,7 +222,7 @@ impl<'a, F: Read + Seek + 'a> Iterator for MetadataRefIter<'a, F> {
             s.file.seek(SeekFrom::Start(s.curr_offset))?;
             let count = s.file.read(&mut raw_header)?;
             if count != METADATA_HEADER_SIZE {
-                return Err(ChdError::MetadataNotFound);
+                return Err(ChdError::ReadError);
             }
             let mut cursor = Cursor::new(raw_header);
             cursor.seek(SeekFrom::Start(0))?;
@@ -0,0 +1,43 @@
+use crate::error::Result;
+use crate::header::ChdHeader;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Searches a directory for the parent of a CHD file, returning the path of the first
+/// `.chd` file whose SHA1 hash matches the parent SHA1 hash recorded in the child header.
+///
+/// Only the header of each candidate is read. Files that are not valid CHD files are skipped.
+/// Candidates are checked in order of their file name.
+///
+/// If the child does not require a parent, or is a V1-2 CHD file that identifies its parent
+/// by MD5 hash only, returns `None`.
+pub fn find_parent(dir: &Path, child: &ChdHeader) -> Result<Option<PathBuf>> {
+    let parent_sha1 = match child.parent_sha1() {
+        Some(parent_sha1) if child.has_parent() => parent_sha1,
+        _ => return Ok(None),
+    };
+
+    let mut candidates = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_chd = path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("chd"));
+        if is_chd && path.is_file() {
+            candidates.push(path);
+        }
+    }
+    candidates.sort();
+
+    for path in candidates {
+        let header = File::open(&path)
+            .map_err(Into::into)
+            .and_then(|mut file| ChdHeader::from_reader(&mut file));
+        if let Ok(header) = header {
+            if header.sha1() == Some(parent_sha1) {
+                return Ok(Some(path));
+            }
+        }
+    }
+    Ok(None)
+}
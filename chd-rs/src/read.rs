@@ -5,9 +5,14 @@
 //! an internal buffer of decompressed hunk data. For the best performance and flexibility,
 //! [`ChdHunk::read_hunk_in`](crate::chdfile::ChdHunk::read_hunk_in) should be used which will
 //! avoid unnecessary buffering.
+//!
+//! [`CachedReader`](crate::read::CachedReader) works in the other direction, and caches the
+//! compressed file data read from a slow source such as a network stream.
 use crate::error::Result;
 use crate::{ChdError, ChdFile, ChdHunk};
-use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, PoisonError};
 
 /// Buffered `BufRead + Seek` adapter for [`ChdHunk`](crate::chdfile::ChdHunk).
 pub struct ChdHunkBufReader {
@@ -48,6 +53,13 @@ impl ChdHunkBufReader {
         })
     }
 
+    /// Creates a `ChdHunkBufReader` over already decompressed hunk contents.
+    pub(crate) fn from_buffer(buffer: Vec<u8>) -> Self {
+        ChdHunkBufReader {
+            inner: Cursor::new(buffer),
+        }
+    }
+
     /// Consumes the reader and returns the underlying value.
     pub fn into_inner(self) -> Vec<u8> {
         self.inner.into_inner()
@@ -148,3 +160,185 @@ impl<F: Read + Seek> Read for ChdFileReader<F> {
         }
     }
 }
+
+/// `Read + Seek` adapter that caches aligned blocks of an underlying stream.
+///
+/// Opening a CHD file and reading hunks issues many small reads of the header, map and hunk
+/// data. When the underlying stream has a high latency per read, such as a stream over HTTP
+/// range requests, `CachedReader` reads whole blocks of `block_size` bytes instead and serves
+/// later reads within those blocks from memory. Reads spanning multiple blocks are assembled
+/// from each block in turn. Up to `blocks` blocks are held, evicting the least recently used
+/// block first.
+///
+/// ```no_run
+/// # use chd::{ChdFile, read::CachedReader};
+/// # use std::fs::File;
+/// let source = File::open("image.chd")?;
+/// let chd = ChdFile::open(CachedReader::new(source, 64 * 1024, 64), None)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// The cached blocks and the underlying stream are held behind a lock. Clones of a
+/// `CachedReader` share them, but each clone has its own position, so that a CHD file can be
+/// opened by every thread over the same cache. [`read_at`](CachedReader::read_at) reads
+/// through a shared reference. `CachedReader` is `Send` and `Sync` if the underlying stream
+/// is `Send`.
+pub struct CachedReader<F: Read + Seek> {
+    cache: Arc<Mutex<BlockCache<F>>>,
+    pos: u64,
+}
+
+/// The blocks held by a [`CachedReader`](crate::read::CachedReader) and its clones.
+struct BlockCache<F: Read + Seek> {
+    inner: F,
+    block_size: usize,
+    capacity: usize,
+    // Each block is stored with the time it was last used, and indexed by that time, so that
+    // the least recently used block is the first entry of `lru`.
+    blocks: HashMap<u64, (u64, Vec<u8>)>,
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+impl<F: Read + Seek> CachedReader<F> {
+    /// Creates a new `CachedReader` that holds up to `blocks` blocks of `block_size` bytes
+    /// of the given stream. The reader always holds at least one block of at least one byte.
+    pub fn new(inner: F, block_size: usize, blocks: usize) -> Self {
+        CachedReader {
+            cache: Arc::new(Mutex::new(BlockCache {
+                inner,
+                block_size: std::cmp::max(block_size, 1),
+                capacity: std::cmp::max(blocks, 1),
+                blocks: HashMap::new(),
+                lru: BTreeMap::new(),
+                clock: 0,
+            })),
+            pos: 0,
+        }
+    }
+
+    /// Consumes the reader and returns the underlying stream, or `None` if the stream is
+    /// still shared with a clone of this reader.
+    pub fn into_inner(self) -> Option<F> {
+        let cache = Arc::try_unwrap(self.cache).ok()?;
+        Some(
+            cache
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+                .inner,
+        )
+    }
+
+    /// Reads from the given offset of the stream into `buf` without changing the position of
+    /// this reader, returning the number of bytes read, which is only short at the end of the
+    /// stream.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        // A reader can only panic in the underlying stream, when the blocks are not being
+        // updated, so a poisoned cache is still consistent.
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let block_size = cache.block_size as u64;
+        let mut read = 0;
+        while read < buf.len() {
+            let pos = offset + read as u64;
+            let block_offset = (pos % block_size) as usize;
+            let block = cache.block(pos / block_size)?;
+            if block_offset >= block.len() {
+                break;
+            }
+            let len = std::cmp::min(block.len() - block_offset, buf.len() - read);
+            buf[read..read + len].copy_from_slice(&block[block_offset..block_offset + len]);
+            read += len;
+        }
+        Ok(read)
+    }
+}
+
+impl<F: Read + Seek> Clone for CachedReader<F> {
+    /// Returns a reader that shares the cached blocks of this reader, starting at the same
+    /// position.
+    fn clone(&self) -> Self {
+        CachedReader {
+            cache: Arc::clone(&self.cache),
+            pos: self.pos,
+        }
+    }
+}
+
+impl<F: Read + Seek> BlockCache<F> {
+    /// Returns the data of the block with the given index, reading it from the underlying
+    /// stream if it is not held. The block is shorter than the block size at the end of the
+    /// stream.
+    fn block(&mut self, index: u64) -> std::io::Result<&[u8]> {
+        self.clock += 1;
+        if let Some((last_used, _)) = self.blocks.get_mut(&index) {
+            self.lru.remove(last_used);
+            *last_used = self.clock;
+        } else {
+            let offset = index
+                .checked_mul(self.block_size as u64)
+                .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidInput))?;
+            let oldest = self.lru.keys().next().copied();
+            let mut buf = match oldest {
+                Some(oldest) if self.blocks.len() >= self.capacity => {
+                    let evicted = self
+                        .lru
+                        .remove(&oldest)
+                        .and_then(|i| self.blocks.remove(&i));
+                    evicted.map(|(_, buf)| buf).unwrap_or_default()
+                }
+                _ => Vec::with_capacity(self.block_size),
+            };
+            buf.resize(self.block_size, 0);
+            self.inner.seek(SeekFrom::Start(offset))?;
+            let mut read = 0;
+            while read < buf.len() {
+                match self.inner.read(&mut buf[read..]) {
+                    Ok(0) => break,
+                    Ok(len) => read += len,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            buf.truncate(read);
+            self.blocks.insert(index, (self.clock, buf));
+        }
+        self.lru.insert(self.clock, index);
+        Ok(&self.blocks[&index].1)
+    }
+}
+
+impl<F: Read + Seek> Read for CachedReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at(self.pos, buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<F: Read + Seek> Seek for CachedReader<F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::Current(delta) => (self.pos, delta),
+            SeekFrom::End(delta) => {
+                let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+                (cache.inner.seek(SeekFrom::End(0))?, delta)
+            }
+        };
+        let pos = if delta >= 0 {
+            base.checked_add(delta as u64)
+        } else {
+            base.checked_sub(delta.unsigned_abs())
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
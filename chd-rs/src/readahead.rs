@@ -0,0 +1,102 @@
+use crate::error::Result;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+/// A window of file data read ahead of sequential hunk reads.
+///
+/// When hunks are read in order, the stored data of the following hunks is read together
+/// with the current hunk in a single read, and later reads are served from the window.
+pub(crate) struct ReadAhead {
+    hunks: u32,
+    last_hunk: Option<u32>,
+    start: u64,
+    buffer: Vec<u8>,
+}
+
+impl ReadAhead {
+    /// Creates a new read-ahead window that does not read ahead.
+    pub(crate) fn new() -> Self {
+        ReadAhead {
+            hunks: 0,
+            last_hunk: None,
+            start: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the number of hunks to read ahead.
+    pub(crate) fn hunks(&self) -> u32 {
+        self.hunks
+    }
+
+    /// Sets the number of hunks to read ahead, discarding the window if read-ahead is disabled.
+    pub(crate) fn set_hunks(&mut self, hunks: u32) {
+        self.hunks = hunks;
+        if hunks == 0 {
+            self.buffer = Vec::new();
+        }
+    }
+
+    /// Discards the data in the window.
+    pub(crate) fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Records an access to the given hunk, and returns whether it continues a sequential
+    /// run of accesses starting from the first hunk or the previously accessed hunk.
+    pub(crate) fn record(&mut self, hunk_num: u32) -> bool {
+        let sequential = match self.last_hunk {
+            None => hunk_num == 0,
+            Some(last) => hunk_num == last || Some(hunk_num) == last.checked_add(1),
+        };
+        self.last_hunk = Some(hunk_num);
+        sequential
+    }
+
+    /// Returns the data at the given offset of the file if it lies entirely in the window.
+    pub(crate) fn get(&self, offset: u64, len: usize) -> Option<&[u8]> {
+        let start = usize::try_from(offset.checked_sub(self.start)?).ok()?;
+        self.buffer.get(start..start.checked_add(len)?)
+    }
+
+    /// Replaces the window with up to `len` bytes of the file at the given offset.
+    ///
+    /// If the read fails, the window is left empty.
+    pub(crate) fn fill<F: Read + Seek>(
+        &mut self,
+        file: &mut F,
+        offset: u64,
+        len: usize,
+    ) -> Result<()> {
+        self.buffer.resize(len, 0);
+        self.start = offset;
+        let res = file
+            .seek(SeekFrom::Start(offset))
+            .map_err(Into::into)
+            .and_then(|_| read_fully(file, &mut self.buffer));
+        match res {
+            Ok(read) => {
+                self.buffer.truncate(read);
+                Ok(())
+            }
+            Err(e) => {
+                self.buffer.clear();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Reads from the stream until the buffer is full or the end of the stream is reached,
+/// returning the number of bytes read.
+pub(crate) fn read_fully<F: Read>(file: &mut F, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(len) => read += len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read)
+}
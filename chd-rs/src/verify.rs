@@ -0,0 +1,173 @@
+//! Types and methods relating to verifying the contents of a CHD file.
+use crate::cdrom::{
+    subcode_q_crc_valid, subcode_q_of, CdSubcodeType, CD_MAX_SECTOR_DATA, CD_MAX_SUBCODE_DATA,
+};
+use crate::header::{ChdHeader, SHA1_BYTES};
+use crate::metadata::{ChdMetadata, METADATA_FLAG_CHECKSUM};
+use sha1::{Digest, Sha1};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// The SHA1 hashes of the contents of a CHD file, as computed by
+/// [`ChdFile::verify`](crate::ChdFile::verify).
+///
+/// For CHD V4 and above, these should be equal to
+/// [`ChdHeader::raw_sha1`](crate::header::ChdHeader::raw_sha1) and
+/// [`ChdHeader::sha1`](crate::header::ChdHeader::sha1) respectively.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChdDigests {
+    /// The SHA1 hash of the raw, uncompressed data.
+    pub raw_sha1: [u8; SHA1_BYTES],
+    /// The SHA1 hash of the raw data combined with the hashes of all checksummed
+    /// metadata entries.
+    pub sha1: [u8; SHA1_BYTES],
+}
+
+impl ChdDigests {
+    /// Compares the digests against the hashes recorded in the header of a CHD file.
+    ///
+    /// The SHA1 hash of a V3 CHD file only covers the raw data. Returns `None` for V1-2 CHD
+    /// files, which do not record SHA1 hashes.
+    pub fn compare(&self, header: &ChdHeader) -> Option<VerifyReport> {
+        match (header.raw_sha1(), header.sha1()) {
+            (None, None) => None,
+            (None, Some(sha1)) if sha1 != self.raw_sha1 => Some(VerifyReport::RawDataMismatch),
+            (None, Some(_)) => Some(VerifyReport::Ok),
+            (Some(raw_sha1), _) if raw_sha1 != self.raw_sha1 => Some(VerifyReport::RawDataMismatch),
+            (Some(_), Some(sha1)) if sha1 != self.sha1 => Some(VerifyReport::MetadataMismatch),
+            (Some(_), _) => Some(VerifyReport::Ok),
+        }
+    }
+}
+
+/// The hashes of the data of a track of a CD-ROM or GD-ROM CHD file, as computed by
+/// [`ChdFile::track_hashes`](crate::ChdFile::track_hashes).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TrackHashes {
+    /// The CRC32 hash of the track data.
+    pub crc32: u32,
+    /// The MD5 hash of the track data.
+    pub md5: [u8; 16],
+    /// The SHA1 hash of the track data.
+    pub sha1: [u8; SHA1_BYTES],
+}
+
+/// The result of comparing the hashes of the contents of a CHD file against the hashes
+/// recorded in its header, as computed by [`ChdFile::verify_report`](crate::ChdFile::verify_report).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VerifyReport {
+    /// The hashes of the contents match the header.
+    Ok,
+    /// The SHA1 hash of the raw data does not match the header.
+    RawDataMismatch,
+    /// The SHA1 hash of the raw data matches the header, but the SHA1 hash of the raw data
+    /// combined with the checksummed metadata does not.
+    MetadataMismatch,
+}
+
+/// The result of validating the Q subchannel of every frame of a CD-ROM CHD file, as computed
+/// by [`ChdFile::verify_with_subcode`](crate::ChdFile::verify_with_subcode).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubcodeReport {
+    /// Whether the CHD file contains subcode data. If the subcode data of every frame is zero,
+    /// the CHD file is considered to not contain subcode data, and no frames are reported.
+    pub has_subcode: bool,
+    /// The frames in the CHD file whose Q subchannel CRC does not match, in order.
+    pub bad_frames: Vec<u32>,
+}
+
+/// Validates the Q subchannel of the frames of each hunk in order.
+pub(crate) struct SubcodeChecker {
+    subcode_frames: Vec<(Range<u32>, CdSubcodeType)>,
+    frame_num: u32,
+    frame_bytes: u32,
+    report: SubcodeReport,
+}
+
+impl SubcodeChecker {
+    /// Creates a checker that validates the given frames of `frame_bytes` bytes each, with the
+    /// subcode type of their track. Other frames are skipped, as are frames too short to hold
+    /// subcode data.
+    pub(crate) fn new(subcode_frames: Vec<(Range<u32>, CdSubcodeType)>, frame_bytes: u32) -> Self {
+        SubcodeChecker {
+            subcode_frames,
+            frame_num: 0,
+            frame_bytes,
+            report: SubcodeReport::default(),
+        }
+    }
+
+    /// Validates the frames of the next hunk.
+    pub(crate) fn push(&mut self, data: &[u8]) {
+        for frame in data.chunks_exact(self.frame_bytes as usize) {
+            let frame_num = self.frame_num;
+            self.frame_num += 1;
+            let subcode_type = match self
+                .subcode_frames
+                .iter()
+                .find(|(frames, _)| frames.contains(&frame_num))
+            {
+                Some(&(_, subcode_type)) => subcode_type,
+                None => continue,
+            };
+
+            let subcode = match <&[u8; CD_MAX_SUBCODE_DATA as usize]>::try_from(
+                &frame[CD_MAX_SECTOR_DATA as usize..],
+            ) {
+                Ok(subcode) => subcode,
+                Err(_) => continue,
+            };
+            if subcode.iter().any(|&b| b != 0) {
+                self.report.has_subcode = true;
+            }
+            if !subcode_q_crc_valid(&subcode_q_of(subcode, subcode_type)) {
+                self.report.bad_frames.push(frame_num);
+            }
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> SubcodeReport {
+        if !self.report.has_subcode {
+            self.report.bad_frames.clear();
+        }
+        self.report
+    }
+}
+
+/// Accumulates the hashes of checksummed metadata entries to compute the overall SHA1
+/// hash of a CHD file.
+pub(crate) struct MetadataHasher {
+    // Each entry is the FourCC tag of the metadata entry followed by the SHA1 of its contents.
+    hashes: Vec<[u8; 4 + SHA1_BYTES]>,
+}
+
+impl MetadataHasher {
+    pub(crate) fn new() -> Self {
+        MetadataHasher { hashes: Vec::new() }
+    }
+
+    /// Hashes the contents of the metadata entry if it is flagged to be checksummed.
+    pub(crate) fn push(&mut self, metadata: &ChdMetadata) {
+        if metadata.flags & METADATA_FLAG_CHECKSUM == 0 {
+            return;
+        }
+        let mut entry = [0u8; 4 + SHA1_BYTES];
+        entry[..4].copy_from_slice(&metadata.metatag.to_be_bytes());
+        entry[4..].copy_from_slice(&Sha1::digest(&metadata.value));
+        self.hashes.push(entry);
+    }
+
+    /// Computes the overall SHA1 hash from the SHA1 hash of the raw data.
+    ///
+    /// MAME sorts the metadata hashes by comparing the bytes of the tag and SHA1 of each entry,
+    /// so that the overall hash does not depend on the order of the metadata entries in the file.
+    pub(crate) fn finalize(mut self, raw_sha1: &[u8; SHA1_BYTES]) -> [u8; SHA1_BYTES] {
+        self.hashes.sort_unstable();
+        let mut overall = Sha1::new();
+        overall.update(raw_sha1);
+        for entry in &self.hashes {
+            overall.update(entry);
+        }
+        overall.finalize().into()
+    }
+}